@@ -0,0 +1,163 @@
+//! Core cache operations across cache sizes
+//!
+//! Run with `cargo bench`. To guard a change against regressions, record a
+//! baseline first and compare against it afterwards:
+//!
+//! ```bash
+//! cargo bench -- --save-baseline before
+//! cargo bench -- --baseline before
+//! ```
+
+use std::hint::black_box;
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use memory_cache::{save_cache_to, Cache};
+
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+const TTL: Duration = Duration::from_secs(3600);
+
+// A fixed clock keeps system time lookups out of the measurements
+fn frozen_clock() -> u64 {
+    1_000_000
+}
+
+fn keys(size: usize) -> Vec<String> {
+    (0..size).map(|index| format!("key{}", index)).collect()
+}
+
+fn filled(keys: &[String]) -> Cache<String> {
+    let mut cache = Cache::new().with_clock(frozen_clock);
+    for key in keys {
+        cache.insert(key, "session_value".to_string(), TTL);
+    }
+    cache
+}
+
+fn insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert");
+    for size in SIZES {
+        let keys = keys(size);
+        let mut cache = filled(&keys);
+        let mut next = keys.iter().cycle();
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| cache.insert(next.next().unwrap(), "new_value".to_string(), TTL))
+        });
+    }
+    group.finish();
+}
+
+fn get_hit(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_hit");
+    for size in SIZES {
+        let keys = keys(size);
+        let mut cache = filled(&keys);
+        let mut next = keys.iter().cycle();
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| black_box(cache.get(next.next().unwrap())))
+        });
+    }
+    group.finish();
+}
+
+fn get_miss(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_miss");
+    for size in SIZES {
+        let mut cache = filled(&keys(size));
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| black_box(cache.get("missing")))
+        });
+    }
+    group.finish();
+}
+
+// Expired entries are evicted lazily by get, so this times an insert that
+// expires immediately followed by the get that removes it
+fn get_expired(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_expired");
+    for size in SIZES {
+        let mut cache = filled(&keys(size));
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| {
+                cache.insert("expired", "stale_value".to_string(), Duration::ZERO);
+                black_box(cache.get("expired"))
+            })
+        });
+    }
+    group.finish();
+}
+
+// The miss-then-fill pattern against entries that are always expired
+fn fill(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fill");
+    let keys = keys(SIZES[1]);
+    let mut cache = Cache::new().with_clock(frozen_clock);
+    for key in &keys {
+        cache.insert(key, "stale_value".to_string(), Duration::ZERO);
+    }
+    let mut next = keys.iter().cycle();
+    group.bench_function("get_then_insert", |b| {
+        b.iter(|| {
+            let key = next.next().unwrap();
+            if cache.get(key).is_none() {
+                cache.insert(key, "stale_value".to_string(), Duration::ZERO);
+            }
+        })
+    });
+    group.bench_function("get_or_insert_with", |b| {
+        b.iter(|| {
+            black_box(
+                cache.get_or_insert_with(next.next().unwrap(), Duration::ZERO, || {
+                    "stale_value".to_string()
+                }),
+            )
+        })
+    });
+    group.finish();
+}
+
+fn clock(c: &mut Criterion) {
+    let mut group = c.benchmark_group("clock");
+    let keys = keys(SIZES[0]);
+    let mut system = Cache::new();
+    let mut coarse = Cache::new().with_coarse_clock();
+    for key in &keys {
+        system.insert(key, "session_value".to_string(), TTL);
+        coarse.insert(key, "session_value".to_string(), TTL);
+    }
+    let mut next = keys.iter().cycle();
+    group.bench_function("system", |b| {
+        b.iter(|| black_box(system.get(next.next().unwrap())))
+    });
+    group.bench_function("coarse", |b| {
+        b.iter(|| black_box(coarse.get(next.next().unwrap())))
+    });
+    group.finish();
+}
+
+fn save(c: &mut Criterion) {
+    let mut group = c.benchmark_group("save");
+    group.sample_size(10);
+    let path = std::env::temp_dir().join("memory_cache_bench_state.json");
+    for size in SIZES {
+        let cache = filled(&keys(size));
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| save_cache_to(&cache, &path).unwrap())
+        });
+    }
+    group.finish();
+    let _ = std::fs::remove_file(path);
+}
+
+criterion_group!(
+    benches,
+    insert,
+    get_hit,
+    get_miss,
+    get_expired,
+    fill,
+    clock,
+    save
+);
+criterion_main!(benches);
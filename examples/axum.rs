@@ -0,0 +1,49 @@
+//! A small router that caches slow lookups
+//!
+//! ```bash
+//! cargo run --example axum --features axum
+//! curl localhost:3000/users/42
+//! curl -X DELETE localhost:3000/users/42
+//! ```
+
+use std::time::Duration;
+
+use axum::extract::{Path, State};
+use axum::routing::get;
+use axum::Router;
+use memory_cache::axum::Cached;
+use memory_cache::SharedCache;
+
+async fn get_user(
+    State(cache): State<SharedCache<String>>,
+    Path(id): Path<u64>,
+    cached: Cached<String>,
+) -> String {
+    if let Some(user) = cached.value {
+        return user;
+    }
+
+    // Stand-in for a slow database or upstream call
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let user = format!("user {}", id);
+
+    cache.insert(cached.key(), user.clone(), Duration::from_secs(30));
+    user
+}
+
+async fn forget_user(State(cache): State<SharedCache<String>>, Path(id): Path<u64>) {
+    cache.invalidate(&format!("/users/{}", id));
+}
+
+#[tokio::main]
+async fn main() {
+    let app = Router::new()
+        .route("/users/{id}", get(get_user).delete(forget_user))
+        .with_state(SharedCache::new());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+        .await
+        .unwrap();
+    println!("Listening on http://127.0.0.1:3000");
+    axum::serve(listener, app).await.unwrap();
+}
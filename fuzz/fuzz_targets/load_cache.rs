@@ -0,0 +1,20 @@
+//! Feeds arbitrary bytes to the state file parser
+//!
+//! Loading must either fail cleanly or produce a usable cache; it must never
+//! panic, hang or exhaust memory.
+
+#![no_main]
+
+use std::time::Duration;
+
+use libfuzzer_sys::fuzz_target;
+use memory_cache::load_cache_from_slice;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(mut cache) = load_cache_from_slice(data) {
+        cache.check_invariants().unwrap();
+        cache.get("user_xyz");
+        cache.insert("user_xyz", "session_value".to_string(), Duration::MAX);
+        assert_eq!(cache.get("user_xyz").as_deref(), Some("session_value"));
+    }
+});
@@ -0,0 +1,76 @@
+#ifndef MEMORY_CACHE_H
+#define MEMORY_CACHE_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * An opaque cache of string values
+ */
+typedef struct MemoryCache MemoryCache;
+
+/**
+ * Creates a new empty cache; release it with `cache_free`
+ */
+struct MemoryCache *cache_new(void);
+
+/**
+ * Destroys a cache created by `cache_new`
+ *
+ * # Safety
+ *
+ * `cache` must be NULL or a pointer returned by `cache_new` that has not
+ * been freed yet.
+ */
+void cache_free(struct MemoryCache *cache);
+
+/**
+ * Inserts a copy of `value` under `key` for `ttl_secs` seconds
+ *
+ * Returns false, without inserting, if any pointer is NULL or a string is
+ * not valid UTF-8.
+ *
+ * # Safety
+ *
+ * `cache` must be a live pointer from `cache_new`; `key` and `value` must be
+ * NULL or point to NUL-terminated strings.
+ */
+bool cache_insert(struct MemoryCache *cache, const char *key, const char *value, uint64_t ttl_secs);
+
+/**
+ * Returns a copy of the live value under `key`, or NULL if there is none
+ *
+ * # Safety
+ *
+ * `cache` must be a live pointer from `cache_new` and `key` must be NULL or
+ * point to a NUL-terminated string. A non-NULL result must be released with
+ * `cache_string_free`.
+ */
+char *cache_get(struct MemoryCache *cache, const char *key);
+
+/**
+ * Removes the entry under `key`, if any
+ *
+ * # Safety
+ *
+ * `cache` must be a live pointer from `cache_new` and `key` must be NULL or
+ * point to a NUL-terminated string.
+ */
+void cache_invalidate(struct MemoryCache *cache, const char *key);
+
+/**
+ * Releases a string returned by `cache_get`
+ *
+ * # Safety
+ *
+ * `value` must be NULL or a pointer returned by `cache_get` that has not
+ * been freed yet.
+ */
+void cache_string_free(char *value);
+
+#endif  /* MEMORY_CACHE_H */
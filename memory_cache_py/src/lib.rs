@@ -0,0 +1,102 @@
+//! Python bindings for `memory_cache`
+//!
+//! ```python
+//! from memory_cache_py import Cache
+//!
+//! cache = Cache.load()  # shares cache_state.json with the CLI
+//! cache.insert("user_xyz", "session_value", ttl=30)
+//! cache["api_key"] = "secret123"  # uses the default TTL
+//! print(cache.get("user_xyz"))
+//! del cache["api_key"]
+//! cache.save()
+//! ```
+
+use std::time::Duration;
+
+use pyo3::exceptions::{PyIOError, PyKeyError};
+use pyo3::prelude::*;
+
+const CACHE_FILE: &str = "cache_state.json";
+
+/// A string cache with per-entry TTLs in seconds
+///
+/// Entries set with item assignment use the cache's default TTL.
+#[pyclass(name = "Cache")]
+struct PyCache {
+    cache: memory_cache::Cache<String>,
+    default_ttl: u64,
+}
+
+#[pymethods]
+impl PyCache {
+    #[new]
+    #[pyo3(signature = (default_ttl = 30))]
+    fn new(default_ttl: u64) -> Self {
+        PyCache {
+            cache: memory_cache::Cache::new(),
+            default_ttl,
+        }
+    }
+
+    /// Loads a state file in the CLI's format, creating it if missing
+    #[staticmethod]
+    #[pyo3(signature = (path = CACHE_FILE, default_ttl = 30))]
+    fn load(path: &str, default_ttl: u64) -> PyResult<Self> {
+        let cache = memory_cache::load_cache_from(path)
+            .map_err(|err| PyIOError::new_err(err.to_string()))?;
+        Ok(PyCache { cache, default_ttl })
+    }
+
+    /// Writes the cache to a state file in the CLI's format
+    #[pyo3(signature = (path = CACHE_FILE))]
+    fn save(&self, path: &str) -> PyResult<()> {
+        memory_cache::save_cache_to(&self.cache, path)
+            .map_err(|err| PyIOError::new_err(err.to_string()))
+    }
+
+    /// Inserts a value, expiring after `ttl` seconds or the default TTL
+    #[pyo3(signature = (key, value, ttl = None))]
+    fn insert(&mut self, key: &str, value: String, ttl: Option<u64>) {
+        let ttl = ttl.unwrap_or(self.default_ttl);
+        self.cache.insert(key, value, Duration::from_secs(ttl));
+    }
+
+    /// Returns the live value for `key`, or `default` if expired or not found
+    #[pyo3(signature = (key, default = None))]
+    fn get(&mut self, key: &str, default: Option<String>) -> Option<String> {
+        self.cache.get(key).or(default)
+    }
+
+    /// Removes an entry if present
+    fn invalidate(&mut self, key: &str) {
+        self.cache.invalidate(key);
+    }
+
+    fn __getitem__(&mut self, key: &str) -> PyResult<String> {
+        self.cache
+            .get(key)
+            .ok_or_else(|| PyKeyError::new_err(key.to_string()))
+    }
+
+    fn __setitem__(&mut self, key: &str, value: String) {
+        self.insert(key, value, None);
+    }
+
+    fn __delitem__(&mut self, key: &str) -> PyResult<()> {
+        if self.cache.get(key).is_none() {
+            return Err(PyKeyError::new_err(key.to_string()));
+        }
+        self.cache.invalidate(key);
+        Ok(())
+    }
+
+    fn __contains__(&mut self, key: &str) -> bool {
+        self.cache.get(key).is_some()
+    }
+}
+
+#[pymodule]
+fn memory_cache_py(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyCache>()?;
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use core::hash::{BuildHasher, Hash};
+use core::time::Duration;
+
+use crate::{Cache, DefaultHashBuilder};
+
+// Per-key TTLs learned by Cache::with_adaptive_ttl
+#[derive(Clone)]
+pub(crate) struct AdaptiveTtl<T> {
+    min: u64,
+    max: u64,
+    hasher: DefaultHashBuilder,
+    fingerprint: fn(&DefaultHashBuilder, &T) -> u64,
+    // Each key's TTL in seconds with the fingerprint of its last value
+    keys: BTreeMap<String, (u64, u64)>,
+}
+
+impl<T> AdaptiveTtl<T> {
+    // The TTL for `value` written under `key`: doubled if the value is
+    // unchanged since the last write, halved if it changed; `ttl` is where
+    // a key starts
+    fn adapt(&mut self, key: &str, value: &T, ttl: u64) -> u64 {
+        let fingerprint = (self.fingerprint)(&self.hasher, value);
+        let ttl = match self.keys.get(key) {
+            None => ttl.clamp(self.min, self.max),
+            Some(&(current, last)) if last == fingerprint => {
+                current.max(1).saturating_mul(2).min(self.max)
+            }
+            Some(&(current, _)) => (current / 2).max(self.min),
+        };
+        self.keys.insert(key.to_string(), (ttl, fingerprint));
+        ttl
+    }
+
+    // Called when `key` is invalidated, so a later write starts over
+    pub(crate) fn forget(&mut self, key: &str) {
+        self.keys.remove(key);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.keys.clear();
+    }
+}
+
+// The TTL in seconds to write `value` under `key` with, learned if the
+// cache adapts TTLs and `ttl` otherwise
+pub(crate) fn effective_ttl<T>(
+    adaptive: &mut Option<AdaptiveTtl<T>>,
+    key: &str,
+    value: &T,
+    ttl: Duration,
+) -> u64 {
+    match adaptive {
+        Some(adaptive) => adaptive.adapt(key, value, ttl.as_secs()),
+        None => ttl.as_secs(),
+    }
+}
+
+impl<T: Clone, S: BuildHasher> Cache<T, S> {
+    /// Learns a TTL per key from how often its value actually changes, within `min` and `max`
+    ///
+    /// Each write compares the new value with the key's previous one: an
+    /// unchanged value doubles the key's TTL and a changed one halves it, so
+    /// stable keys are refreshed less often and volatile ones go stale less.
+    /// The TTL passed to the first write of a key is only its starting
+    /// point. This applies to [`Cache::insert`], [`Cache::try_insert`] and
+    /// loads by [`Cache::get_or_insert_with`]; invalidating a key starts it
+    /// over. Learned TTLs are not persisted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min` is longer than `max`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new().with_adaptive_ttl(Duration::from_secs(10), Duration::from_secs(3600));
+    ///
+    /// cache.insert("config", "v1", Duration::from_secs(60));
+    /// cache.insert("config", "v1", Duration::from_secs(60));
+    /// assert_eq!(cache.adaptive_ttl("config"), Some(Duration::from_secs(120)));
+    ///
+    /// cache.insert("config", "v2", Duration::from_secs(60));
+    /// assert_eq!(cache.ttl("config"), Some(Duration::from_secs(60)));
+    /// ```
+    pub fn with_adaptive_ttl(mut self, min: Duration, max: Duration) -> Self
+    where
+        T: Hash,
+    {
+        assert!(
+            min <= max,
+            "adaptive TTL bounds are reversed: {:?} > {:?}",
+            min,
+            max
+        );
+        self.adaptive = Some(AdaptiveTtl {
+            min: min.as_secs(),
+            max: max.as_secs(),
+            hasher: DefaultHashBuilder::default(),
+            fingerprint: |hasher, value| hasher.hash_one(value),
+            keys: BTreeMap::new(),
+        });
+        self
+    }
+
+    /// Returns the TTL the last write of `key` was given by [`Cache::with_adaptive_ttl`]
+    pub fn adaptive_ttl(&self, key: &str) -> Option<Duration> {
+        let adaptive = self.adaptive.as_ref()?;
+        adaptive
+            .keys
+            .get(key)
+            .map(|&(ttl, _)| Duration::from_secs(ttl))
+    }
+}
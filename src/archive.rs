@@ -0,0 +1,248 @@
+use std::fmt;
+use std::fs;
+use std::io::{Read, Write};
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::blob::is_blob_reference;
+use crate::clock::system_now;
+use crate::{load_cache_from_slice, Cache, MAX_STATE_SIZE};
+
+// "MCACHE", a NUL and the format version
+const MAGIC: &[u8; 7] = b"MCACHE\0";
+const VERSION: u8 = 1;
+
+// Headers are a few hundred bytes; anything much larger is not an archive
+const MAX_HEADER_SIZE: u32 = 64 * 1024;
+
+/// How the payload of an archive is compressed
+///
+/// Only uncompressed payloads are written so far; the field exists so that
+/// readers reject codecs they do not know instead of misreading them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Compression {
+    None,
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Compression::None => "none",
+        })
+    }
+}
+
+/// Metadata at the start of a `.mcache` archive
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ArchiveHeader {
+    /// Seconds since the Unix epoch
+    pub created_at: u64,
+    /// Host the archive was packed on
+    pub host: String,
+    /// Number of entries in the packed cache, expired ones included
+    pub entries: usize,
+    pub compression: Compression,
+    /// Length of the payload in bytes, as stored
+    pub payload_len: u64,
+    /// Hex SHA-256 of the payload, as stored
+    pub payload_sha256: String,
+}
+
+/// Writes `cache` to `writer` as a `.mcache` archive
+///
+/// An archive is a magic number and format version, a length-prefixed JSON
+/// [`ArchiveHeader`] and the cache in the state file format as payload, so
+/// pre-warmed caches can be shipped as build artifacts. Values kept in blob
+/// files are not included, so this fails if a live entry references one
+/// rather than packing a reference nothing could resolve once unpacked.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::{pack_cache, unpack_cache, Cache};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let mut cache = Cache::new();
+/// cache.insert("config", "v42".to_string(), Duration::from_secs(3600));
+///
+/// let mut archive = Vec::new();
+/// pack_cache(&cache, &mut archive)?;
+///
+/// let (header, mut unpacked) = unpack_cache(&archive[..])?;
+/// assert_eq!(header.entries, 1);
+/// assert_eq!(unpacked.get("config").as_deref(), Some("v42"));
+///
+/// // Any corruption is caught by the checksum
+/// let last = archive.len() - 2;
+/// archive[last] ^= 1;
+/// assert!(unpack_cache(&archive[..]).is_err());
+/// # Ok(())
+/// # }
+/// ```
+pub fn pack_cache(cache: &Cache<String>, mut writer: impl Write) -> Result<ArchiveHeader> {
+    let blobs = cache.entries.keys().find(|key| {
+        cache
+            .live_entry(key)
+            .is_some_and(|entry| is_blob_reference(&entry.value))
+    });
+    if let Some(key) = blobs {
+        bail!(
+            "entry '{}' is kept in a blob file, which archives cannot hold",
+            key
+        );
+    }
+    let payload = serde_json::to_vec(cache)?;
+    let header = ArchiveHeader {
+        created_at: system_now(),
+        host: host_name(),
+        entries: cache.entries.len(),
+        compression: Compression::None,
+        payload_len: payload.len() as u64,
+        payload_sha256: sha256_hex(&payload),
+    };
+    let encoded = serde_json::to_vec(&header)?;
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION])?;
+    writer.write_all(&(encoded.len() as u32).to_le_bytes())?;
+    writer.write_all(&encoded)?;
+    writer.write_all(&payload)?;
+    writer.flush()?;
+    Ok(header)
+}
+
+/// Reads a cache from a `.mcache` archive written by [`pack_cache`]
+///
+/// Fails on an unknown format version or compression, a payload over
+/// [`MAX_STATE_SIZE`], or a payload that does not match its checksum.
+pub fn unpack_cache(mut reader: impl Read) -> Result<(ArchiveHeader, Cache<String>)> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic[..7] != MAGIC {
+        bail!("not a .mcache archive");
+    }
+    if magic[7] != VERSION {
+        bail!("unsupported .mcache version {}", magic[7]);
+    }
+
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len);
+    if len > MAX_HEADER_SIZE {
+        bail!("archive header is larger than {} bytes", MAX_HEADER_SIZE);
+    }
+    let mut encoded = vec![0u8; len as usize];
+    reader.read_exact(&mut encoded)?;
+    let header: ArchiveHeader = serde_json::from_slice(&encoded)?;
+
+    if header.payload_len > MAX_STATE_SIZE {
+        bail!("archive payload is larger than {} bytes", MAX_STATE_SIZE);
+    }
+    let mut payload = Vec::new();
+    reader.take(header.payload_len).read_to_end(&mut payload)?;
+    if payload.len() as u64 != header.payload_len {
+        bail!(
+            "archive is truncated: expected {} payload bytes, found {}",
+            header.payload_len,
+            payload.len()
+        );
+    }
+    if sha256_hex(&payload) != header.payload_sha256 {
+        bail!("archive payload does not match its checksum");
+    }
+    let cache = match header.compression {
+        Compression::None => load_cache_from_slice(&payload)?,
+    };
+    Ok((header, cache))
+}
+
+/// Replaces the entries of `cache` with those of a `.mcache` archive, keeping its configuration
+///
+/// Unlike [`unpack_cache`], which returns a new cache, this keeps the
+/// stats, tuning, size limit, indexes and event log already set up on
+/// `cache`. The live entries of the archive are inserted with their
+/// remaining TTL; if `cache` would reject any of them, e.g. for being over
+/// its size limit, this fails before changing anything. Returns the
+/// archive's header and how many entries were inserted, which leaves out
+/// the expired ones [`ArchiveHeader::entries`] counts.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::{pack_cache, unpack_cache_into, Cache};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let mut packed = Cache::new();
+/// packed.insert("config", "v2".to_string(), Duration::from_secs(60));
+/// packed.insert("expired", "v0".to_string(), Duration::ZERO);
+/// let mut archive = Vec::new();
+/// pack_cache(&packed, &mut archive)?;
+///
+/// let mut cache = Cache::new().with_stats().with_max_value_size(16);
+/// cache.insert("stale", "v1".to_string(), Duration::from_secs(60));
+/// let (header, inserted) = unpack_cache_into(&mut cache, &archive[..])?;
+/// assert_eq!((header.entries, inserted), (2, 1));
+/// assert_eq!(cache.get("config"), Some("v2".to_string()));
+/// assert_eq!(cache.get("stale"), None);
+/// assert_eq!(cache.stats().inserts, 2);
+///
+/// packed.insert("config", "x".repeat(32), Duration::from_secs(60));
+/// let mut archive = Vec::new();
+/// pack_cache(&packed, &mut archive)?;
+/// assert!(unpack_cache_into(&mut cache, &archive[..]).is_err());
+/// assert_eq!(cache.get("config"), Some("v2".to_string()));
+/// # Ok(())
+/// # }
+/// ```
+pub fn unpack_cache_into(
+    cache: &mut Cache<String>,
+    reader: impl Read,
+) -> Result<(ArchiveHeader, usize)> {
+    let (header, mut unpacked) = unpack_cache(reader)?;
+    let keys: Vec<String> = unpacked
+        .entries
+        .keys()
+        .filter(|key| unpacked.contains_key(key))
+        .cloned()
+        .collect();
+    let mut entries = Vec::with_capacity(keys.len());
+    for key in keys {
+        let ttl = unpacked.ttl(&key).unwrap_or_default();
+        let Some(value) = unpacked.take(&key) else {
+            continue;
+        };
+        cache
+            .admission
+            .check(&key, &value, ttl)
+            .map_err(|err| anyhow!("entry '{}' of the archive: {}", key, err))?;
+        entries.push((key, value, ttl));
+    }
+    cache.clear();
+    let mut inserted = 0;
+    for (key, value, ttl) in entries {
+        cache.insert(&key, value, ttl);
+        inserted += usize::from(cache.contains_key(&key));
+    }
+    Ok((header, inserted))
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    let digest = Sha256::digest(bytes);
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn host_name() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
@@ -0,0 +1,191 @@
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::clock::system_now;
+
+const AUDIT_FILE: &str = "cache_audit.jsonl";
+
+/// The kind of access an [`AuditRecord`] describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum AuditOp {
+    Insert,
+    Get,
+    Invalidate,
+    List,
+    RatelimitCheck,
+    Lpush,
+    Rpop,
+    Sadd,
+    Srem,
+    Smembers,
+    Hset,
+    Hget,
+    Hdel,
+    Pack,
+    Unpack,
+    Import,
+    Export,
+    Edit,
+    Clear,
+    Prune,
+    Undo,
+    Stats,
+    Doctor,
+    Persist,
+    Sample,
+    Analyze,
+    Warm,
+}
+
+impl fmt::Display for AuditOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AuditOp::Insert => "insert",
+            AuditOp::Get => "get",
+            AuditOp::Invalidate => "invalidate",
+            AuditOp::List => "list",
+            AuditOp::RatelimitCheck => "ratelimit check",
+            AuditOp::Lpush => "lpush",
+            AuditOp::Rpop => "rpop",
+            AuditOp::Sadd => "sadd",
+            AuditOp::Srem => "srem",
+            AuditOp::Smembers => "smembers",
+            AuditOp::Hset => "hset",
+            AuditOp::Hget => "hget",
+            AuditOp::Hdel => "hdel",
+            AuditOp::Pack => "pack",
+            AuditOp::Unpack => "unpack",
+            AuditOp::Import => "import",
+            AuditOp::Export => "export",
+            AuditOp::Edit => "edit",
+            AuditOp::Clear => "clear",
+            AuditOp::Prune => "prune",
+            AuditOp::Undo => "undo",
+            AuditOp::Stats => "stats",
+            AuditOp::Doctor => "doctor",
+            AuditOp::Persist => "persist",
+            AuditOp::Sample => "sample",
+            AuditOp::Analyze => "analyze",
+            AuditOp::Warm => "warm",
+        })
+    }
+}
+
+/// One line of the audit log: who did what to which key, and when
+///
+/// Values are never recorded, only optionally their SHA-256 so that two
+/// records can be compared without revealing what was stored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct AuditRecord {
+    /// Seconds since the Unix epoch
+    pub at: u64,
+    pub actor: String,
+    pub op: AuditOp,
+    pub key: String,
+    /// TTL in seconds, for inserts, pushes, adds, field sets and edits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u64>,
+    /// Hex SHA-256 of the inserted value, if value hashing is on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_sha256: Option<String>,
+    /// Whether a get, pop, removal or member listing found anything
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hit: Option<bool>,
+}
+
+impl AuditRecord {
+    /// Creates a record of `actor` performing `op` on `key` now
+    pub fn new(actor: &str, op: AuditOp, key: &str) -> Self {
+        AuditRecord {
+            at: system_now(),
+            actor: actor.to_string(),
+            op,
+            key: key.to_string(),
+            ttl: None,
+            value_sha256: None,
+            hit: None,
+        }
+    }
+
+    /// Records the TTL of an insert
+    pub fn with_ttl(mut self, ttl: u64) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Records the SHA-256 of an inserted value
+    pub fn with_value_hash(mut self, value: &str) -> Self {
+        let digest = Sha256::digest(value.as_bytes());
+        self.value_sha256 = Some(digest.iter().map(|byte| format!("{:02x}", byte)).collect());
+        self
+    }
+
+    /// Records whether a get, pop, removal or member listing found anything
+    pub fn with_hit(mut self, hit: bool) -> Self {
+        self.hit = Some(hit);
+        self
+    }
+}
+
+impl fmt::Display for AuditRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {} '{}'", self.at, self.actor, self.op, self.key)?;
+        if let Some(ttl) = self.ttl {
+            write!(f, " ttl={}s", ttl)?;
+        }
+        if let Some(hash) = &self.value_sha256 {
+            write!(f, " sha256={}", hash)?;
+        }
+        if let Some(hit) = self.hit {
+            f.write_str(if hit { " hit" } else { " miss" })?;
+        }
+        Ok(())
+    }
+}
+
+/// Appends a record to the audit log next to the CLI's state file
+pub fn append_audit(record: &AuditRecord) -> Result<()> {
+    append_audit_to(record, AUDIT_FILE)
+}
+
+/// Appends a record to the audit log at `path`, creating it if missing
+///
+/// Each record is written as one JSON line with a single append, so
+/// concurrent writers do not interleave their records.
+pub fn append_audit_to(record: &AuditRecord, path: impl AsRef<Path>) -> Result<()> {
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Returns the last `count` records of the audit log next to the CLI's state file
+pub fn tail_audit(count: usize) -> Result<Vec<AuditRecord>> {
+    tail_audit_from(AUDIT_FILE, count)
+}
+
+/// Returns the last `count` records of the audit log at `path`, oldest first
+///
+/// A missing log has no records.
+pub fn tail_audit_from(path: impl AsRef<Path>, count: usize) -> Result<Vec<AuditRecord>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let lines: Vec<&str> = contents.lines().collect();
+    lines[lines.len().saturating_sub(count)..]
+        .iter()
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
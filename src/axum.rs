@@ -0,0 +1,73 @@
+//! Integration with the [`axum`] web framework
+//!
+//! [`SharedCache`] can be used directly as router state. The [`Cached`]
+//! extractor looks up the value cached for the current request path, so
+//! handlers only need to fill the cache on a miss.
+//!
+//! See `examples/axum.rs` for a complete router.
+
+use std::convert::Infallible;
+
+use axum::extract::FromRef;
+use axum::http::request::Parts;
+
+use crate::SharedCache;
+
+/// Extracts the value cached under the request's path and query
+///
+/// The state must provide a [`SharedCache<T>`], either directly or through
+/// [`FromRef`].
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use axum::{extract::State, routing::get, Router};
+/// use memory_cache::{axum::Cached, SharedCache};
+///
+/// async fn report(State(cache): State<SharedCache<String>>, cached: Cached<String>) -> String {
+///     if let Some(report) = cached.value {
+///         return report;
+///     }
+///     let report = String::from("expensive report");
+///     cache.insert(cached.key(), report.clone(), Duration::from_secs(30));
+///     report
+/// }
+///
+/// let app: Router = Router::new()
+///     .route("/report", get(report))
+///     .with_state(SharedCache::new());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Cached<T> {
+    key: String,
+    /// The cached value, or `None` on a miss
+    pub value: Option<T>,
+}
+
+impl<T> Cached<T> {
+    /// The cache key used for this request
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+}
+
+impl<S, T> axum::extract::FromRequestParts<S> for Cached<T>
+where
+    SharedCache<T>: FromRef<S>,
+    S: Send + Sync,
+    T: Clone,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let key = parts
+            .uri
+            .path_and_query()
+            .map(|path| path.as_str())
+            .unwrap_or_else(|| parts.uri.path())
+            .to_string();
+        let value = SharedCache::<T>::from_ref(state).get(&key);
+        Ok(Cached { key, value })
+    }
+}
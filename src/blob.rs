@@ -0,0 +1,206 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::Cache;
+
+const BLOB_DIR: &str = "cache_blobs";
+
+// Values that reference a blob file; the NUL keeps ordinary text from looking like one
+const BLOB_MARKER: &str = "\0memory_cache-blob:";
+
+// Whether `value` is a reference to a blob file rather than the value itself
+pub(crate) fn is_blob_reference(value: &str) -> bool {
+    value.starts_with(BLOB_MARKER)
+}
+
+/// Largest value, in bytes, that a [`BlobStore`] keeps inline by default
+pub const DEFAULT_BLOB_THRESHOLD: usize = 1024 * 1024;
+
+/// Streams large values to and from files kept beside the state file
+///
+/// Values up to the threshold are stored in the cache as usual. Larger ones,
+/// and any that are not UTF-8, are written to a file in the blob directory
+/// and the cache only holds a reference to it, so neither the value nor the
+/// JSON state ever has to hold the whole payload. Size limits and insert
+/// hooks see that reference rather than the contents.
+///
+/// Blob files outlive entries that expire, are invalidated or are
+/// overwritten until [`BlobStore::prune`] removes them.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::{BlobStore, Cache};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let blobs = BlobStore::new(std::env::temp_dir().join("memory_cache-blobs")).with_threshold(8);
+/// let mut cache = Cache::new();
+///
+/// // Any `Read` works, e.g. a `File` holding a build artifact
+/// let artifact = &b"a payload over the threshold"[..];
+/// blobs.insert_reader(&mut cache, "artifact", artifact, Duration::from_secs(3600))?;
+///
+/// let mut out = Vec::new();
+/// blobs.get_writer(&mut cache, "artifact", &mut out)?;
+/// assert_eq!(out, artifact);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct BlobStore {
+    dir: PathBuf,
+    threshold: usize,
+}
+
+impl Default for BlobStore {
+    fn default() -> Self {
+        BlobStore::new(BLOB_DIR)
+    }
+}
+
+impl BlobStore {
+    /// Creates a store keeping blob files in `dir`, which is created on first use
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        BlobStore {
+            dir: dir.into(),
+            threshold: DEFAULT_BLOB_THRESHOLD,
+        }
+    }
+
+    /// Sets the largest value, in bytes, that is stored inline
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Inserts everything `reader` yields under `key`, returning the number of bytes stored
+    ///
+    /// At most the threshold is buffered in memory; anything larger is
+    /// streamed into a blob file.
+    pub fn insert_reader(
+        &self,
+        cache: &mut Cache<String>,
+        key: &str,
+        mut reader: impl Read,
+        ttl: Duration,
+    ) -> Result<u64> {
+        let mut head = Vec::new();
+        (&mut reader)
+            .take(self.threshold as u64 + 1)
+            .read_to_end(&mut head)?;
+        let fits = head.len() <= self.threshold;
+        let head = match String::from_utf8(head) {
+            Ok(value) if fits && !is_blob_reference(&value) => {
+                let len = value.len() as u64;
+                cache.try_insert(key, value, ttl)?;
+                return Ok(len);
+            }
+            Ok(value) => value.into_bytes(),
+            Err(err) => err.into_bytes(),
+        };
+
+        fs::create_dir_all(&self.dir)?;
+        let name = new_blob_name()?;
+        let path = self.dir.join(&name);
+        let partial = self.dir.join(format!("{}.partial", name));
+        let written = (|| -> Result<u64> {
+            let mut file = File::create(&partial)?;
+            file.write_all(&head)?;
+            let rest = io::copy(&mut reader, &mut file)?;
+            file.sync_all()?;
+            fs::rename(&partial, &path)?;
+            Ok(head.len() as u64 + rest)
+        })();
+        let result = written.and_then(|written| {
+            cache.try_insert(key, format!("{}{}", BLOB_MARKER, name), ttl)?;
+            Ok(written)
+        });
+        if result.is_err() {
+            let _ = fs::remove_file(&partial);
+            let _ = fs::remove_file(&path);
+        }
+        result
+    }
+
+    /// Writes the live value for `key` to `writer`, returning the number of bytes written
+    ///
+    /// Returns `None` if there is no live value. Inline values are written
+    /// as-is, so this works for every entry of a string cache.
+    pub fn get_writer(
+        &self,
+        cache: &mut Cache<String>,
+        key: &str,
+        writer: &mut impl Write,
+    ) -> Result<Option<u64>> {
+        let Some(value) = cache.get(key) else {
+            return Ok(None);
+        };
+        self.write_value(key, &value, writer).map(Some)
+    }
+
+    /// Writes a value stored under `key` to `writer`, from its blob file if it refers to one
+    ///
+    /// For values read without a cache, such as from a
+    /// `MappedState`; returns the number of bytes written.
+    pub fn write_value(&self, key: &str, value: &str, writer: &mut impl Write) -> Result<u64> {
+        match value.strip_prefix(BLOB_MARKER) {
+            Some(name) => {
+                let path = self.blob_path(name)?;
+                let mut file = File::open(&path).map_err(|err| {
+                    anyhow!("blob for key '{}' at {}: {}", key, path.display(), err)
+                })?;
+                Ok(io::copy(&mut file, writer)?)
+            }
+            None => {
+                writer.write_all(value.as_bytes())?;
+                Ok(value.len() as u64)
+            }
+        }
+    }
+
+    /// Removes blob files that no entry of `cache` references, returning how many were removed
+    pub fn prune(&self, cache: &Cache<String>) -> Result<usize> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+        let referenced: HashSet<&str> = cache
+            .entries
+            .values()
+            .filter_map(|entry| entry.value.strip_prefix(BLOB_MARKER))
+            .collect();
+
+        let mut removed = 0;
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            if !referenced.contains(name.to_string_lossy().as_ref()) {
+                fs::remove_file(entry.path())?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    // Blob names come from the state file, so they must not be able to reach outside the directory
+    fn blob_path(&self, name: &str) -> Result<PathBuf> {
+        if name.is_empty() || !name.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            bail!("invalid blob reference '{}'", name.escape_debug());
+        }
+        Ok(self.dir.join(name))
+    }
+}
+
+// 128 random bits, hex encoded
+fn new_blob_name() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes).map_err(|err| anyhow!("no randomness: {}", err))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
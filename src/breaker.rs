@@ -0,0 +1,170 @@
+use core::hash::BuildHasher;
+use core::time::Duration;
+
+use crate::{Cache, CacheError};
+
+/// Where a [`CircuitBreaker`] stands, as of its last call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Loads go through
+    Closed,
+    /// Loads fail without calling the origin until the cooldown has passed
+    Open,
+    /// The cooldown has passed and the next load is a probe: success closes the breaker, failure opens it again
+    HalfOpen,
+}
+
+/// Stops calling an origin that keeps failing, for [`Cache::try_get_or_insert_with_breaker`]
+///
+/// After `threshold` failed loads in a row the breaker opens, and misses
+/// fail fast with [`CacheError::CircuitOpen`] instead of waiting on the
+/// origin. Once `cooldown` has passed, one load is let through as a probe.
+/// Use one breaker per origin, shared by every key loaded from it.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: u64,
+    failures: u32,
+    state: BreakerState,
+    // When the breaker last opened, by the cache's clock
+    opened_at: u64,
+}
+
+impl CircuitBreaker {
+    /// Creates a closed breaker that opens after `threshold` failures in a row, for `cooldown`
+    ///
+    /// The cooldown is counted in whole seconds by the clock of the cache
+    /// the breaker is used with.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threshold` is zero.
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        assert!(
+            threshold > 0,
+            "a circuit breaker needs a failure threshold of at least one"
+        );
+        CircuitBreaker {
+            threshold,
+            cooldown: cooldown.as_secs(),
+            failures: 0,
+            state: BreakerState::Closed,
+            opened_at: 0,
+        }
+    }
+
+    /// The state the last load left the breaker in
+    ///
+    /// An open breaker whose cooldown has passed stays [`BreakerState::Open`]
+    /// here until the next load probes the origin.
+    pub fn state(&self) -> BreakerState {
+        self.state
+    }
+
+    /// The number of loads that have failed since the last one that succeeded
+    pub fn consecutive_failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Closes the breaker and forgets its failures, e.g. once the origin is known to be back
+    pub fn reset(&mut self) {
+        self.failures = 0;
+        self.state = BreakerState::Closed;
+    }
+
+    // Runs `load` unless the breaker is open, recording how it went
+    fn call<T, E>(
+        &mut self,
+        key: &str,
+        now: u64,
+        load: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E>
+    where
+        E: From<CacheError>,
+    {
+        if self.state == BreakerState::Open {
+            let closes = self.opened_at.saturating_add(self.cooldown);
+            if now < closes {
+                return Err(CacheError::CircuitOpen {
+                    key: key.into(),
+                    retry_after: Duration::from_secs(closes - now),
+                }
+                .into());
+            }
+            self.state = BreakerState::HalfOpen;
+        }
+        let result = load();
+        if result.is_ok() {
+            self.reset();
+        } else {
+            self.failures = self.failures.saturating_add(1);
+            if self.state == BreakerState::HalfOpen || self.failures >= self.threshold {
+                self.state = BreakerState::Open;
+                self.opened_at = now;
+            }
+        }
+        result
+    }
+}
+
+impl<T: Clone, S: BuildHasher> Cache<T, S> {
+    /// Like [`Cache::try_get_or_insert_with`], with `breaker` deciding whether to call `load` at all
+    ///
+    /// While the breaker is open a miss does not call `load` and fails with
+    /// [`CacheError::CircuitOpen`] converted into `E`, so with
+    /// [`Cache::with_stale_on_error`] an expired value that is still within
+    /// the allowed staleness is served instead. Live values are returned
+    /// whatever the breaker's state.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    /// use std::time::Duration;
+    /// use memory_cache::{BreakerState, Cache, CacheError, CircuitBreaker};
+    ///
+    /// static NOW: AtomicU64 = AtomicU64::new(1_000);
+    ///
+    /// let mut cache = Cache::new()
+    ///     .with_clock(|| NOW.load(Ordering::SeqCst))
+    ///     .with_stale_on_error(Duration::from_secs(3600));
+    /// let mut breaker = CircuitBreaker::new(2, Duration::from_secs(30));
+    /// cache.insert("rates", "cached rates", Duration::from_secs(60));
+    /// NOW.fetch_add(60, Ordering::SeqCst);
+    ///
+    /// let mut calls = 0;
+    /// let mut origin_down = || {
+    ///     calls += 1;
+    ///     Err(CacheError::Timeout { key: Some("rates".into()), waited: Duration::from_secs(5) })
+    /// };
+    /// for _ in 0..3 {
+    ///     let rates = cache.try_get_or_insert_with_breaker("rates", Duration::from_secs(60), &mut breaker, &mut origin_down);
+    ///     assert_eq!(rates, Ok("cached rates"));
+    /// }
+    /// // The third miss was served stale without calling the origin
+    /// assert_eq!(calls, 2);
+    /// assert_eq!(breaker.state(), BreakerState::Open);
+    ///
+    /// // After the cooldown one probe goes through, and closes the breaker
+    /// NOW.fetch_add(30, Ordering::SeqCst);
+    /// let rates = cache.try_get_or_insert_with_breaker("rates", Duration::from_secs(60), &mut breaker, || {
+    ///     Ok::<_, CacheError>("fresh rates")
+    /// });
+    /// assert_eq!(rates, Ok("fresh rates"));
+    /// assert_eq!(breaker.state(), BreakerState::Closed);
+    /// ```
+    pub fn try_get_or_insert_with_breaker<F, E>(
+        &mut self,
+        key: &str,
+        ttl: Duration,
+        breaker: &mut CircuitBreaker,
+        load: F,
+    ) -> Result<T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+        E: From<CacheError>,
+    {
+        let now = self.now();
+        self.try_get_or_insert_with(key, ttl, || breaker.call(key, now, load))
+    }
+}
@@ -0,0 +1,2216 @@
+use alloc::borrow::Cow;
+#[cfg(feature = "std")]
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::fmt;
+use core::mem;
+use core::ops::Bound;
+use core::hash::BuildHasher;
+use core::time::Duration;
+use hashbrown::hash_map::EntryRef;
+
+use crate::adaptive::{effective_ttl, AdaptiveTtl};
+#[cfg(feature = "std")]
+use crate::clock::unix_secs;
+use crate::epoch::Epochs;
+use crate::feed::Feed;
+use crate::history::History;
+use crate::policy::{disabled_by_env, Admission, Weigher};
+use crate::tuning::TuningStats;
+use crate::{CacheError, CacheEventKind, CacheStats, Clock, ExpiryHook};
+#[cfg(feature = "persistence")]
+use serde::{Serialize, Deserialize};
+
+/// The hasher a [`Cache`] uses unless another is given with [`Cache::with_hasher`]
+///
+/// This is std's randomly seeded SipHash. Without std there is no random
+/// seed to draw from, so it falls back to hashbrown's default hasher.
+#[cfg(feature = "std")]
+pub type DefaultHashBuilder = std::collections::hash_map::RandomState;
+#[cfg(not(feature = "std"))]
+pub type DefaultHashBuilder = hashbrown::DefaultHashBuilder;
+
+/// A key-value cache with automatic expiration
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct CacheEntry<T> {
+    pub(crate) value: T,
+    pub(crate) expiry: u64,
+    // When the key was first stored, last written and last read (or first
+    // stored, if never read); 0 for entries saved before these were recorded
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub(crate) created_at: u64,
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub(crate) updated_at: u64,
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub(crate) accessed_at: u64,
+    // The cache's epoch when the value was written, see Cache::bump_epoch
+    #[cfg_attr(feature = "persistence", serde(default, skip_serializing_if = "is_zero"))]
+    pub(crate) epoch: u64,
+    // Changes whenever the value is written, see Cache::get_if_modified
+    #[cfg_attr(feature = "persistence", serde(default, skip_serializing_if = "is_zero"))]
+    pub(crate) version: u64,
+}
+
+#[cfg(feature = "persistence")]
+pub(crate) fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+impl<T> CacheEntry<T> {
+    pub(crate) fn new(value: T, expiry: u64, now: u64) -> Self {
+        CacheEntry {
+            value,
+            expiry,
+            created_at: now,
+            updated_at: now,
+            accessed_at: now,
+            epoch: 0,
+            version: 0,
+        }
+    }
+
+    // Records the epoch and version the value is written in
+    pub(crate) fn stamped(mut self, epoch: u64, version: u64) -> Self {
+        self.epoch = epoch;
+        self.version = version;
+        self
+    }
+
+    // Keeps the creation and access times of `previous` if it is still live,
+    // so writes alone do not make an entry look used; a key that expired
+    // starts afresh
+    pub(crate) fn replacing(mut self, previous: &CacheEntry<T>, now: u64) -> Self {
+        if now < previous.expiry {
+            self.created_at = previous.created_at;
+            self.accessed_at = previous.accessed_at;
+        }
+        self
+    }
+}
+
+/// An in-memory cache that automatically evicts entries after their TTL expires
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::Cache;
+/// let mut cache = Cache::new();
+///
+/// // Store a value with 30 second TTL
+/// cache.insert("api_key", "secret123", Duration::from_secs(30));
+///
+/// // Retrieve the value
+/// assert_eq!(cache.get("api_key"), Some("secret123"));
+///
+/// // Manually invalidate
+/// cache.invalidate("api_key");
+/// assert_eq!(cache.get("api_key"), None);
+/// ```
+///
+/// `Debug` output lists keys but not values, so logging a cache does not leak
+/// what it holds; use [`Cache::debug_full`] to see values too.
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "persistence",
+    serde(bound(
+        serialize = "T: Serialize, S: BuildHasher",
+        deserialize = "T: Deserialize<'de>, S: BuildHasher + Default"
+    ))
+)]
+pub struct Cache<T, S = DefaultHashBuilder> {
+    pub(crate) entries: hashbrown::HashMap<String, CacheEntry<T>, S>,
+    #[cfg_attr(feature = "persistence", serde(default, skip_serializing_if = "Epochs::is_empty"))]
+    pub(crate) epochs: Epochs,
+    // The last version handed out to an entry
+    #[cfg_attr(feature = "persistence", serde(default, skip_serializing_if = "is_zero"))]
+    pub(crate) version: u64,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub(crate) clock: Option<Clock>,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub(crate) admission: Admission<T>,
+    #[cfg_attr(feature = "persistence", serde(skip, default = "disabled_by_env"))]
+    pub(crate) disabled: bool,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub(crate) max_staleness: u64,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub(crate) indexes: Indexes<T>,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub(crate) history: Option<History<T>>,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub(crate) adaptive: Option<AdaptiveTtl<T>>,
+    #[cfg_attr(feature = "persistence", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub(crate) tuning: Option<TuningStats>,
+    #[cfg_attr(feature = "persistence", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub(crate) stats: Option<CacheStats>,
+    // How Cache::with_stats sizes values
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub(crate) weigh: Option<Weigher<T>>,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub(crate) expiry_hook: Option<(u64, ExpiryHook)>,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub(crate) auto_shrink: Option<f64>,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub(crate) feed: Feed,
+    // What was removed while a budgeted load restores entries in the background
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pub(crate) tombstones: Option<Tombstones>,
+}
+
+impl<T: Clone> Cache<T> {
+    /// Creates a new empty cache
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use memory_cache::Cache;
+    /// let cache: Cache<String> = Cache::new();
+    /// ```
+    pub fn new() -> Self {
+        Self::with_hasher(DefaultHashBuilder::default())
+    }
+}
+
+impl<T: Clone, S: BuildHasher> Cache<T, S> {
+    /// Creates a new empty cache that hashes keys with `hasher`
+    ///
+    /// Use a faster hasher for trusted keys, or a fixed-seed one for tests
+    /// that must iterate the same way on every run. Loaded caches use
+    /// `S::default()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::collections::hash_map::DefaultHasher;
+    /// use std::hash::BuildHasherDefault;
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    ///
+    /// // SipHash with fixed keys
+    /// let mut cache = Cache::with_hasher(BuildHasherDefault::<DefaultHasher>::default());
+    /// cache.insert("user", "alice", Duration::from_secs(30));
+    /// assert_eq!(cache.get("user"), Some("alice"));
+    /// ```
+    pub fn with_hasher(hasher: S) -> Self {
+        Cache {
+            entries: hashbrown::HashMap::with_hasher(hasher),
+            epochs: Epochs::default(),
+            version: 0,
+            clock: None,
+            admission: Admission::default(),
+            disabled: disabled_by_env(),
+            max_staleness: 0,
+            indexes: Indexes::default(),
+            history: None,
+            adaptive: None,
+            tuning: None,
+            stats: None,
+            weigh: None,
+            expiry_hook: None,
+            auto_shrink: None,
+            feed: Feed::default(),
+            tombstones: None,
+        }
+    }
+
+    /// Inserts a value into the cache with a specified TTL
+    ///
+    /// Values rejected by an insert hook are dropped; use
+    /// [`Cache::try_insert`] to find out why.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new();
+    /// cache.insert("session", "token123", Duration::from_secs(60));
+    /// ```
+    pub fn insert(&mut self, key: &str, value: T, ttl: Duration) {
+        let _ = self.try_insert(key, value, ttl);
+    }
+
+    /// Inserts a value into the cache with a specified TTL, unless an insert hook rejects it
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new();
+    /// cache.try_insert("session", "token123", Duration::from_secs(60))?;
+    /// # Ok::<(), memory_cache::CacheError>(())
+    /// ```
+    pub fn try_insert(&mut self, key: &str, value: T, ttl: Duration) -> Result<(), CacheError> {
+        self.admission.check(key, &value, ttl)?;
+        if self.disabled {
+            return Ok(());
+        }
+
+        // Calculate the absolute expiry timestamp
+        self.drop_superseded(key);
+        let now = self.now();
+        let version = self.next_version();
+
+        let ttl = effective_ttl(&mut self.adaptive, key, &value, ttl);
+        let entry = CacheEntry::new(value, now.saturating_add(ttl), now).stamped(self.epochs.current, version);
+        let expiry = entry.expiry;
+        count_write(&mut self.tuning, &mut self.stats, self.weigh, key, &entry.value, expiry, now);
+        let kind = match self.entries.entry_ref(key) {
+            EntryRef::Occupied(mut occupied) => {
+                self.indexes.update(key, Some(&occupied.get().value), Some(&entry.value));
+                self.indexes.update_expiry(key, Some(occupied.get().expiry), Some(expiry));
+                let kind = if now < occupied.get().expiry {
+                    CacheEventKind::Update
+                } else {
+                    CacheEventKind::Insert
+                };
+                let ended = if kind == CacheEventKind::Update { kind } else { CacheEventKind::Expire };
+                count_end(&mut self.tuning, &mut self.stats, key, occupied.get(), ended, now);
+                let entry = entry.replacing(occupied.get(), now);
+                let previous = occupied.insert(entry);
+                if let Some(history) = &mut self.history {
+                    match kind {
+                        CacheEventKind::Update => history.record(key, previous.value),
+                        _ => history.forget(key),
+                    }
+                }
+                kind
+            }
+            EntryRef::Vacant(vacant) => {
+                self.indexes.update(key, None, Some(&entry.value));
+                self.indexes.update_expiry(key, None, Some(expiry));
+                vacant.insert(entry);
+                CacheEventKind::Insert
+            }
+        };
+        self.feed.emit(kind, key, now, Some(expiry));
+        Ok(())
+    }
+
+    /// Inserts a value that expires at `at` rather than after a TTL
+    ///
+    /// Suits values with an expiry of their own, such as certificates and
+    /// signed URLs. The time is rounded down to whole seconds; a time that
+    /// has already passed stores an expired entry.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::{Duration, UNIX_EPOCH};
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new().with_clock(|| 1_700_000_000);
+    ///
+    /// let not_after = UNIX_EPOCH + Duration::from_secs(1_700_086_400);
+    /// cache.insert_until("certs/api", "-----BEGIN CERTIFICATE-----", not_after);
+    /// assert_eq!(cache.ttl("certs/api"), Some(Duration::from_secs(86_400)));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn insert_until(&mut self, key: &str, value: T, at: std::time::SystemTime) {
+        let ttl = unix_secs(at).saturating_sub(self.now());
+        self.insert(key, value, Duration::from_secs(ttl));
+    }
+
+    /// Retrieves a value from the cache, returning None if expired or not found
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new();
+    /// cache.insert("user", "alice", Duration::from_secs(30));
+    ///
+    /// if let Some(user) = cache.get("user") {
+    ///     println!("Found user: {}", user);
+    /// }
+    /// ```
+    pub fn get(&mut self, key: &str) -> Option<T> {
+        if self.disabled {
+            return None;
+        }
+        self.drop_superseded(key);
+        let now = self.now();
+        if let Some(entry) = self.entries.get_mut(key) {
+            if now < entry.expiry {
+                entry.accessed_at = now;
+                if let Some((within, hook)) = self.expiry_hook {
+                    let left = entry.expiry - now;
+                    if left <= within {
+                        hook(key, Duration::from_secs(left));
+                    }
+                }
+                if let Some(tuning) = &mut self.tuning {
+                    tuning.read(key, true);
+                }
+                if let Some(stats) = &mut self.stats {
+                    stats.read(true);
+                }
+                return Some(entry.value.clone());
+            }
+            // Keep expired values around while they may still be served as stale
+            if now >= entry.expiry.saturating_add(self.max_staleness) {
+                self.remove(key, CacheEventKind::Expire);
+            }
+        }
+        if let Some(tuning) = &mut self.tuning {
+            tuning.read(key, false);
+        }
+        if let Some(stats) = &mut self.stats {
+            stats.read(false);
+        }
+        None
+    }
+
+    /// Retrieves the value of the first of `keys` with a live entry, together with that key
+    ///
+    /// Suits keys with fallbacks from specific to generic. Each key tried
+    /// counts as a read, as [`Cache::get`] would.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new();
+    /// cache.insert("banner/en", "Welcome", Duration::from_secs(60));
+    /// cache.insert("banner/default", "Hello", Duration::from_secs(60));
+    ///
+    /// let locales = ["banner/en-GB", "banner/en", "banner/default"];
+    /// assert_eq!(cache.get_first(&locales), Some(("banner/en", "Welcome")));
+    /// assert_eq!(cache.get_first(&["banner/fr"]), None);
+    /// ```
+    pub fn get_first<'k>(&mut self, keys: &[&'k str]) -> Option<(&'k str, T)> {
+        keys.iter().find_map(|&key| self.get(key).map(|value| (key, value)))
+    }
+
+    /// Retrieves a value from the cache without cloning it, returning None if expired or not found
+    ///
+    /// This counts as a read like [`Cache::get`], but borrows the stored
+    /// value, which suits large values that callers mostly only inspect;
+    /// [`Cow::into_owned`] clones it when ownership is needed after all.
+    /// Unlike `get`, an expired entry is left for [`Cache::prune_expired`] or
+    /// the next `get` to drop.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new();
+    /// cache.insert("page/home", "<html>...</html>".repeat(10_000), Duration::from_secs(60));
+    ///
+    /// let page = cache.get_cow("page/home").unwrap();
+    /// assert!(page.starts_with("<html>"));
+    /// let owned: String = page.into_owned();
+    /// ```
+    pub fn get_cow(&mut self, key: &str) -> Option<Cow<'_, T>> {
+        if self.disabled {
+            return None;
+        }
+        self.drop_superseded(key);
+        let now = self.now();
+        let Some(entry) = self.entries.get_mut(key).filter(|entry| now < entry.expiry) else {
+            if let Some(tuning) = &mut self.tuning {
+                tuning.read(key, false);
+            }
+            if let Some(stats) = &mut self.stats {
+                stats.read(false);
+            }
+            return None;
+        };
+        entry.accessed_at = now;
+        if let Some((within, hook)) = self.expiry_hook {
+            let left = entry.expiry - now;
+            if left <= within {
+                hook(key, Duration::from_secs(left));
+            }
+        }
+        if let Some(tuning) = &mut self.tuning {
+            tuning.read(key, true);
+        }
+        if let Some(stats) = &mut self.stats {
+            stats.read(true);
+        }
+        Some(Cow::Borrowed(&entry.value))
+    }
+
+    /// Manually removes an entry from the cache
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new();
+    /// cache.insert("temp", "data", Duration::from_secs(60));
+    /// cache.invalidate("temp");
+    /// assert_eq!(cache.get("temp"), None);
+    /// ```
+    pub fn invalidate(&mut self, key: &str) {
+        self.remove(key, CacheEventKind::Invalidate);
+    }
+
+    /// Removes the live value under `key` and returns it, or None if expired or not found
+    ///
+    /// This suits values that must be used at most once, such as one-time
+    /// tokens: unlike a [`Cache::get`] followed by [`Cache::invalidate`],
+    /// only one caller of a [`SharedCache`](crate::SharedCache) can get the
+    /// value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new();
+    /// cache.insert("reset/3f9a", "alice", Duration::from_secs(900));
+    ///
+    /// assert_eq!(cache.take("reset/3f9a"), Some("alice"));
+    /// assert_eq!(cache.take("reset/3f9a"), None);
+    /// ```
+    pub fn take(&mut self, key: &str) -> Option<T> {
+        if self.disabled {
+            return None;
+        }
+        self.drop_superseded(key);
+        let now = self.now();
+        let entry = self.entries.get(key)?;
+        if now < entry.expiry {
+            return self.remove(key, CacheEventKind::Invalidate).map(|entry| entry.value);
+        }
+        if now >= entry.expiry.saturating_add(self.max_staleness) {
+            self.remove(key, CacheEventKind::Expire);
+        }
+        None
+    }
+
+    pub(crate) fn remove(&mut self, key: &str, kind: CacheEventKind) -> Option<CacheEntry<T>> {
+        self.bury(key);
+        // Value indexers may panic, so they run while the entry is still there
+        let entry = self.entries.get(key)?;
+        self.indexes.update(key, Some(&entry.value), None);
+        self.indexes.update_expiry(key, Some(entry.expiry), None);
+        let entry = self.entries.remove(key)?;
+        if self.tuning.is_some() {
+            let now = self.now();
+            if let Some(tuning) = &mut self.tuning {
+                tuning.end(key, &entry, kind, now);
+            }
+        }
+        if let Some(stats) = &mut self.stats {
+            stats.end(kind);
+        }
+        if let Some(history) = &mut self.history {
+            history.forget(key);
+        }
+        // Expired keys keep what was learned, as the next write is a refresh
+        if let (Some(adaptive), CacheEventKind::Invalidate) = (&mut self.adaptive, kind) {
+            adaptive.forget(key);
+        }
+        if self.feed.is_active() {
+            let now = self.now();
+            self.feed.emit(kind, key, now, None);
+        }
+        self.shrink_if_sparse();
+        Some(entry)
+    }
+
+    /// Removes every expired entry, returning how many were removed
+    ///
+    /// Entries are otherwise only dropped when a lookup finds them expired.
+    /// Expired values still within the [`Cache::with_stale_on_error`] window
+    /// are kept.
+    pub fn prune_expired(&mut self) -> usize {
+        let now = self.now();
+        let keys: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(key, entry)| {
+                now >= entry.expiry.saturating_add(self.max_staleness) || self.epochs.supersedes(key, entry.epoch)
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &keys {
+            self.remove(key, CacheEventKind::Expire);
+        }
+        keys.len()
+    }
+
+    /// Removes every entry from the cache
+    pub fn clear(&mut self) {
+        if self.feed.is_active() || self.tuning.is_some() || self.stats.is_some() {
+            let now = self.now();
+            for (key, entry) in &self.entries {
+                let kind = if now < entry.expiry { CacheEventKind::Invalidate } else { CacheEventKind::Expire };
+                count_end(&mut self.tuning, &mut self.stats, key, entry, kind, now);
+                self.feed.emit(CacheEventKind::Invalidate, key, now, None);
+            }
+        }
+        self.entries.clear();
+        self.epochs = Epochs::default();
+        if let Some(tombstones) = &mut self.tombstones {
+            tombstones.cleared = true;
+        }
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+        if let Some(adaptive) = &mut self.adaptive {
+            adaptive.clear();
+        }
+        if let Some(keys) = &mut self.indexes.keys {
+            keys.clear();
+        }
+        if let Some(expiries) = &mut self.indexes.expiries {
+            expiries.clear();
+        }
+        for index in &mut self.indexes.values {
+            index.entries.clear();
+        }
+        self.shrink_if_sparse();
+    }
+
+    /// Frees the memory the entry map holds beyond what its entries need
+    ///
+    /// The map keeps its capacity when entries are removed, so a cache that
+    /// once held a burst of entries keeps the memory for them. Expired
+    /// entries still count; call [`Cache::prune_expired`] first to drop them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new();
+    /// for job in 0..10_000 {
+    ///     cache.insert(&format!("job/{}", job), job, Duration::from_secs(60));
+    /// }
+    /// cache.invalidate_subtree("job");
+    ///
+    /// cache.shrink_to_fit();
+    /// assert_eq!(cache.capacity(), 0);
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        self.entries.shrink_to_fit();
+    }
+
+    /// Returns how many entries the cache can hold without reallocating
+    pub fn capacity(&self) -> usize {
+        self.entries.capacity()
+    }
+
+    /// Returns true if `key` has a live entry
+    ///
+    /// Unlike [`Cache::get`], this neither clones the value nor counts as a
+    /// read, so it does not change [`Cache::idle_keys`].
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.live_entry(key).is_some()
+    }
+
+    /// Returns a reference to the live value under `key` without counting as a read
+    ///
+    /// Expired entries are not returned but, unlike with [`Cache::get`], are
+    /// not dropped either.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new();
+    /// cache.insert("report", "x".repeat(1 << 20), Duration::from_secs(60));
+    ///
+    /// assert!(cache.contains_key("report"));
+    /// assert_eq!(cache.peek("report").map(String::len), Some(1 << 20));
+    /// assert_eq!(cache.peek("missing"), None);
+    /// ```
+    pub fn peek(&self, key: &str) -> Option<&T> {
+        self.live_entry(key).map(|entry| &entry.value)
+    }
+
+    /// Returns how long the entry under `key` has left to live, or None if expired or not found
+    ///
+    /// Entries that never expire, see [`Cache::persist`], return [`Duration::MAX`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new().with_clock(|| 1_000);
+    /// cache.insert("session", "token123", Duration::from_secs(60));
+    /// assert_eq!(cache.ttl("session"), Some(Duration::from_secs(60)));
+    /// ```
+    pub fn ttl(&self, key: &str) -> Option<Duration> {
+        if self.disabled {
+            return None;
+        }
+        let now = self.now();
+        self.entries
+            .get(key)
+            .filter(|entry| now < entry.expiry && !self.epochs.supersedes(key, entry.epoch))
+            .map(|entry| match entry.expiry {
+                u64::MAX => Duration::MAX,
+                expiry => Duration::from_secs(expiry - now),
+            })
+    }
+
+    /// Makes the live entry under `key` expire at `at`, returning false if there is none
+    ///
+    /// The value and its write time are left as they are.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::{Duration, UNIX_EPOCH};
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new().with_clock(|| 1_700_000_000);
+    /// cache.insert("signed/report.pdf", "https://...", Duration::from_secs(3_600));
+    ///
+    /// assert!(cache.expire_at("signed/report.pdf", UNIX_EPOCH + Duration::from_secs(1_700_000_600)));
+    /// assert_eq!(cache.ttl("signed/report.pdf"), Some(Duration::from_secs(600)));
+    /// assert!(!cache.expire_at("missing", UNIX_EPOCH));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn expire_at(&mut self, key: &str, at: std::time::SystemTime) -> bool {
+        self.set_expiry(key, |_, _| Some(unix_secs(at)))
+    }
+
+    /// Makes the live entry under `key` never expire, returning false if there is none
+    ///
+    /// This is Redis's `PERSIST`, for promoting a provisional entry to a
+    /// permanent one. Inserting with a TTL of [`Duration::MAX`] has the same
+    /// effect.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new();
+    /// cache.insert("flags/beta", "on", Duration::from_secs(3_600));
+    ///
+    /// assert!(cache.persist("flags/beta"));
+    /// assert_eq!(cache.ttl("flags/beta"), Some(Duration::MAX));
+    /// ```
+    pub fn persist(&mut self, key: &str) -> bool {
+        self.set_expiry(key, |_, _| Some(u64::MAX))
+    }
+
+    /// Gives the live entry under `key` a TTL of `ttl` if that ends sooner than its current one
+    ///
+    /// Returns whether the TTL changed. Since the TTL only ever shrinks,
+    /// writers that each cap an entry's lifetime can do so in any order and
+    /// the strictest cap wins, as with Redis's `EXPIRE ... LT`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new().with_clock(|| 1_000);
+    /// cache.insert("session", "token123", Duration::from_secs(3_600));
+    ///
+    /// assert!(cache.expire_if_shorter("session", Duration::from_secs(600)));
+    /// assert!(!cache.expire_if_shorter("session", Duration::from_secs(1_800)));
+    /// assert_eq!(cache.ttl("session"), Some(Duration::from_secs(600)));
+    ///
+    /// assert!(cache.expire_if_longer("session", Duration::from_secs(900)));
+    /// assert_eq!(cache.ttl("session"), Some(Duration::from_secs(900)));
+    /// ```
+    pub fn expire_if_shorter(&mut self, key: &str, ttl: Duration) -> bool {
+        self.set_expiry(key, |expiry, now| {
+            let shorter = now.saturating_add(ttl.as_secs());
+            (shorter < expiry).then_some(shorter)
+        })
+    }
+
+    /// Gives the live entry under `key` a TTL of `ttl` if that ends later than its current one
+    ///
+    /// Returns whether the TTL changed; the counterpart of
+    /// [`Cache::expire_if_shorter`], as with Redis's `EXPIRE ... GT`.
+    pub fn expire_if_longer(&mut self, key: &str, ttl: Duration) -> bool {
+        self.set_expiry(key, |expiry, now| {
+            let longer = now.saturating_add(ttl.as_secs());
+            (longer > expiry).then_some(longer)
+        })
+    }
+
+    // Moves the expiry of a live entry to what `update` returns given the
+    // current expiry and time, returning false if there is no live entry or
+    // `update` leaves it
+    fn set_expiry(&mut self, key: &str, update: impl FnOnce(u64, u64) -> Option<u64>) -> bool {
+        if self.disabled {
+            return false;
+        }
+        self.drop_superseded(key);
+        let now = self.now();
+        let expiry = match self.entries.get_mut(key) {
+            Some(entry) if now < entry.expiry => match update(entry.expiry, now) {
+                Some(expiry) => {
+                    self.indexes.update_expiry(key, Some(entry.expiry), Some(expiry));
+                    entry.expiry = expiry;
+                    expiry
+                }
+                None => return false,
+            },
+            _ => return false,
+        };
+        self.feed.emit(CacheEventKind::Update, key, now, Some(expiry));
+        true
+    }
+
+    /// Removes the live entries last written more than `age` ago, returning how many were removed
+    pub fn invalidate_older_than(&mut self, age: Duration) -> usize {
+        let cutoff = self.now().saturating_sub(age.as_secs());
+        if let Some(tombstones) = &mut self.tombstones {
+            tombstones.written_before = tombstones.written_before.max(cutoff);
+        }
+        let older = self.older_than(age);
+        for (key, _) in &older {
+            self.invalidate(key);
+        }
+        older.len()
+    }
+
+    // Keeps a restore still to come from bringing back `key`
+    fn bury(&mut self, key: &str) {
+        if let Some(tombstones) = &mut self.tombstones {
+            tombstones.keys.insert(key.to_string());
+        }
+    }
+
+    pub(crate) fn live_entry(&self, key: &str) -> Option<&CacheEntry<T>> {
+        if self.disabled {
+            return None;
+        }
+        let now = self.now();
+        self.entries.get(key).filter(|entry| now < entry.expiry && !self.epochs.supersedes(key, entry.epoch))
+    }
+
+    // Stores an entry read back from a state file, times and all, unless
+    // `key` already has one; returns whether it was stored
+    #[cfg(all(feature = "persistence", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+    pub(crate) fn restore(&mut self, key: String, entry: CacheEntry<T>) -> bool {
+        if self.entries.contains_key(&key) || self.tombstones.as_ref().is_some_and(|tombstones| tombstones.cover(&key, entry.updated_at)) {
+            return false;
+        }
+        self.indexes.update(&key, None, Some(&entry.value));
+        self.indexes.update_expiry(&key, None, Some(entry.expiry));
+        self.version = self.version.max(entry.version);
+        self.entries.insert(key, entry);
+        true
+    }
+
+    /// Inserts a value only if the key has no live entry, returning whether it did
+    ///
+    /// A disabled cache stores nothing, so this returns false, leaving
+    /// callers that claim keys this way to fail rather than all succeed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new();
+    /// assert!(cache.insert_if_absent("job", "host-a", Duration::from_secs(60)));
+    /// assert!(!cache.insert_if_absent("job", "host-b", Duration::from_secs(60)));
+    /// assert_eq!(cache.get("job"), Some("host-a"));
+    /// ```
+    pub fn insert_if_absent(&mut self, key: &str, value: T, ttl: Duration) -> bool {
+        if self.disabled {
+            return false;
+        }
+        self.drop_superseded(key);
+        let now = self.now();
+        let version = self.next_version();
+        let entry = CacheEntry::new(value, now.saturating_add(ttl.as_secs()), now).stamped(self.epochs.current, version);
+        match self.entries.entry_ref(key) {
+            EntryRef::Occupied(occupied) if now < occupied.get().expiry => false,
+            _ if self.admission.check(key, &entry.value, ttl).is_err() => false,
+            EntryRef::Occupied(mut occupied) => {
+                self.indexes.update(key, Some(&occupied.get().value), Some(&entry.value));
+                self.indexes.update_expiry(key, Some(occupied.get().expiry), Some(entry.expiry));
+                self.feed.emit(CacheEventKind::Insert, key, now, Some(entry.expiry));
+                occupied.insert(entry);
+                true
+            }
+            EntryRef::Vacant(vacant) => {
+                self.indexes.update(key, None, Some(&entry.value));
+                self.indexes.update_expiry(key, None, Some(entry.expiry));
+                self.feed.emit(CacheEventKind::Insert, key, now, Some(entry.expiry));
+                vacant.insert(entry);
+                true
+            }
+        }
+    }
+
+    /// Returns the live value under `key`, or fills it with `fill` and the given TTL
+    ///
+    /// Unlike a `get` followed by an `insert` on a miss, the key is hashed
+    /// only once and an expired entry is replaced in place, without
+    /// reallocating its key. `fill` runs only on a miss; if an insert hook
+    /// rejects its value, the value is returned without being stored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new();
+    /// let user = cache.get_or_insert_with("user", Duration::from_secs(30), || "alice");
+    /// assert_eq!(user, "alice");
+    ///
+    /// let user = cache.get_or_insert_with("user", Duration::from_secs(30), || unreachable!());
+    /// assert_eq!(user, "alice");
+    /// ```
+    pub fn get_or_insert_with<F>(&mut self, key: &str, ttl: Duration, fill: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        match self.try_get_or_insert_with(key, ttl, || Ok::<T, Infallible>(fill())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Like [`Cache::get_or_insert_with`] for a fallible loader, such as a call to an origin
+    ///
+    /// A failed load is not cached and its error is returned, unless the
+    /// cache was set up with [`Cache::with_stale_on_error`] and the expired
+    /// value is still within the allowed staleness, in which case that value
+    /// is returned instead. A loader that panics leaves the entry as it was;
+    /// the panic is passed on after a [`CacheEventKind::Panic`] event.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new();
+    ///
+    /// let user = cache.try_get_or_insert_with("user", Duration::from_secs(30), || {
+    ///     Ok::<_, std::io::Error>("alice")
+    /// })?;
+    /// assert_eq!(user, "alice");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn try_get_or_insert_with<F, E>(
+        &mut self,
+        key: &str,
+        ttl: Duration,
+        load: F,
+    ) -> Result<T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        if self.disabled {
+            return traced_load(key, load);
+        }
+        self.drop_superseded(key);
+        let now = self.now();
+        if self.tuning.is_some() || self.stats.is_some() {
+            let hit = self.entries.get(key).is_some_and(|entry| now < entry.expiry);
+            if let Some(tuning) = &mut self.tuning {
+                tuning.read(key, hit);
+            }
+            if let Some(stats) = &mut self.stats {
+                stats.read(hit);
+            }
+        }
+        match self.entries.entry_ref(key) {
+            EntryRef::Occupied(mut occupied) => {
+                if now < occupied.get().expiry {
+                    occupied.get_mut().accessed_at = now;
+                    return Ok(occupied.get().value.clone());
+                }
+                let value = match guarded_load(&mut self.feed, key, now, load) {
+                    Ok(value) => value,
+                    Err(err) => {
+                        let stale = occupied.get();
+                        if now < stale.expiry.saturating_add(self.max_staleness) {
+                            return Ok(stale.value.clone());
+                        }
+                        return Err(err);
+                    }
+                };
+                if self.admission.check(key, &value, ttl).is_err() {
+                    self.indexes.update(key, Some(&occupied.get().value), None);
+                    self.indexes.update_expiry(key, Some(occupied.get().expiry), None);
+                    if let Some(tombstones) = &mut self.tombstones {
+                        tombstones.keys.insert(key.to_string());
+                    }
+                    let stale = occupied.remove();
+                    count_end(&mut self.tuning, &mut self.stats, key, &stale, CacheEventKind::Evict, now);
+                    self.feed.emit(CacheEventKind::Evict, key, now, None);
+                    return Ok(value);
+                }
+                let expiry = now.saturating_add(effective_ttl(&mut self.adaptive, key, &value, ttl));
+                count_end(&mut self.tuning, &mut self.stats, key, occupied.get(), CacheEventKind::Expire, now);
+                count_write(&mut self.tuning, &mut self.stats, self.weigh, key, &value, expiry, now);
+                self.indexes.update(key, Some(&occupied.get().value), Some(&value));
+                self.indexes.update_expiry(key, Some(occupied.get().expiry), Some(expiry));
+                self.version += 1;
+                *occupied.get_mut() = CacheEntry::new(value.clone(), expiry, now).stamped(self.epochs.current, self.version);
+                self.feed.emit(CacheEventKind::Insert, key, now, Some(expiry));
+                Ok(value)
+            }
+            EntryRef::Vacant(vacant) => {
+                let value = guarded_load(&mut self.feed, key, now, load)?;
+                if self.admission.check(key, &value, ttl).is_ok() {
+                    let expiry = now.saturating_add(effective_ttl(&mut self.adaptive, key, &value, ttl));
+                    count_write(&mut self.tuning, &mut self.stats, self.weigh, key, &value, expiry, now);
+                    self.indexes.update(key, None, Some(&value));
+                    self.indexes.update_expiry(key, None, Some(expiry));
+                    self.version += 1;
+                    vacant.insert(CacheEntry::new(value.clone(), expiry, now).stamped(self.epochs.current, self.version));
+                    self.feed.emit(CacheEventKind::Insert, key, now, Some(expiry));
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    /// Adds `delta` to the counter stored under `key` and returns the new count
+    ///
+    /// A missing or expired counter starts from zero and gets the given TTL;
+    /// incrementing a live counter keeps its existing expiry. Returns `None`,
+    /// leaving the entry untouched, if the stored value is not a counter, an
+    /// insert hook rejects the new count or the cache is disabled.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache: Cache<u64> = Cache::new();
+    /// assert_eq!(cache.increment("logins", 1, Duration::from_secs(60)), Some(1));
+    /// assert_eq!(cache.increment("logins", 2, Duration::from_secs(60)), Some(3));
+    /// ```
+    pub fn increment(&mut self, key: &str, delta: u64, ttl: Duration) -> Option<u64>
+    where
+        T: Counter,
+    {
+        if self.disabled {
+            return None;
+        }
+        self.drop_superseded(key);
+        let now = self.now();
+        let version = self.next_version();
+        let fresh = CacheEntry::new(T::from_count(delta), now.saturating_add(ttl.as_secs()), now).stamped(self.epochs.current, version);
+        match self.entries.entry_ref(key) {
+            EntryRef::Occupied(mut occupied) => {
+                let entry = occupied.get_mut();
+                if now < entry.expiry {
+                    let count = entry.value.count()?.saturating_add(delta);
+                    let value = T::from_count(count);
+                    let remaining = Duration::from_secs(entry.expiry - now);
+                    self.admission.check(key, &value, remaining).ok()?;
+                    self.indexes.update(key, Some(&entry.value), Some(&value));
+                    count_end(&mut self.tuning, &mut self.stats, key, entry, CacheEventKind::Update, now);
+                    count_write(&mut self.tuning, &mut self.stats, self.weigh, key, &value, entry.expiry, now);
+                    entry.value = value;
+                    entry.updated_at = now;
+                    entry.version = version;
+                    let expiry = entry.expiry;
+                    self.feed.emit(CacheEventKind::Update, key, now, Some(expiry));
+                    return Some(count);
+                }
+                self.admission.check(key, &fresh.value, ttl).ok()?;
+                self.indexes.update(key, Some(&entry.value), Some(&fresh.value));
+                self.indexes.update_expiry(key, Some(entry.expiry), Some(fresh.expiry));
+                count_end(&mut self.tuning, &mut self.stats, key, entry, CacheEventKind::Expire, now);
+                count_write(&mut self.tuning, &mut self.stats, self.weigh, key, &fresh.value, fresh.expiry, now);
+                *entry = fresh;
+            }
+            EntryRef::Vacant(vacant) => {
+                self.admission.check(key, &fresh.value, ttl).ok()?;
+                self.indexes.update(key, None, Some(&fresh.value));
+                self.indexes.update_expiry(key, None, Some(fresh.expiry));
+                count_write(&mut self.tuning, &mut self.stats, self.weigh, key, &fresh.value, fresh.expiry, now);
+                vacant.insert(fresh);
+            }
+        }
+        let expiry = now.saturating_add(ttl.as_secs());
+        self.feed.emit(CacheEventKind::Insert, key, now, Some(expiry));
+        Some(delta)
+    }
+
+    /// Pushes `member` onto the front of the list stored under `key` and returns its new length
+    ///
+    /// Like [`Cache::increment`], a missing or expired list starts empty and
+    /// gets the given TTL, while pushing onto a live list keeps its expiry.
+    /// Returns `None`, leaving the entry untouched, if the stored value is not
+    /// a collection, an insert hook rejects the new list or the cache is
+    /// disabled. Pushing onto a
+    /// `VecDeque<String>` takes constant time, while a `Vec<String>` moves
+    /// its members up to make room.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache: Cache<Vec<String>> = Cache::new();
+    ///
+    /// cache.lpush("jobs", "first", Duration::from_secs(60));
+    /// assert_eq!(cache.lpush("jobs", "second", Duration::from_secs(60)), Some(2));
+    /// assert_eq!(cache.rpop("jobs").as_deref(), Some("first"));
+    /// assert_eq!(cache.rpop("jobs").as_deref(), Some("second"));
+    /// assert_eq!(cache.rpop("jobs"), None);
+    /// ```
+    pub fn lpush(&mut self, key: &str, member: impl Into<String>, ttl: Duration) -> Option<usize>
+    where
+        T: Collection,
+    {
+        if self.disabled {
+            return None;
+        }
+        let member = member.into();
+        self.update_members(key, ttl, |members| {
+            members.push_front(member);
+            members.len()
+        })
+    }
+
+    /// Removes and returns the member at the back of the list stored under `key`
+    ///
+    /// Popping the last member removes the entry.
+    pub fn rpop(&mut self, key: &str) -> Option<String>
+    where
+        T: Collection,
+    {
+        if self.disabled {
+            return None;
+        }
+        self.update_members(key, Duration::ZERO, VecDeque::pop_back).flatten()
+    }
+
+    /// Adds `member` to the set stored under `key`, returning whether it was new
+    ///
+    /// TTLs and failures work as for [`Cache::lpush`]; adding a member that is
+    /// already present changes nothing.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache: Cache<Vec<String>> = Cache::new();
+    ///
+    /// assert_eq!(cache.sadd("seen", "msg-1", Duration::from_secs(60)), Some(true));
+    /// assert_eq!(cache.sadd("seen", "msg-1", Duration::from_secs(60)), Some(false));
+    /// cache.sadd("seen", "msg-2", Duration::from_secs(60));
+    /// assert!(cache.srem("seen", "msg-1"));
+    /// assert_eq!(cache.smembers("seen"), ["msg-2"]);
+    /// ```
+    pub fn sadd(&mut self, key: &str, member: impl Into<String>, ttl: Duration) -> Option<bool>
+    where
+        T: Collection,
+    {
+        if self.disabled {
+            return None;
+        }
+        let member = member.into();
+        self.update_members(key, ttl, |members| {
+            if members.contains(&member) {
+                return false;
+            }
+            members.push_back(member);
+            true
+        })
+    }
+
+    /// Removes `member` from the set stored under `key`, returning whether it was present
+    ///
+    /// Removing the last member removes the entry.
+    pub fn srem(&mut self, key: &str, member: &str) -> bool
+    where
+        T: Collection,
+    {
+        if self.disabled {
+            return false;
+        }
+        self.update_members(key, Duration::ZERO, |members| {
+            let before = members.len();
+            members.retain(|existing| existing != member);
+            members.len() != before
+        })
+        .unwrap_or(false)
+    }
+
+    /// Returns the members of the live set or list stored under `key`, in insertion order
+    pub fn smembers(&mut self, key: &str) -> Vec<String>
+    where
+        T: Collection,
+    {
+        self.get(key)
+            .and_then(|value| value.members())
+            .unwrap_or_default()
+    }
+
+    /// Sets `field` of the map stored under `key` to `value` for `ttl`, returning whether the field was new
+    ///
+    /// Each field expires on its own; the entry lives as long as its
+    /// longest-lived field. Returns `None`, leaving the entry untouched, if
+    /// the stored value is not a field map, an insert hook rejects the new
+    /// map or the cache is disabled.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::{Cache, FieldMap};
+    /// let mut cache: Cache<FieldMap> = Cache::new();
+    ///
+    /// cache.hset("user/42", "name", "alice", Duration::from_secs(3600));
+    /// cache.hset("user/42", "status", "online", Duration::from_secs(60));
+    /// assert_eq!(cache.hget("user/42", "status").as_deref(), Some("online"));
+    ///
+    /// assert!(cache.hdel("user/42", "status"));
+    /// assert_eq!(cache.hget("user/42", "status"), None);
+    /// assert_eq!(cache.hget("user/42", "name").as_deref(), Some("alice"));
+    /// ```
+    pub fn hset(
+        &mut self,
+        key: &str,
+        field: impl Into<String>,
+        value: impl Into<String>,
+        ttl: Duration,
+    ) -> Option<bool>
+    where
+        T: Fields,
+    {
+        if self.disabled {
+            return None;
+        }
+        let (field, value) = (field.into(), value.into());
+        self.update_fields(key, |fields, now| {
+            let entry = CacheEntry::new(value, now.saturating_add(ttl.as_secs()), now);
+            fields
+                .fields
+                .insert(field, entry)
+                .is_none_or(|previous| previous.expiry <= now)
+        })
+    }
+
+    /// Returns the live value of `field` in the map stored under `key`
+    pub fn hget(&mut self, key: &str, field: &str) -> Option<String>
+    where
+        T: Fields,
+    {
+        let now = self.now();
+        let value = self.get_cow(key)?;
+        let live = |entry: &CacheEntry<String>| now < entry.expiry;
+        match value.field_map() {
+            Some(fields) => fields.fields.get(field).filter(|entry| live(entry)).map(|entry| entry.value.clone()),
+            None => value.fields()?.fields.remove(field).filter(live).map(|entry| entry.value),
+        }
+    }
+
+    /// Removes `field` from the map stored under `key`, returning whether it was live
+    ///
+    /// Removing the last live field removes the entry.
+    pub fn hdel(&mut self, key: &str, field: &str) -> bool
+    where
+        T: Fields,
+    {
+        if self.disabled {
+            return false;
+        }
+        self.update_fields(key, |fields, now| {
+            fields
+                .fields
+                .remove(field)
+                .is_some_and(|entry| now < entry.expiry)
+        })
+        .unwrap_or(false)
+    }
+
+    // Applies `update` to the members under `key`. A missing or expired
+    // entry starts with no members and gets `ttl`; an empty result removes it.
+    fn update_members<R>(
+        &mut self,
+        key: &str,
+        ttl: Duration,
+        update: impl FnOnce(&mut VecDeque<String>) -> R,
+    ) -> Option<R>
+    where
+        T: Collection,
+    {
+        if self.updatable_in_place(key) {
+            return self.update_in_place(key, |entry, _| {
+                let (result, empty) = entry.value.update_members(|members| (update(members), members.is_empty()))?;
+                Some((result, !empty))
+            });
+        }
+        self.update_entry(key, |live, now| {
+            let (mut members, expiry) = match live {
+                Some(entry) => (VecDeque::from(entry.value.members()?), entry.expiry),
+                None => (VecDeque::new(), now.saturating_add(ttl.as_secs())),
+            };
+            let result = update(&mut members);
+            let replacement = (!members.is_empty()).then(|| CacheEntry::new(T::from_members(members.into()), expiry, now));
+            Some((result, replacement))
+        })
+    }
+
+    // Applies `update` to the field map under `key`, then drops expired
+    // fields. The entry expires with its last field; an empty map removes it.
+    fn update_fields<R>(
+        &mut self,
+        key: &str,
+        update: impl FnOnce(&mut FieldMap, u64) -> R,
+    ) -> Option<R>
+    where
+        T: Fields,
+    {
+        if self.updatable_in_place(key) && self.live_entry(key).is_some_and(|entry| entry.value.field_map().is_some()) {
+            return self.update_in_place(key, |entry, now| {
+                let fields = entry.value.field_map_mut()?;
+                let result = update(fields, now);
+                fields.fields.retain(|_, entry| now < entry.expiry);
+                let expiry = fields.fields.values().map(|entry| entry.expiry).max();
+                if let Some(expiry) = expiry {
+                    entry.expiry = expiry;
+                }
+                Some((result, expiry.is_some()))
+            });
+        }
+        self.update_entry(key, |live, now| {
+            let mut fields = match live {
+                Some(entry) => entry.value.fields()?,
+                None => FieldMap::default(),
+            };
+            let result = update(&mut fields, now);
+            fields.fields.retain(|_, entry| now < entry.expiry);
+            let expiry = fields.fields.values().map(|entry| entry.expiry).max();
+            let replacement = expiry.map(|expiry| CacheEntry::new(T::from_fields(fields), expiry, now));
+            Some((result, replacement))
+        })
+    }
+
+    // Whether update_in_place can change the entry under `key`: it is live,
+    // and no insert hook, size limit or value indexer has to see the new
+    // value before it is stored
+    fn updatable_in_place(&mut self, key: &str) -> bool {
+        self.drop_superseded(key);
+        self.admission.is_empty() && self.indexes.values.is_empty() && self.live_entry(key).is_some()
+    }
+
+    // Changes the live entry under `key` where it is stored, given the
+    // current time, instead of building a replacement as update_entry does.
+    // `update` may change the value and expiry and returns its result and
+    // whether to keep the entry. If it returns None it must have left the
+    // entry as it was.
+    fn update_in_place<R>(
+        &mut self,
+        key: &str,
+        update: impl FnOnce(&mut CacheEntry<T>, u64) -> Option<(R, bool)>,
+    ) -> Option<R> {
+        let now = self.now();
+        let version = self.next_version();
+        let entry = self.entries.get_mut(key)?;
+        let expiry = entry.expiry;
+        // What count_end needs of the entry as it was
+        let before = CacheEntry::new((), expiry, entry.updated_at);
+        let (result, keep) = update(entry, now)?;
+        if !keep {
+            self.remove(key, CacheEventKind::Invalidate);
+            return Some(result);
+        }
+        count_end(&mut self.tuning, &mut self.stats, key, &before, CacheEventKind::Update, now);
+        count_write(&mut self.tuning, &mut self.stats, self.weigh, key, &entry.value, entry.expiry, now);
+        entry.updated_at = now;
+        entry.epoch = self.epochs.current;
+        entry.version = version;
+        let new_expiry = entry.expiry;
+        self.indexes.update_expiry(key, Some(expiry), Some(new_expiry));
+        self.feed.emit(CacheEventKind::Update, key, now, Some(new_expiry));
+        Some(result)
+    }
+
+    // Replaces the entry under `key` with what `update` makes of it, given
+    // the entry if it is live and the current time. `update` returns its
+    // result and the new entry, or None for no entry; the new entry goes
+    // through admission like any insert and keeps the creation time of a live
+    // entry it replaces. If `update` or admission fails the entry is left
+    // untouched.
+    fn update_entry<R>(
+        &mut self,
+        key: &str,
+        update: impl FnOnce(Option<&CacheEntry<T>>, u64) -> Option<(R, Option<CacheEntry<T>>)>,
+    ) -> Option<R> {
+        self.drop_superseded(key);
+        let now = self.now();
+        let version = self.next_version();
+        match self.entries.entry_ref(key) {
+            EntryRef::Occupied(mut occupied) => {
+                let entry = occupied.get();
+                let live = now < entry.expiry;
+                let (result, replacement) = update(Some(entry).filter(|_| live), now)?;
+                match replacement {
+                    Some(replacement) => {
+                        let ttl = Duration::from_secs(replacement.expiry.saturating_sub(now));
+                        self.admission.check(key, &replacement.value, ttl).ok()?;
+                        self.indexes.update(key, Some(&occupied.get().value), Some(&replacement.value));
+                        self.indexes.update_expiry(key, Some(occupied.get().expiry), Some(replacement.expiry));
+                        let ended = if live { CacheEventKind::Update } else { CacheEventKind::Expire };
+                        count_end(&mut self.tuning, &mut self.stats, key, occupied.get(), ended, now);
+                        count_write(&mut self.tuning, &mut self.stats, self.weigh, key, &replacement.value, replacement.expiry, now);
+                        let kind = if live { CacheEventKind::Update } else { CacheEventKind::Insert };
+                        self.feed.emit(kind, key, now, Some(replacement.expiry));
+                        let replacement = replacement.replacing(occupied.get(), now).stamped(self.epochs.current, version);
+                        occupied.insert(replacement);
+                    }
+                    None => {
+                        self.indexes.update(key, Some(&occupied.get().value), None);
+                        self.indexes.update_expiry(key, Some(occupied.get().expiry), None);
+                        if let Some(tombstones) = &mut self.tombstones {
+                            tombstones.keys.insert(key.to_string());
+                        }
+                        let kind = if live { CacheEventKind::Invalidate } else { CacheEventKind::Expire };
+                        count_end(&mut self.tuning, &mut self.stats, key, occupied.get(), kind, now);
+                        occupied.remove();
+                        self.feed.emit(kind, key, now, None);
+                    }
+                }
+                Some(result)
+            }
+            EntryRef::Vacant(vacant) => {
+                let (result, replacement) = update(None, now)?;
+                if let Some(replacement) = replacement {
+                    let ttl = Duration::from_secs(replacement.expiry.saturating_sub(now));
+                    self.admission.check(key, &replacement.value, ttl).ok()?;
+                    self.indexes.update(key, None, Some(&replacement.value));
+                    self.indexes.update_expiry(key, None, Some(replacement.expiry));
+                    count_write(&mut self.tuning, &mut self.stats, self.weigh, key, &replacement.value, replacement.expiry, now);
+                    self.feed.emit(CacheEventKind::Insert, key, now, Some(replacement.expiry));
+                    vacant.insert(replacement.stamped(self.epochs.current, version));
+                }
+                Some(result)
+            }
+        }
+    }
+
+    /// Checks the cache's internal bookkeeping, describing the first inconsistency found
+    ///
+    /// Meant for tests that drive the cache with generated operations, such as
+    /// the `testing::Op` sequences enabled by the `proptest` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new();
+    /// cache.insert("user", "alice", Duration::from_secs(30));
+    /// assert_eq!(cache.check_invariants(), Ok(()));
+    /// ```
+    pub fn check_invariants(&self) -> Result<(), String> {
+        // Any index kept alongside the entry map has to be checked against it here
+        if let Some(index) = &self.indexes.keys {
+            if index.len() != self.entries.len() {
+                return Err(format!(
+                    "key index has {} keys but the cache has {} entries",
+                    index.len(),
+                    self.entries.len()
+                ));
+            }
+            if let Some(key) = index.iter().find(|key| !self.entries.contains_key(key.as_str())) {
+                return Err(format!("key index lists '{}', which has no entry", key));
+            }
+        }
+        if let Some(index) = &self.indexes.expiries {
+            if index.len() != self.entries.len() {
+                return Err(format!(
+                    "expiry index has {} keys but the cache has {} entries",
+                    index.len(),
+                    self.entries.len()
+                ));
+            }
+            let stale = index
+                .iter()
+                .find(|(expiry, key)| self.entries.get(key.as_str()).is_none_or(|entry| entry.expiry != *expiry));
+            if let Some((expiry, key)) = stale {
+                return Err(format!("expiry index lists '{}' as expiring at {}, which it does not", key, expiry));
+            }
+        }
+        for index in &self.indexes.values {
+            let mut indexed = 0;
+            for (index_key, keys) in &index.entries {
+                for key in keys {
+                    let listed = self
+                        .entries
+                        .get(key.as_str())
+                        .is_some_and(|entry| (index.indexer)(&entry.value).contains(index_key));
+                    if !listed {
+                        return Err(format!(
+                            "index '{}' maps '{}' to '{}', whose value does not produce it",
+                            index.name, index_key, key
+                        ));
+                    }
+                }
+                indexed += keys.len();
+            }
+            let expected: usize = self
+                .entries
+                .values()
+                .map(|entry| (index.indexer)(&entry.value).len())
+                .sum();
+            if indexed != expected {
+                return Err(format!(
+                    "index '{}' has {} mappings but the values produce {}",
+                    index.name, indexed, expected
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps a sorted index of keys so that subtree operations avoid a full scan
+    ///
+    /// [`Cache::list_children`] and [`Cache::invalidate_subtree`] work without
+    /// the index but then look at every key. The index holds a second copy of
+    /// each key and is not persisted, so call this again on a loaded cache.
+    pub fn with_key_index(mut self) -> Self {
+        self.indexes.keys = Some(self.entries.keys().cloned().collect());
+        self
+    }
+
+    /// Keeps the keys sorted by expiry so that [`Cache::next_expiry`] and
+    /// [`Cache::iter_by_expiry`] avoid a full scan
+    ///
+    /// Both work without the index but then look at, and sort, every entry.
+    /// Like the key index, it holds a second copy of each key and is not
+    /// persisted.
+    pub fn with_expiry_index(mut self) -> Self {
+        let expiries = self.entries.iter().map(|(key, entry)| (entry.expiry, key.clone()));
+        self.indexes.expiries = Some(expiries.collect());
+        self
+    }
+
+    /// Returns the live key that expires soonest and when it expires
+    ///
+    /// Applications can sleep until then instead of polling, e.g. to prune
+    /// or to refresh the entry. Entries that never expire are left out.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::{Duration, UNIX_EPOCH};
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new().with_clock(|| 1_000).with_expiry_index();
+    /// cache.insert("jobs/nightly", "...", Duration::from_secs(3_600));
+    /// cache.insert("jobs/hourly", "...", Duration::from_secs(60));
+    ///
+    /// assert_eq!(cache.next_expiry(), Some(("jobs/hourly".to_string(), UNIX_EPOCH + Duration::from_secs(1_060))));
+    ///
+    /// let order: Vec<_> = cache.iter_by_expiry().map(|(key, _)| key).collect();
+    /// assert_eq!(order, ["jobs/hourly", "jobs/nightly"]);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn next_expiry(&self) -> Option<(String, std::time::SystemTime)> {
+        self.iter_by_expiry().next().map(|(key, at)| (key.to_string(), at))
+    }
+
+    /// Iterates over the live keys with their expiry times, soonest first
+    ///
+    /// Keys expiring at the same second come in key order. Entries that never
+    /// expire are left out.
+    #[cfg(feature = "std")]
+    pub fn iter_by_expiry(&self) -> impl Iterator<Item = (&str, std::time::SystemTime)> + '_ {
+        let now = self.now();
+        let by_expiry: Box<dyn Iterator<Item = (u64, &str)>> = match &self.indexes.expiries {
+            _ if self.disabled => Box::new(core::iter::empty()),
+            Some(expiries) => Box::new(
+                expiries
+                    .range((now.saturating_add(1), String::new())..)
+                    .filter(|(_, key)| self.entries.get(key.as_str()).is_some_and(|entry| !self.epochs.supersedes(key, entry.epoch)))
+                    .map(|(expiry, key)| (*expiry, key.as_str())),
+            ),
+            None => {
+                let mut live: Vec<_> = self
+                    .entries
+                    .iter()
+                    .filter(|(key, entry)| now < entry.expiry && !self.epochs.supersedes(key, entry.epoch))
+                    .map(|(key, entry)| (entry.expiry, key.as_str()))
+                    .collect();
+                live.sort_unstable();
+                Box::new(live.into_iter())
+            }
+        };
+        by_expiry
+            .take_while(|(expiry, _)| *expiry != u64::MAX)
+            .map(|(expiry, key)| (key, std::time::UNIX_EPOCH + Duration::from_secs(expiry)))
+    }
+
+    /// Maintains a secondary index named `name` over the index keys `indexer` derives from each value
+    ///
+    /// The index is updated whenever a value is stored or removed and lets
+    /// [`Cache::find_by_index`] look entries up by something other than
+    /// their key. Like the key index it is not persisted; registering it
+    /// indexes the entries already in the cache.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    ///
+    /// #[derive(Clone, Debug, PartialEq)]
+    /// struct User {
+    ///     name: String,
+    ///     email: String,
+    /// }
+    ///
+    /// let mut cache = Cache::new().with_value_index("email", |user: &User| vec![user.email.clone()]);
+    /// let alice = User { name: "alice".into(), email: "a@b.com".into() };
+    /// cache.insert("user/42", alice.clone(), Duration::from_secs(60));
+    ///
+    /// assert_eq!(cache.find_by_index("email", "a@b.com"), [alice]);
+    /// cache.invalidate("user/42");
+    /// assert!(cache.find_by_index("email", "a@b.com").is_empty());
+    /// ```
+    pub fn with_value_index(mut self, name: &'static str, indexer: ValueIndexer<T>) -> Self {
+        let mut index = ValueIndex {
+            name,
+            indexer,
+            entries: BTreeMap::new(),
+        };
+        for (key, entry) in &self.entries {
+            index.add(key, indexer(&entry.value));
+        }
+        self.indexes.values.retain(|existing| existing.name != name);
+        self.indexes.values.push(index);
+        self
+    }
+
+    /// Returns the live values whose index `name` produced `index_key`, ordered by key
+    ///
+    /// Returns nothing for an index that was never registered.
+    pub fn find_by_index(&self, name: &str, index_key: &str) -> Vec<T> {
+        let now = self.now();
+        let Some(index) = self.indexes.values.iter().find(|index| index.name == name) else {
+            return Vec::new();
+        };
+        index
+            .entries
+            .get(index_key)
+            .into_iter()
+            .flatten()
+            .filter_map(|key| self.entries.get(key.as_str()).filter(|entry| now < entry.expiry && !self.epochs.supersedes(key, entry.epoch)))
+            .map(|entry| entry.value.clone())
+            .collect()
+    }
+
+    /// Lists the live immediate children of `prefix` in a `/`-separated key hierarchy
+    ///
+    /// A child is listed once, in sorted order, whether it is a key itself, a
+    /// parent of other keys, or both. An empty prefix lists the top level.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new().with_key_index();
+    /// cache.insert("user/42/prefs", "dark", Duration::from_secs(60));
+    /// cache.insert("user/42/email", "a@b.com", Duration::from_secs(60));
+    /// cache.insert("user/7", "bob", Duration::from_secs(60));
+    ///
+    /// assert_eq!(cache.list_children("user"), ["42", "7"]);
+    /// assert_eq!(cache.list_children("user/42"), ["email", "prefs"]);
+    /// assert_eq!(cache.list_children(""), ["user"]);
+    /// ```
+    pub fn list_children(&self, prefix: &str) -> Vec<String> {
+        let now = self.now();
+        let skip = if prefix.is_empty() { 0 } else { prefix.len() + 1 };
+        let mut children = BTreeSet::new();
+        for key in self.subtree_keys(prefix) {
+            let live = self.entries.get(key).is_some_and(|entry| now < entry.expiry && !self.epochs.supersedes(key, entry.epoch));
+            if key != prefix && live {
+                children.insert(key[skip..].split(KEY_SEPARATOR).next().unwrap_or_default());
+            }
+        }
+        children.into_iter().map(ToString::to_string).collect()
+    }
+
+    /// Removes `prefix` and every key below it, returning how many entries were removed
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new();
+    /// cache.insert("user/42/prefs", "dark", Duration::from_secs(60));
+    /// cache.insert("user/42", "alice", Duration::from_secs(60));
+    /// cache.insert("user/420", "carol", Duration::from_secs(60));
+    ///
+    /// assert_eq!(cache.invalidate_subtree("user/42"), 2);
+    /// assert_eq!(cache.get("user/420"), Some("carol"));
+    /// ```
+    pub fn invalidate_subtree(&mut self, prefix: &str) -> usize {
+        if let Some(tombstones) = &mut self.tombstones {
+            tombstones.subtrees.push(prefix.to_string());
+        }
+        let keys: Vec<String> = self
+            .subtree_keys(prefix)
+            .into_iter()
+            .map(ToString::to_string)
+            .collect();
+        for key in &keys {
+            self.invalidate(key);
+        }
+        keys.len()
+    }
+
+    /// Removes every key matching the glob `pattern`, returning how many entries were removed
+    ///
+    /// `*` matches any run of characters, `/` included, and `?` matches a
+    /// single character.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new();
+    /// cache.insert("session/alice", "token1", Duration::from_secs(60));
+    /// cache.insert("session/bob", "token2", Duration::from_secs(60));
+    /// cache.insert("user/alice", "admin", Duration::from_secs(60));
+    ///
+    /// assert_eq!(cache.invalidate_matching("*/alice"), 2);
+    /// assert_eq!(cache.get("session/bob"), Some("token2"));
+    /// ```
+    pub fn invalidate_matching(&mut self, pattern: &str) -> usize {
+        if let Some(tombstones) = &mut self.tombstones {
+            tombstones.patterns.push(pattern.to_string());
+        }
+        let keys: Vec<String> = self
+            .entries
+            .keys()
+            .filter(|key| glob_match(pattern, key))
+            .cloned()
+            .collect();
+        for key in &keys {
+            self.invalidate(key);
+        }
+        keys.len()
+    }
+
+    // Keys equal to `prefix` or below it, including expired ones
+    fn subtree_keys(&self, prefix: &str) -> Vec<&str> {
+        let in_subtree = |key: &&str| in_subtree(prefix, key);
+        match &self.indexes.keys {
+            // Keys starting with the prefix sort directly after it
+            Some(index) => index
+                .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+                .map(String::as_str)
+                .take_while(|key| key.starts_with(prefix))
+                .filter(in_subtree)
+                .collect(),
+            None => self.entries.keys().map(String::as_str).filter(in_subtree).collect(),
+        }
+    }
+}
+
+/// Separates the levels of hierarchical keys such as `user/42/prefs`
+pub const KEY_SEPARATOR: char = '/';
+
+// Whether `key` is `prefix` or below it
+fn in_subtree(prefix: &str, key: &str) -> bool {
+    prefix.is_empty() || key.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with(KEY_SEPARATOR))
+}
+
+// Removals made while load_shared_with_budget_from is still restoring
+// entries in the background; a saved entry they cover stays removed
+#[derive(Debug, Default)]
+pub(crate) struct Tombstones {
+    cleared: bool,
+    keys: hashbrown::HashSet<String>,
+    subtrees: Vec<String>,
+    patterns: Vec<String>,
+    // Entries last written before this were removed by invalidate_older_than
+    written_before: u64,
+}
+
+impl Tombstones {
+    #[cfg(all(feature = "persistence", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+    fn cover(&self, key: &str, updated_at: u64) -> bool {
+        self.cleared
+            || self.keys.contains(key)
+            || self.subtrees.iter().any(|prefix| in_subtree(prefix, key))
+            || self.patterns.iter().any(|pattern| glob_match(pattern, key))
+            || (updated_at > 0 && updated_at < self.written_before)
+    }
+}
+
+/// Derives the index keys of a value for [`Cache::with_value_index`]
+pub type ValueIndexer<T> = fn(&T) -> Vec<String>;
+
+// Indexes kept in sync with the entry map
+#[derive(Clone)]
+pub(crate) struct Indexes<T> {
+    keys: Option<BTreeSet<String>>,
+    // Every entry's expiry and key, soonest first
+    expiries: Option<BTreeSet<(u64, String)>>,
+    values: Vec<ValueIndex<T>>,
+}
+
+#[derive(Clone)]
+struct ValueIndex<T> {
+    name: &'static str,
+    indexer: ValueIndexer<T>,
+    // Index key to the cache keys whose values produce it
+    entries: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl<T> Indexes<T> {
+    // Called whenever the value under `key` changes from `old` to `new`,
+    // where None means there is no entry
+    pub(crate) fn update(&mut self, key: &str, old: Option<&T>, new: Option<&T>) {
+        // Indexers are user code that may panic, so they all run before any
+        // index changes
+        let index_keys: Vec<_> = self
+            .values
+            .iter()
+            .map(|index| (old.map_or_else(Vec::new, index.indexer), new.map_or_else(Vec::new, index.indexer)))
+            .collect();
+        if let Some(keys) = &mut self.keys {
+            match (old, new) {
+                (None, Some(_)) => {
+                    keys.insert(key.to_string());
+                }
+                (Some(_), None) => {
+                    keys.remove(key);
+                }
+                _ => {}
+            }
+        }
+        for (index, (old_keys, new_keys)) in self.values.iter_mut().zip(index_keys) {
+            index.remove(key, old_keys);
+            index.add(key, new_keys);
+        }
+    }
+
+    // Called whenever the expiry of `key` changes from `old` to `new`
+    fn update_expiry(&mut self, key: &str, old: Option<u64>, new: Option<u64>) {
+        let Some(expiries) = &mut self.expiries else {
+            return;
+        };
+        if old == new {
+            return;
+        }
+        if let Some(old) = old {
+            expiries.remove(&(old, key.to_string()));
+        }
+        if let Some(new) = new {
+            expiries.insert((new, key.to_string()));
+        }
+    }
+}
+
+impl<T> Default for Indexes<T> {
+    fn default() -> Self {
+        Indexes {
+            keys: None,
+            expiries: None,
+            values: Vec::new(),
+        }
+    }
+}
+
+impl<T> ValueIndex<T> {
+    fn add(&mut self, key: &str, index_keys: Vec<String>) {
+        for index_key in index_keys {
+            self.entries.entry(index_key).or_default().insert(key.to_string());
+        }
+    }
+
+    fn remove(&mut self, key: &str, index_keys: Vec<String>) {
+        for index_key in index_keys {
+            if let Some(keys) = self.entries.get_mut(&index_key) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.entries.remove(&index_key);
+                }
+            }
+        }
+    }
+}
+
+impl<T, S> Cache<T, S> {
+    /// Formats the cache including values and expiry times, for development only
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new();
+    /// cache.insert("api_key", "secret123", Duration::from_secs(30));
+    ///
+    /// assert!(!format!("{:?}", cache).contains("secret123"));
+    /// assert!(format!("{:?}", cache.debug_full()).contains("secret123"));
+    /// ```
+    pub fn debug_full(&self) -> impl fmt::Debug + '_
+    where
+        T: fmt::Debug,
+    {
+        DebugFull(self)
+    }
+}
+
+impl<T, S> fmt::Debug for Cache<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("len", &self.entries.len())
+            .field("keys", &self.entries.keys())
+            .finish_non_exhaustive()
+    }
+}
+
+struct DebugFull<'a, T, S>(&'a Cache<T, S>);
+
+impl<T: fmt::Debug, S> fmt::Debug for DebugFull<'_, T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("entries", &self.0.entries)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Values that can be used as counters with [`Cache::increment`]
+///
+/// `String` values hold counters in decimal, so counters can live alongside
+/// other entries in a string cache.
+pub trait Counter {
+    /// The current count, or `None` if the value is not a counter
+    fn count(&self) -> Option<u64>;
+
+    /// Creates a value holding `count`
+    fn from_count(count: u64) -> Self;
+}
+
+impl Counter for u64 {
+    fn count(&self) -> Option<u64> {
+        Some(*self)
+    }
+
+    fn from_count(count: u64) -> Self {
+        count
+    }
+}
+
+impl Counter for String {
+    fn count(&self) -> Option<u64> {
+        self.parse().ok()
+    }
+
+    fn from_count(count: u64) -> Self {
+        count.to_string()
+    }
+}
+
+/// Values that can hold the lists and sets of [`Cache::lpush`] and [`Cache::sadd`]
+///
+/// With the `persistence` feature, `String` values hold their members as a
+/// JSON array, so collections can live alongside other entries in a string
+/// cache.
+///
+/// # Example
+///
+/// ```
+/// use std::collections::VecDeque;
+/// use std::time::Duration;
+/// use memory_cache::Cache;
+///
+/// // Queues indexed by their newest job, which follows every push
+/// let mut queues = Cache::new().with_value_index("newest", |jobs: &VecDeque<String>| jobs.front().cloned().into_iter().collect());
+/// queues.lpush("queue/mail", "job-1", Duration::from_secs(60));
+/// queues.lpush("queue/mail", "job-2", Duration::from_secs(60));
+///
+/// assert_eq!(queues.find_by_index("newest", "job-2").len(), 1);
+/// assert!(queues.find_by_index("newest", "job-1").is_empty());
+/// ```
+pub trait Collection {
+    /// The members in order, or `None` if the value is not a collection
+    fn members(&self) -> Option<Vec<String>>;
+
+    /// Creates a value holding `members`
+    fn from_members(members: Vec<String>) -> Self;
+
+    /// Applies `update` to the members, or returns `None`, leaving the value as it was, if it is not a collection
+    ///
+    /// The default decodes the members with [`Collection::members`] and
+    /// stores the result with [`Collection::from_members`]; values that hold
+    /// their members directly change them in place.
+    fn update_members<R>(&mut self, update: impl FnOnce(&mut VecDeque<String>) -> R) -> Option<R>
+    where
+        Self: Sized,
+    {
+        let mut members = VecDeque::from(self.members()?);
+        let result = update(&mut members);
+        *self = Self::from_members(members.into());
+        Some(result)
+    }
+}
+
+impl Collection for Vec<String> {
+    fn members(&self) -> Option<Vec<String>> {
+        Some(self.clone())
+    }
+
+    fn from_members(members: Vec<String>) -> Self {
+        members
+    }
+
+    fn update_members<R>(&mut self, update: impl FnOnce(&mut VecDeque<String>) -> R) -> Option<R> {
+        // Both conversions reuse the buffer rather than cloning the members
+        let mut members = VecDeque::from(mem::take(self));
+        let result = update(&mut members);
+        *self = members.into();
+        Some(result)
+    }
+}
+
+impl Collection for VecDeque<String> {
+    fn members(&self) -> Option<Vec<String>> {
+        Some(self.iter().cloned().collect())
+    }
+
+    fn from_members(members: Vec<String>) -> Self {
+        members.into()
+    }
+
+    fn update_members<R>(&mut self, update: impl FnOnce(&mut VecDeque<String>) -> R) -> Option<R> {
+        Some(update(self))
+    }
+}
+
+#[cfg(feature = "persistence")]
+impl Collection for String {
+    fn members(&self) -> Option<Vec<String>> {
+        serde_json::from_str(self).ok()
+    }
+
+    fn from_members(members: Vec<String>) -> Self {
+        serde_json::to_string(&members).unwrap_or_default()
+    }
+}
+
+/// A map of string fields that each expire on their own, as stored by [`Cache::hset`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize), serde(transparent))]
+pub struct FieldMap {
+    fields: BTreeMap<String, CacheEntry<String>>,
+}
+
+/// Values that can hold the field maps of [`Cache::hset`]
+///
+/// With the `persistence` feature, `String` values hold their fields as a
+/// JSON object, so field maps can live alongside other entries in a string
+/// cache.
+pub trait Fields {
+    /// The fields, or `None` if the value is not a field map
+    fn fields(&self) -> Option<FieldMap>;
+
+    /// Creates a value holding `fields`
+    fn from_fields(fields: FieldMap) -> Self;
+
+    /// The fields as stored, for reading one without copying the map, or `None` if they have to be decoded with [`Fields::fields`]
+    fn field_map(&self) -> Option<&FieldMap> {
+        None
+    }
+
+    /// The fields as stored, for changing them in place, or `None` if they have to be decoded with [`Fields::fields`]
+    fn field_map_mut(&mut self) -> Option<&mut FieldMap> {
+        None
+    }
+}
+
+impl Fields for FieldMap {
+    fn fields(&self) -> Option<FieldMap> {
+        Some(self.clone())
+    }
+
+    fn from_fields(fields: FieldMap) -> Self {
+        fields
+    }
+
+    fn field_map(&self) -> Option<&FieldMap> {
+        Some(self)
+    }
+
+    fn field_map_mut(&mut self) -> Option<&mut FieldMap> {
+        Some(self)
+    }
+}
+
+#[cfg(feature = "persistence")]
+impl Fields for String {
+    fn fields(&self) -> Option<FieldMap> {
+        serde_json::from_str(self).ok()
+    }
+
+    fn from_fields(fields: FieldMap) -> Self {
+        serde_json::to_string(&fields).unwrap_or_default()
+    }
+}
+
+// Runs a loader for `key`, in a span of its own with the `tracing` feature
+fn traced_load<R>(key: &str, load: impl FnOnce() -> R) -> R {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("memory_cache.load", key).entered();
+    #[cfg(not(feature = "tracing"))]
+    let _ = key;
+    load()
+}
+
+// Runs a loader as traced_load does, emitting a Panic event for `key` if it
+// panics before letting the panic go on; nothing has been changed by then
+fn guarded_load<R>(feed: &mut Feed, key: &str, now: u64, load: impl FnOnce() -> R) -> R {
+    #[cfg(feature = "std")]
+    match std::panic::catch_unwind(core::panic::AssertUnwindSafe(|| traced_load(key, load))) {
+        Ok(value) => value,
+        Err(panic) => {
+            feed.emit(CacheEventKind::Panic, key, now, None);
+            std::panic::resume_unwind(panic)
+        }
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        let _ = (feed, now);
+        traced_load(key, load)
+    }
+}
+
+// Counts a write in the stats and tuning counters; every path that stores a
+// value goes through here
+fn count_write<T>(tuning: &mut Option<TuningStats>, stats: &mut Option<CacheStats>, weigh: Option<Weigher<T>>, key: &str, value: &T, expiry: u64, now: u64) {
+    if let Some(tuning) = tuning {
+        tuning.write(key, expiry, now);
+    }
+    if let Some(stats) = stats {
+        stats.write(weigh, value);
+    }
+}
+
+// Counts the end of `entry`, overwritten or removed as `kind` says
+fn count_end<V>(tuning: &mut Option<TuningStats>, stats: &mut Option<CacheStats>, key: &str, entry: &CacheEntry<V>, kind: CacheEventKind, now: u64) {
+    if let Some(tuning) = tuning {
+        tuning.end(key, entry, kind, now);
+    }
+    if let Some(stats) = stats {
+        stats.end(kind);
+    }
+}
+
+// Matches `text` against a glob where `*` is any run of characters and `?` is one character
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was seen and how much text it had swallowed
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the last `*` swallow one more character and retry
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+impl<T: Clone, S: BuildHasher + Default> Default for Cache<T, S> {
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+// A clone keeps the entries and configuration, but starts with no subscribers
+impl<T: Clone, S: Clone> Clone for Cache<T, S> {
+    fn clone(&self) -> Self {
+        Cache {
+            entries: self.entries.clone(),
+            epochs: self.epochs.clone(),
+            version: self.version,
+            clock: self.clock,
+            admission: self.admission.clone(),
+            disabled: self.disabled,
+            max_staleness: self.max_staleness,
+            indexes: self.indexes.clone(),
+            history: self.history.clone(),
+            adaptive: self.adaptive.clone(),
+            tuning: self.tuning.clone(),
+            stats: self.stats,
+            weigh: self.weigh,
+            expiry_hook: self.expiry_hook,
+            auto_shrink: self.auto_shrink,
+            feed: Feed::default(),
+            tombstones: None,
+        }
+    }
+}
+
+/// Caches are equal when they hold the same live keys and values
+///
+/// Expiry times, expired entries and configuration are ignored, so a cache
+/// can be compared with one built in a test without matching its TTLs.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::Cache;
+/// let mut cache = Cache::new();
+/// cache.extend([("a", (1, Duration::from_secs(30))), ("b", (2, Duration::from_secs(60)))]);
+///
+/// let mut expected = Cache::new();
+/// expected.extend([("b", (2, Duration::from_secs(5))), ("a", (1, Duration::from_secs(5)))]);
+/// expected.insert("gone", 3, Duration::ZERO);
+///
+/// assert_eq!(cache, expected);
+/// assert_eq!(cache.clone(), cache);
+/// ```
+impl<T: PartialEq, S: BuildHasher> PartialEq for Cache<T, S> {
+    fn eq(&self, other: &Self) -> bool {
+        let (now, other_now) = (self.now(), other.now());
+        let is_live = |cache: &Self, now: u64, key: &str, entry: &CacheEntry<T>| now < entry.expiry && !cache.epochs.supersedes(key, entry.epoch);
+        let live = |cache: &Self, now: u64| cache.entries.iter().filter(|(key, entry)| is_live(cache, now, key, entry)).count();
+        live(self, now) == live(other, other_now)
+            && self.entries.iter().filter(|(key, entry)| is_live(self, now, key, entry)).all(|(key, entry)| {
+                other.entries.get(key.as_str()).is_some_and(|theirs| is_live(other, other_now, key, theirs) && theirs.value == entry.value)
+            })
+    }
+}
+
+/// Inserts each key with its value and TTL, as [`Cache::insert`] does
+impl<K: AsRef<str>, T: Clone, S: BuildHasher> Extend<(K, (T, Duration))> for Cache<T, S> {
+    fn extend<I: IntoIterator<Item = (K, (T, Duration))>>(&mut self, entries: I) {
+        for (key, (value, ttl)) in entries {
+            self.insert(key.as_ref(), value, ttl);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use core::hash::BuildHasher;
+
+use crate::Cache;
+
+/// A source of the current time, in whole seconds since the Unix epoch
+///
+/// Caches read the system clock unless one is supplied with
+/// [`Cache::with_clock`]. On `wasm32-unknown-unknown` the system clock is only
+/// available with the `js` feature.
+pub type Clock = fn() -> u64;
+
+impl<T: Clone, S: BuildHasher> Cache<T, S> {
+    /// Makes the cache read the current time from `clock` instead of the system clock
+    ///
+    /// Since the clock is not persisted, it has to be set again on a loaded cache.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    ///
+    /// static NOW: AtomicU64 = AtomicU64::new(1_000);
+    ///
+    /// let mut cache = Cache::new().with_clock(|| NOW.load(Ordering::SeqCst));
+    /// cache.insert("session", "token123", Duration::from_secs(60));
+    ///
+    /// NOW.fetch_add(61, Ordering::SeqCst);
+    /// assert_eq!(cache.get("session"), None);
+    /// ```
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Makes the cache read a clock that a background thread refreshes every 100ms
+    ///
+    /// Each operation then costs an atomic load instead of a system clock
+    /// read, which shows up in profiles of very hot caches. Readings lag the
+    /// system clock by at most 100ms. The thread is shared by all caches and
+    /// started on first use.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new().with_coarse_clock();
+    /// cache.insert("session", "token123", Duration::from_secs(60));
+    /// assert_eq!(cache.get("session"), Some("token123"));
+    /// ```
+    #[cfg(all(feature = "std", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+    pub fn with_coarse_clock(self) -> Self {
+        coarse::start_coarse_clock();
+        self.with_clock(coarse::coarse_now)
+    }
+}
+
+impl<T, S> Cache<T, S> {
+    // Current time according to the cache's clock
+    pub(crate) fn now(&self) -> u64 {
+        match self.clock {
+            Some(clock) => clock(),
+            None => system_now(),
+        }
+    }
+}
+
+// Current time in whole seconds since the Unix epoch
+#[cfg(all(feature = "std", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+pub(crate) fn system_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(all(feature = "js", target_arch = "wasm32", target_os = "unknown"))]
+pub(crate) fn system_now() -> u64 {
+    (js_sys::Date::now() / 1000.0) as u64
+}
+
+#[cfg(not(any(
+    all(feature = "std", not(all(target_arch = "wasm32", target_os = "unknown"))),
+    all(feature = "js", target_arch = "wasm32", target_os = "unknown")
+)))]
+pub(crate) fn system_now() -> u64 {
+    panic!("no system clock available: enable the `std` (or on wasm, `js`) feature or use Cache::with_clock")
+}
+
+// Whole seconds since the Unix epoch, or 0 for earlier times
+#[cfg(feature = "std")]
+pub(crate) fn unix_secs(at: std::time::SystemTime) -> u64 {
+    at.duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+// A clock kept current by a background thread, for Cache::with_coarse_clock
+#[cfg(all(feature = "std", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+mod coarse {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Once;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::system_now;
+
+    // Clock readings are whole seconds, so a tenth of a second of lag is invisible
+    const TICK: Duration = Duration::from_millis(100);
+
+    static NOW: AtomicU64 = AtomicU64::new(0);
+    static TICKER: Once = Once::new();
+
+    // Starts the background thread that keeps NOW up to date, once per process
+    pub(crate) fn start_coarse_clock() {
+        TICKER.call_once(|| {
+            NOW.store(system_now(), Ordering::Relaxed);
+            thread::Builder::new()
+                .name("memory_cache-clock".into())
+                .spawn(|| loop {
+                    thread::sleep(TICK);
+                    NOW.store(system_now(), Ordering::Relaxed);
+                })
+                .expect("failed to spawn the coarse clock thread");
+        });
+    }
+
+    // The last reading taken by the ticker thread
+    pub(crate) fn coarse_now() -> u64 {
+        NOW.load(Ordering::Relaxed)
+    }
+}
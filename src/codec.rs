@@ -0,0 +1,294 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::de::{DeserializeOwned, Error as _};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{Cache, KEY_SEPARATOR};
+
+/// Turns values of type `T` into bytes and back
+///
+/// Implement this for formats beyond the built-in [`JsonCodec`],
+/// [`RawCodec`] and, with the `cbor` feature, `CborCodec`, and register it
+/// with [`CodecCache::with_codec`].
+pub trait Codec<T>: Send + Sync {
+    fn encode(&self, value: &T) -> Result<Vec<u8>>;
+    fn decode(&self, data: &[u8]) -> Result<T>;
+}
+
+/// Encodes values as JSON
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+impl<T: Serialize + DeserializeOwned> Codec<T> for JsonCodec {
+    fn encode(&self, value: &T) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<T> {
+        Ok(serde_json::from_slice(data)?)
+    }
+}
+
+/// Stores bytes and strings as they are
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RawCodec;
+
+impl Codec<Vec<u8>> for RawCodec {
+    fn encode(&self, value: &Vec<u8>) -> Result<Vec<u8>> {
+        Ok(value.clone())
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+}
+
+impl Codec<String> for RawCodec {
+    fn encode(&self, value: &String) -> Result<Vec<u8>> {
+        Ok(value.as_bytes().to_vec())
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<String> {
+        Ok(String::from_utf8(data.to_vec())?)
+    }
+}
+
+/// Encodes values as CBOR, a compact binary counterpart of JSON
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl<T: Serialize + DeserializeOwned> Codec<T> for CborCodec {
+    fn encode(&self, value: &T) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        ciborium::into_writer(value, &mut data)?;
+        Ok(data)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<T> {
+        Ok(ciborium::from_reader(data)?)
+    }
+}
+
+/// An encoded value with the name of the codec that encoded it
+///
+/// In a state file, data that is valid UTF-8 is written as a string and
+/// anything else as base64, so text stays readable and binary data grows by
+/// a third rather than the fourfold of a JSON array of numbers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encoded {
+    codec: String,
+    data: Vec<u8>,
+}
+
+impl Encoded {
+    /// Returns the name of the codec that encoded the value
+    pub fn codec(&self) -> &str {
+        &self.codec
+    }
+
+    /// Returns the encoded bytes
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredEncoded<'a> {
+    codec: String,
+    #[serde(default, borrow, skip_serializing_if = "Option::is_none")]
+    text: Option<Cow<'a, str>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    base64: Option<String>,
+}
+
+impl Serialize for Encoded {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (text, base64) = match std::str::from_utf8(&self.data) {
+            Ok(text) => (Some(text.into()), None),
+            Err(_) => (None, Some(STANDARD.encode(&self.data))),
+        };
+        StoredEncoded {
+            codec: self.codec.clone(),
+            text,
+            base64,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Encoded {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stored = StoredEncoded::deserialize(deserializer)?;
+        let data = match (stored.text, stored.base64) {
+            (Some(text), None) => text.into_owned().into_bytes(),
+            (None, Some(base64)) => STANDARD.decode(base64).map_err(D::Error::custom)?,
+            _ => {
+                return Err(D::Error::custom(
+                    "expected exactly one of `text` and `base64`",
+                ))
+            }
+        };
+        Ok(Encoded {
+            codec: stored.codec,
+            data,
+        })
+    }
+}
+
+/// A cache that encodes each value with a codec chosen per key
+///
+/// Codecs are registered by name. Each value is encoded with the codec of
+/// the most specific namespace its key falls in, or the default codec, or
+/// one named on insert, and the codec's name is stored with the entry so
+/// that it is decoded the same way after a reload. Save and load the
+/// underlying [`Cache<Encoded>`] with [`CodecCache::cache`] and
+/// [`CodecCache::with_cache`]; codecs must be registered again after loading.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::{CodecCache, JsonCodec, RawCodec};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let mut cache = CodecCache::new("json", JsonCodec)
+///     .with_codec("raw", RawCodec)
+///     .with_namespace("images", "raw");
+///
+/// let png = vec![0x89, b'P', b'N', b'G', 0, 0xff];
+/// cache.insert("images/logo", &png, Duration::from_secs(60))?;
+/// cache.insert("scores/alice", &vec![10, 20], Duration::from_secs(60))?;
+/// assert_eq!(cache.get("images/logo")?, Some(png));
+///
+/// // Binary data is stored as base64 instead of an array of numbers
+/// let state = serde_json::to_string(cache.cache())?;
+/// assert!(state.contains(r#"{"codec":"raw","base64":"iVBORwD/"}"#));
+/// assert!(state.contains(r#"{"codec":"json","text":"[10,20]"}"#));
+/// # Ok(())
+/// # }
+/// ```
+pub struct CodecCache<T> {
+    cache: Cache<Encoded>,
+    codecs: Vec<(String, Arc<dyn Codec<T>>)>,
+    default: String,
+    // Key prefixes with the codec for keys equal to or below them
+    namespaces: Vec<(String, String)>,
+}
+
+impl<T> CodecCache<T> {
+    /// Creates a new empty cache that encodes values with `codec` by default
+    pub fn new(name: &str, codec: impl Codec<T> + 'static) -> Self {
+        CodecCache {
+            cache: Cache::new(),
+            codecs: vec![(name.to_string(), Arc::new(codec))],
+            default: name.to_string(),
+            namespaces: Vec::new(),
+        }
+    }
+
+    /// Registers `codec` under `name`, replacing any codec already registered under it
+    pub fn with_codec(mut self, name: &str, codec: impl Codec<T> + 'static) -> Self {
+        self.codecs.retain(|(registered, _)| registered != name);
+        self.codecs.push((name.to_string(), Arc::new(codec)));
+        self
+    }
+
+    /// Encodes values for `prefix` and the keys below it with the codec registered as `codec`
+    pub fn with_namespace(mut self, prefix: &str, codec: &str) -> Self {
+        self.namespaces
+            .retain(|(registered, _)| registered != prefix);
+        self.namespaces
+            .push((prefix.to_string(), codec.to_string()));
+        self
+    }
+
+    /// Replaces the stored entries with those of `cache`, e.g. one loaded from a state file
+    pub fn with_cache(mut self, cache: Cache<Encoded>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Returns the underlying cache of encoded values, e.g. to save it
+    pub fn cache(&self) -> &Cache<Encoded> {
+        &self.cache
+    }
+
+    /// Encodes `value` with the codec for `key` and inserts it with a specified TTL
+    pub fn insert(&mut self, key: &str, value: &T, ttl: Duration) -> Result<()> {
+        let codec = self.codec_for(key).to_string();
+        self.insert_with(&codec, key, value, ttl)
+    }
+
+    /// Encodes `value` with the codec registered as `codec` and inserts it with a specified TTL
+    pub fn insert_with(&mut self, codec: &str, key: &str, value: &T, ttl: Duration) -> Result<()> {
+        let data = self.codec(codec)?.encode(value)?;
+        let encoded = Encoded {
+            codec: codec.to_string(),
+            data,
+        };
+        self.cache.try_insert(key, encoded, ttl)?;
+        Ok(())
+    }
+
+    /// Retrieves and decodes a value, returning None if expired or not found
+    ///
+    /// Fails if the value's codec is not registered or cannot decode it.
+    pub fn get(&mut self, key: &str) -> Result<Option<T>> {
+        let Some(encoded) = self.cache.get_cow(key) else {
+            return Ok(None);
+        };
+        let codec = self.codecs.iter().find(|(name, _)| *name == encoded.codec);
+        match codec {
+            Some((_, codec)) => codec.decode(&encoded.data).map(Some),
+            None => bail!(
+                "value for '{}' was encoded with codec '{}', which is not registered",
+                key,
+                encoded.codec
+            ),
+        }
+    }
+
+    /// Removes an entry if present
+    pub fn invalidate(&mut self, key: &str) {
+        self.cache.invalidate(key);
+    }
+
+    fn codec(&self, name: &str) -> Result<&dyn Codec<T>> {
+        self.codecs
+            .iter()
+            .find(|(registered, _)| registered == name)
+            .map(|(_, codec)| &**codec)
+            .ok_or_else(|| anyhow!("no codec is registered as '{}'", name))
+    }
+
+    // The codec of the longest namespace `key` is in, or the default
+    fn codec_for(&self, key: &str) -> &str {
+        self.namespaces
+            .iter()
+            .filter(|(prefix, _)| {
+                key.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(KEY_SEPARATOR))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(&self.default, |(_, codec)| codec)
+    }
+}
+
+impl<T> fmt::Debug for CodecCache<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let codecs: Vec<&str> = self.codecs.iter().map(|(name, _)| name.as_str()).collect();
+        f.debug_struct("CodecCache")
+            .field("len", &self.cache.entries.len())
+            .field("codecs", &codecs)
+            .field("default", &self.default)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,220 @@
+use alloc::sync::Arc;
+use core::fmt;
+use core::hash::Hash;
+use core::time::Duration;
+
+#[cfg(feature = "persistence")]
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+#[cfg(feature = "persistence")]
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+#[cfg(feature = "persistence")]
+use crate::CacheEntry;
+use crate::{Cache, Clock, DefaultHashBuilder};
+
+/// A cache that stores one shared copy of each distinct value
+///
+/// Values are addressed by their content: inserting a value equal to one
+/// already stored reuses that copy, and a copy is dropped once no entry
+/// refers to it. This suits payloads that many keys share, such as rendered
+/// templates. With the `persistence` feature the serialized form also keeps
+/// a single copy of each value, with entries referring to it by position.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use memory_cache::DedupCache;
+/// let mut pages = DedupCache::new();
+///
+/// pages.insert("/en/home", "<h1>Welcome</h1>".to_string(), Duration::from_secs(60));
+/// pages.insert("/en-gb/home", "<h1>Welcome</h1>".to_string(), Duration::from_secs(60));
+/// assert_eq!(pages.distinct_values(), 1);
+///
+/// let us = pages.get("/en/home").unwrap();
+/// let gb = pages.get("/en-gb/home").unwrap();
+/// assert!(Arc::ptr_eq(&us, &gb));
+///
+/// // The saved state holds a single copy too
+/// let state = serde_json::to_string(&pages).unwrap();
+/// assert_eq!(state.matches("Welcome").count(), 1);
+/// ```
+pub struct DedupCache<T> {
+    cache: Cache<Arc<T>>,
+    // Each distinct value with the number of entries referring to it
+    refs: hashbrown::HashMap<Arc<T>, usize, DefaultHashBuilder>,
+}
+
+impl<T: Hash + Eq> DedupCache<T> {
+    /// Creates a new empty cache
+    pub fn new() -> Self {
+        DedupCache {
+            cache: Cache::new(),
+            refs: hashbrown::HashMap::with_hasher(DefaultHashBuilder::default()),
+        }
+    }
+
+    /// Uses `clock` instead of the system clock, as [`Cache::with_clock`] does
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.cache = self.cache.with_clock(clock);
+        self
+    }
+
+    /// Inserts a value into the cache with a specified TTL, sharing an equal stored value if there is one
+    pub fn insert(&mut self, key: &str, value: T, ttl: Duration) {
+        let previous = self.peek(key);
+        let shared = self.acquire(Arc::new(value));
+        self.cache.insert(key, shared.clone(), ttl);
+        // A disabled cache stores nothing, so the new reference never materialised
+        if !self
+            .peek(key)
+            .is_some_and(|stored| Arc::ptr_eq(&stored, &shared))
+        {
+            self.release(&shared);
+            return;
+        }
+        if let Some(previous) = previous {
+            self.release(&previous);
+        }
+    }
+
+    /// Retrieves a value from the cache, or None if expired or not found
+    pub fn get(&mut self, key: &str) -> Option<Arc<T>> {
+        let stored = self.peek(key);
+        let value = self.cache.get(key);
+        // An expired entry is evicted by the lookup, taking its reference along
+        if let Some(stored) = stored {
+            if !self.cache.entries.contains_key(key) {
+                self.release(&stored);
+            }
+        }
+        value
+    }
+
+    /// Removes an entry if present
+    pub fn invalidate(&mut self, key: &str) {
+        if let Some(stored) = self.peek(key) {
+            self.cache.invalidate(key);
+            self.release(&stored);
+        }
+    }
+
+    /// Returns the number of distinct values held, expired entries included until they are evicted
+    pub fn distinct_values(&self) -> usize {
+        self.refs.len()
+    }
+
+    fn peek(&self, key: &str) -> Option<Arc<T>> {
+        self.cache.entries.get(key).map(|entry| entry.value.clone())
+    }
+
+    // Takes a reference to the stored copy equal to `value`, storing `value` if there is none
+    fn acquire(&mut self, value: Arc<T>) -> Arc<T> {
+        match self.refs.get_key_value_mut(&*value) {
+            Some((shared, count)) => {
+                *count += 1;
+                shared.clone()
+            }
+            None => {
+                self.refs.insert(value.clone(), 1);
+                value
+            }
+        }
+    }
+
+    fn release(&mut self, value: &Arc<T>) {
+        if let Some(count) = self.refs.get_mut(&**value) {
+            *count -= 1;
+            if *count == 0 {
+                self.refs.remove(&**value);
+            }
+        }
+    }
+}
+
+impl<T: Hash + Eq> Default for DedupCache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for DedupCache<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DedupCache")
+            .field("len", &self.cache.entries.len())
+            .field("distinct_values", &self.refs.len())
+            .finish_non_exhaustive()
+    }
+}
+
+// Serialized form: each distinct value once, with entries holding its position
+#[cfg(feature = "persistence")]
+#[derive(Serialize, Deserialize)]
+struct State<V, K: Ord> {
+    values: Vec<V>,
+    entries: BTreeMap<K, CacheEntry<usize>>,
+}
+
+#[cfg(feature = "persistence")]
+impl<T: Hash + Eq + Serialize> Serialize for DedupCache<T> {
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        let positions: hashbrown::HashMap<*const T, usize> = self
+            .refs
+            .keys()
+            .enumerate()
+            .map(|(position, value)| (Arc::as_ptr(value), position))
+            .collect();
+        let entries = self
+            .cache
+            .entries
+            .iter()
+            .map(|(key, entry)| {
+                let entry = CacheEntry {
+                    value: positions[&Arc::as_ptr(&entry.value)],
+                    expiry: entry.expiry,
+                    created_at: entry.created_at,
+                    updated_at: entry.updated_at,
+                    accessed_at: entry.accessed_at,
+                epoch: entry.epoch,
+                version: entry.version,
+                };
+                (key.as_str(), entry)
+            })
+            .collect();
+        State {
+            values: self.refs.keys().map(|value| &**value).collect(),
+            entries,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "persistence")]
+impl<'de, T: Hash + Eq + Deserialize<'de>> Deserialize<'de> for DedupCache<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let state = State::<T, String>::deserialize(deserializer)?;
+        let values: Vec<Arc<T>> = state.values.into_iter().map(Arc::new).collect();
+        let mut dedup = DedupCache::new();
+        for (key, entry) in state.entries {
+            let value = values.get(entry.value).ok_or_else(|| {
+                D::Error::custom(format!(
+                    "entry '{}' refers to missing value {}",
+                    key, entry.value
+                ))
+            })?;
+            let entry = CacheEntry {
+                value: dedup.acquire(value.clone()),
+                expiry: entry.expiry,
+                created_at: entry.created_at,
+                updated_at: entry.updated_at,
+                accessed_at: entry.accessed_at,
+                epoch: entry.epoch,
+                version: entry.version,
+            };
+            dedup.cache.version = dedup.cache.version.max(entry.version);
+            dedup.cache.entries.insert(key, entry);
+        }
+        Ok(dedup)
+    }
+}
@@ -0,0 +1,161 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::net::IpAddr;
+use core::time::Duration;
+
+use crate::Cache;
+
+/// The answer a [`Resolver`] got for a host name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnsAnswer {
+    /// The addresses the name resolves to, each with the TTL of its record
+    Records(Vec<(IpAddr, Duration)>),
+    /// The name does not exist (NXDOMAIN); `ttl` is the negative-caching TTL from the zone's SOA record
+    NxDomain { ttl: Duration },
+}
+
+/// Looks up host names for a [`DnsCache`]
+///
+/// Implemented for closures, so a resolver library or a test double can be
+/// plugged in without a wrapper type. Return an error for failures that
+/// should not be cached, such as timeouts or SERVFAIL.
+pub trait Resolver {
+    type Error;
+
+    fn resolve(&mut self, host: &str) -> Result<DnsAnswer, Self::Error>;
+}
+
+impl<F, E> Resolver for F
+where
+    F: FnMut(&str) -> Result<DnsAnswer, E>,
+{
+    type Error = E;
+
+    fn resolve(&mut self, host: &str) -> Result<DnsAnswer, E> {
+        self(host)
+    }
+}
+
+/// A cache of DNS lookups that honors record TTLs and caches NXDOMAIN answers
+///
+/// A name's addresses are cached for the shortest TTL among its records,
+/// and a name that does not exist for the negative TTL of its answer, both
+/// optionally capped. Names are compared case-insensitively and without a
+/// trailing dot. Answers without records and resolver errors are not
+/// cached.
+///
+/// # Example
+///
+/// ```
+/// use std::net::IpAddr;
+/// use std::time::Duration;
+/// use memory_cache::{DnsAnswer, DnsCache};
+///
+/// let mut lookups = 0;
+/// let mut dns = DnsCache::new(|host: &str| {
+///     lookups += 1;
+///     Ok::<_, std::io::Error>(match host {
+///         "api.example.com" => DnsAnswer::Records(vec![
+///             ("192.0.2.10".parse().unwrap(), Duration::from_secs(300)),
+///             ("192.0.2.11".parse().unwrap(), Duration::from_secs(60)),
+///         ]),
+///         _ => DnsAnswer::NxDomain { ttl: Duration::from_secs(30) },
+///     })
+/// });
+///
+/// let addresses = dns.lookup("api.example.com")?.unwrap();
+/// assert_eq!(addresses[0], "192.0.2.10".parse::<IpAddr>().unwrap());
+/// assert_eq!(dns.ttl("API.example.com."), Some(Duration::from_secs(60)));
+/// assert_eq!(dns.lookup("typo.example.com")?, None);
+/// assert_eq!(dns.lookup("typo.example.com")?, None);
+/// drop(dns);
+/// assert_eq!(lookups, 2);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct DnsCache<R> {
+    resolver: R,
+    // None marks a name that does not exist
+    cache: Cache<Option<Vec<IpAddr>>>,
+    max_ttl: Option<Duration>,
+    max_negative_ttl: Option<Duration>,
+}
+
+impl<R: Resolver> DnsCache<R> {
+    /// Creates a cache that looks names up with `resolver`
+    pub fn new(resolver: R) -> Self {
+        DnsCache {
+            resolver,
+            cache: Cache::new(),
+            max_ttl: None,
+            max_negative_ttl: None,
+        }
+    }
+
+    /// Keeps answers in an existing cache, e.g. one with its own clock
+    pub fn with_cache(mut self, cache: Cache<Option<Vec<IpAddr>>>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Caches addresses for at most `ttl`, whatever their records say
+    pub fn with_max_ttl(mut self, ttl: Duration) -> Self {
+        self.max_ttl = Some(ttl);
+        self
+    }
+
+    /// Caches NXDOMAIN answers for at most `ttl`, so a newly created name is found sooner
+    pub fn with_max_negative_ttl(mut self, ttl: Duration) -> Self {
+        self.max_negative_ttl = Some(ttl);
+        self
+    }
+
+    /// Returns the addresses of `host`, or None if it does not exist, asking the resolver on a miss
+    pub fn lookup(&mut self, host: &str) -> Result<Option<Vec<IpAddr>>, R::Error> {
+        let key = normalize(host);
+        if let Some(answer) = self.cache.get(&key) {
+            return Ok(answer);
+        }
+        let (answer, ttl) = match self.resolver.resolve(&key)? {
+            DnsAnswer::Records(records) => {
+                let Some(ttl) = records.iter().map(|(_, ttl)| *ttl).min() else {
+                    return Ok(Some(Vec::new()));
+                };
+                let addresses = records.into_iter().map(|(address, _)| address).collect();
+                (Some(addresses), cap(ttl, self.max_ttl))
+            }
+            DnsAnswer::NxDomain { ttl } => (None, cap(ttl, self.max_negative_ttl)),
+        };
+        self.cache.insert(&key, answer.clone(), ttl);
+        Ok(answer)
+    }
+
+    /// Returns how much longer the cached answer for `host` is kept, if there is one
+    pub fn ttl(&self, host: &str) -> Option<Duration> {
+        self.cache.ttl(&normalize(host))
+    }
+
+    /// Drops the cached answer for `host`, so the next lookup asks the resolver
+    pub fn invalidate(&mut self, host: &str) {
+        self.cache.invalidate(&normalize(host));
+    }
+
+    /// Drops every cached answer
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Returns the underlying cache of answers
+    pub fn cache(&self) -> &Cache<Option<Vec<IpAddr>>> {
+        &self.cache
+    }
+}
+
+// DNS names are case-insensitive, and `example.com.` is `example.com`
+fn normalize(host: &str) -> String {
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+fn cap(ttl: Duration, max: Option<Duration>) -> Duration {
+    max.map_or(ttl, |max| ttl.min(max))
+}
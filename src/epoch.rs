@@ -0,0 +1,92 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use core::hash::BuildHasher;
+
+#[cfg(feature = "persistence")]
+use serde::{Deserialize, Serialize};
+
+use crate::{Cache, CacheEventKind, KEY_SEPARATOR};
+
+// The epochs behind Cache::bump_epoch
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub(crate) struct Epochs {
+    // Goes up by one on every bump; entries record it when written
+    pub(crate) current: u64,
+    // Each bumped namespace with the epoch it was bumped to
+    namespaces: BTreeMap<String, u64>,
+}
+
+impl Epochs {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.namespaces.is_empty()
+    }
+
+    // Whether an entry for `key` written in `epoch` predates a bump of a
+    // namespace it is in
+    pub(crate) fn supersedes(&self, key: &str, epoch: u64) -> bool {
+        if epoch == self.current {
+            return false;
+        }
+        let ancestors = key
+            .match_indices(KEY_SEPARATOR)
+            .map(|(at, _)| &key[..at])
+            .chain([key]);
+        [""]
+            .into_iter()
+            .chain(ancestors)
+            .any(|namespace| self.namespaces.get(namespace).is_some_and(|&bumped| epoch < bumped))
+    }
+}
+
+impl<T: Clone, S: BuildHasher> Cache<T, S> {
+    /// Invalidates every entry in `namespace` at once, without visiting them
+    ///
+    /// An entry is in a namespace if its key equals it or is below it, so
+    /// `user/42` covers `user/42/prefs`; the empty namespace covers every
+    /// key. Entries written before the bump are misses from then on and are
+    /// dropped when a lookup or [`Cache::prune_expired`] comes across them,
+    /// so until then they still count towards memory use, though listings
+    /// and iteration skip them. Epochs are saved with the cache.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new();
+    /// cache.insert("tenant/acme/users", "[...]", Duration::from_secs(60));
+    /// cache.insert("tenant/acme/plans", "[...]", Duration::from_secs(60));
+    /// cache.insert("tenant/globex/users", "[...]", Duration::from_secs(60));
+    ///
+    /// cache.bump_epoch("tenant/acme");
+    /// assert_eq!(cache.get("tenant/acme/users"), None);
+    /// assert!(!cache.contains_key("tenant/acme/plans"));
+    /// assert!(cache.contains_key("tenant/globex/users"));
+    /// assert_eq!(cache.list_children("tenant"), ["globex"]);
+    ///
+    /// // New writes belong to the new epoch
+    /// cache.insert("tenant/acme/users", "[]", Duration::from_secs(60));
+    /// assert_eq!(cache.get("tenant/acme/users"), Some("[]"));
+    /// ```
+    pub fn bump_epoch(&mut self, namespace: &str) {
+        self.epochs.current += 1;
+        let current = self.epochs.current;
+        self.epochs.namespaces.insert(namespace.to_string(), current);
+    }
+
+    // Drops the entry under `key` if a bump has superseded it, so the
+    // caller finds no entry
+    pub(crate) fn drop_superseded(&mut self, key: &str) {
+        if self.epochs.is_empty() {
+            return;
+        }
+        if self
+            .entries
+            .get(key)
+            .is_some_and(|entry| self.epochs.supersedes(key, entry.epoch))
+        {
+            self.remove(key, CacheEventKind::Expire);
+        }
+    }
+}
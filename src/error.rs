@@ -0,0 +1,67 @@
+use alloc::string::String;
+use core::fmt;
+use core::time::Duration;
+
+/// Why an insert hook refused a value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectReason(String);
+
+impl RejectReason {
+    /// Creates a reason from a human-readable message
+    pub fn new(message: impl Into<String>) -> Self {
+        RejectReason(message.into())
+    }
+
+    /// The message the hook gave
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Errors returned by fallible cache operations
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CacheError {
+    /// An insert hook refused to store a value under `key`
+    Rejected { key: String, reason: RejectReason },
+    /// A value of `size` bytes exceeded the cache's maximum of `max`
+    ValueTooLarge { key: String, size: usize, max: usize },
+    /// Gave up after `waited` on the lock or the loader for `key`, or on the lock alone without one
+    Timeout { key: Option<String>, waited: Duration },
+    /// The circuit breaker for `key`'s origin is open, and lets a load through in `retry_after`
+    CircuitOpen { key: String, retry_after: Duration },
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::Rejected { key, reason } => {
+                write!(f, "insert of key '{}' rejected: {}", key, reason)
+            }
+            CacheError::ValueTooLarge { key, size, max } => write!(
+                f,
+                "value for key '{}' is {} bytes, over the {} byte limit",
+                key, size, max
+            ),
+            CacheError::Timeout { key: Some(key), waited } => {
+                write!(f, "timed out after {:?} waiting for key '{}'", waited, key)
+            }
+            CacheError::Timeout { key: None, waited } => {
+                write!(f, "timed out after {:?} waiting for the cache lock", waited)
+            }
+            CacheError::CircuitOpen { key, retry_after } => write!(
+                f,
+                "origin for key '{}' is failing, not loading for another {:?}",
+                key, retry_after
+            ),
+        }
+    }
+}
+
+impl core::error::Error for CacheError {}
@@ -0,0 +1,179 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Cache, CacheEvent, CacheEventKind};
+
+const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+const DEFAULT_MAX_FILES: usize = 3;
+
+/// How noteworthy a [`CacheEvent`] is, for filtering an [`EventLog`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventLevel {
+    /// Inserts and updates
+    Debug,
+    /// Entries leaving the cache: expirations, evictions and invalidations
+    Info,
+    /// Loaders that panicked
+    Warn,
+}
+
+impl EventLevel {
+    /// The level events of `kind` are logged at
+    pub fn of(kind: CacheEventKind) -> Self {
+        match kind {
+            CacheEventKind::Insert | CacheEventKind::Update => EventLevel::Debug,
+            CacheEventKind::Panic => EventLevel::Warn,
+            _ => EventLevel::Info,
+        }
+    }
+}
+
+/// A JSON Lines file that a cache writes its events to, for [`Cache::with_event_log`]
+///
+/// Each line is a [`CacheEvent`] with its `level`. When a write would take
+/// the file past its maximum size, the file is renamed to `<path>.1`,
+/// earlier ones move up to `<path>.2` and so on, and the oldest beyond the
+/// number kept is deleted.
+#[derive(Debug)]
+pub struct EventLog {
+    path: PathBuf,
+    file: File,
+    // Bytes in the current file, as far as this log knows
+    size: u64,
+    max_size: u64,
+    max_files: usize,
+    level: EventLevel,
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    level: EventLevel,
+    #[serde(flatten)]
+    event: &'a CacheEvent,
+}
+
+impl EventLog {
+    /// Opens the log at `path` for appending, creating it if missing
+    ///
+    /// By default every event is logged, files are rotated at 10 MiB and
+    /// three rotated files are kept.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(EventLog {
+            path,
+            file,
+            size,
+            max_size: DEFAULT_MAX_SIZE,
+            max_files: DEFAULT_MAX_FILES,
+            level: EventLevel::Debug,
+        })
+    }
+
+    /// Rotates the file before it grows past `bytes`
+    ///
+    /// A single event larger than that still gets a file of its own.
+    pub fn with_max_size(mut self, bytes: u64) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    /// Keeps `count` rotated files; with zero the file is emptied instead of rotated
+    pub fn with_max_files(mut self, count: usize) -> Self {
+        self.max_files = count;
+        self
+    }
+
+    /// Logs only events at `level` or above
+    pub fn with_level(mut self, level: EventLevel) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// The path of the current file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn write(&mut self, event: &CacheEvent) {
+        let level = EventLevel::of(event.kind);
+        if level < self.level {
+            return;
+        }
+        // The change has already been made, so a failed write can only be dropped
+        let _ = self.append(&Line { level, event });
+    }
+
+    fn append(&mut self, line: &Line<'_>) -> io::Result<()> {
+        let mut line = serde_json::to_string(line)?;
+        line.push('\n');
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.max_files == 0 {
+            self.file.set_len(0)?;
+        } else {
+            // Missing files leave gaps that simply close up
+            for n in (1..self.max_files).rev() {
+                let _ = fs::rename(self.rotated(n), self.rotated(n + 1));
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+            self.file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+}
+
+impl<T, S> Cache<T, S> {
+    /// Writes every change made to the cache from now on to `log`
+    ///
+    /// Events are the ones [`Cache::subscribe`] delivers, so an expired
+    /// entry is logged when it is dropped rather than the moment it expires.
+    /// Failed writes are ignored. The log is not persisted or cloned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::{Cache, EventLevel, EventLog};
+    /// let path = std::env::temp_dir().join("memory_cache_event_log_example.jsonl");
+    /// # let _ = std::fs::remove_file(&path);
+    ///
+    /// let log = EventLog::open(&path)?.with_level(EventLevel::Info);
+    /// let mut cache = Cache::new().with_clock(|| 1_000).with_event_log(log);
+    /// cache.insert("session/7", "token", Duration::from_secs(60));
+    /// cache.invalidate("session/7");
+    ///
+    /// assert_eq!(
+    ///     std::fs::read_to_string(&path)?,
+    ///     "{\"level\":\"info\",\"kind\":\"invalidate\",\"key\":\"session/7\",\"at\":1000,\"expiry\":null}\n"
+    /// );
+    /// # std::fs::remove_file(&path)?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn with_event_log(mut self, log: EventLog) -> Self {
+        self.feed.log = Some(log);
+        self
+    }
+}
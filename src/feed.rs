@@ -0,0 +1,138 @@
+use alloc::string::String;
+#[cfg(feature = "std")]
+use alloc::{string::ToString, vec::Vec};
+#[cfg(feature = "std")]
+use std::sync::mpsc::{self, Receiver, Sender};
+
+#[cfg(feature = "persistence")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "std")]
+use crate::Cache;
+#[cfg(all(feature = "persistence", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+use crate::EventLog;
+
+/// What happened to the entry a [`CacheEvent`] is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "persistence",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum CacheEventKind {
+    /// A value was stored under a key with no live entry
+    Insert,
+    /// A live entry was overwritten or modified in place
+    Update,
+    /// An expired entry was dropped
+    Expire,
+    /// The cache dropped an entry itself, e.g. an expired one whose replacement an insert hook refused
+    Evict,
+    /// An entry was removed on request
+    Invalidate,
+    /// A loader for the key panicked, leaving its entry as it was
+    Panic,
+}
+
+/// One change to a cache, as delivered by [`Cache::subscribe`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub struct CacheEvent {
+    pub kind: CacheEventKind,
+    pub key: String,
+    /// When the change happened, in seconds since the Unix epoch by the cache's clock
+    pub at: u64,
+    /// When the entry now expires, for inserts and updates
+    pub expiry: Option<u64>,
+}
+
+// The senders behind Cache::subscribe and the log behind
+// Cache::with_event_log; without std there is nothing to send on
+#[derive(Default)]
+pub(crate) struct Feed {
+    #[cfg(feature = "std")]
+    subscribers: Vec<Sender<CacheEvent>>,
+    #[cfg(all(feature = "persistence", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+    pub(crate) log: Option<EventLog>,
+}
+
+impl Feed {
+    // Lets callers skip reading the clock when nobody is listening
+    #[cfg(feature = "std")]
+    pub(crate) fn is_active(&self) -> bool {
+        #[cfg(all(feature = "persistence", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+        if self.log.is_some() {
+            return true;
+        }
+        !self.subscribers.is_empty()
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub(crate) fn is_active(&self) -> bool {
+        false
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn emit(&mut self, kind: CacheEventKind, key: &str, at: u64, expiry: Option<u64>) {
+        if !self.is_active() {
+            return;
+        }
+        let event = CacheEvent {
+            kind,
+            key: key.to_string(),
+            at,
+            expiry,
+        };
+        #[cfg(all(feature = "persistence", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+        if let Some(log) = &mut self.log {
+            log.write(&event);
+        }
+        // A dropped receiver unsubscribes
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub(crate) fn emit(
+        &mut self,
+        _kind: CacheEventKind,
+        _key: &str,
+        _at: u64,
+        _expiry: Option<u64>,
+    ) {
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, S> Cache<T, S> {
+    /// Returns a receiver for every change made to the cache from now on
+    ///
+    /// Events are sent as changes happen, in order, and queue up in the
+    /// channel until received; dropping the receiver unsubscribes. Values are
+    /// not included, only keys and times. Expired entries produce an
+    /// [`CacheEventKind::Expire`] event when they are dropped, not the moment
+    /// they expire. Subscriptions are not persisted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::{Cache, CacheEventKind};
+    /// let mut cache = Cache::new().with_clock(|| 1_000);
+    /// let events = cache.subscribe();
+    ///
+    /// cache.insert("user", "alice", Duration::from_secs(60));
+    /// cache.insert("user", "bob", Duration::from_secs(60));
+    /// cache.invalidate("user");
+    ///
+    /// let kinds: Vec<_> = events.try_iter().map(|event| event.kind).collect();
+    /// assert_eq!(kinds, [CacheEventKind::Insert, CacheEventKind::Update, CacheEventKind::Invalidate]);
+    /// ```
+    pub fn subscribe(&mut self) -> Receiver<CacheEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.feed.subscribers.push(sender);
+        receiver
+    }
+}
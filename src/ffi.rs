@@ -0,0 +1,130 @@
+//! C bindings for a string cache
+//!
+//! The functions here are exported unmangled so C and C++ code can embed the
+//! cache; `include/memory_cache.h` declares them. Build a linkable library with
+//!
+//! ```bash
+//! cargo rustc --lib --release --features ffi --crate-type cdylib
+//! ```
+//!
+//! and regenerate the header after changing this module with
+//!
+//! ```bash
+//! cbindgen --config cbindgen.toml --output include/memory_cache.h
+//! ```
+//!
+//! All strings are NUL-terminated UTF-8. Strings returned by
+//! [`cache_get`] are owned by the caller and must be released with
+//! [`cache_string_free`].
+
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+use std::time::Duration;
+
+use crate::Cache;
+
+/// An opaque cache of string values
+pub struct MemoryCache {
+    cache: Cache<String>,
+}
+
+// Borrows a C string as UTF-8, or None for NULL and invalid UTF-8
+unsafe fn borrow_str<'a>(value: *const c_char) -> Option<&'a str> {
+    if value.is_null() {
+        return None;
+    }
+    CStr::from_ptr(value).to_str().ok()
+}
+
+/// Creates a new empty cache; release it with `cache_free`
+#[no_mangle]
+pub extern "C" fn cache_new() -> *mut MemoryCache {
+    Box::into_raw(Box::new(MemoryCache {
+        cache: Cache::new(),
+    }))
+}
+
+/// Destroys a cache created by `cache_new`
+///
+/// # Safety
+///
+/// `cache` must be NULL or a pointer returned by `cache_new` that has not
+/// been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn cache_free(cache: *mut MemoryCache) {
+    if !cache.is_null() {
+        drop(Box::from_raw(cache));
+    }
+}
+
+/// Inserts a copy of `value` under `key` for `ttl_secs` seconds
+///
+/// Returns false, without inserting, if any pointer is NULL or a string is
+/// not valid UTF-8.
+///
+/// # Safety
+///
+/// `cache` must be a live pointer from `cache_new`; `key` and `value` must be
+/// NULL or point to NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn cache_insert(
+    cache: *mut MemoryCache,
+    key: *const c_char,
+    value: *const c_char,
+    ttl_secs: u64,
+) -> bool {
+    let (Some(cache), Some(key), Some(value)) =
+        (cache.as_mut(), borrow_str(key), borrow_str(value))
+    else {
+        return false;
+    };
+    cache
+        .cache
+        .insert(key, value.to_string(), Duration::from_secs(ttl_secs));
+    true
+}
+
+/// Returns a copy of the live value under `key`, or NULL if there is none
+///
+/// # Safety
+///
+/// `cache` must be a live pointer from `cache_new` and `key` must be NULL or
+/// point to a NUL-terminated string. A non-NULL result must be released with
+/// `cache_string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn cache_get(cache: *mut MemoryCache, key: *const c_char) -> *mut c_char {
+    let (Some(cache), Some(key)) = (cache.as_mut(), borrow_str(key)) else {
+        return ptr::null_mut();
+    };
+    cache
+        .cache
+        .get(key)
+        .and_then(|value| CString::new(value).ok())
+        .map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Removes the entry under `key`, if any
+///
+/// # Safety
+///
+/// `cache` must be a live pointer from `cache_new` and `key` must be NULL or
+/// point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cache_invalidate(cache: *mut MemoryCache, key: *const c_char) {
+    if let (Some(cache), Some(key)) = (cache.as_mut(), borrow_str(key)) {
+        cache.cache.invalidate(key);
+    }
+}
+
+/// Releases a string returned by `cache_get`
+///
+/// # Safety
+///
+/// `value` must be NULL or a pointer returned by `cache_get` that has not
+/// been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn cache_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(CString::from_raw(value));
+    }
+}
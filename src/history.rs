@@ -0,0 +1,129 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::hash::BuildHasher;
+
+use crate::{Cache, CacheEventKind};
+
+// Earlier values of each key, kept by Cache::with_history
+#[derive(Clone)]
+pub(crate) struct History<T> {
+    depth: usize,
+    // Newest first
+    versions: BTreeMap<String, VecDeque<T>>,
+}
+
+impl<T> History<T> {
+    // Called when an insert replaces the live value `previous` under `key`
+    pub(crate) fn record(&mut self, key: &str, previous: T) {
+        if self.depth == 0 {
+            return;
+        }
+        let versions = self.versions.entry(key.to_string()).or_default();
+        versions.push_front(previous);
+        versions.truncate(self.depth);
+    }
+
+    // Called when the entry under `key` goes away
+    pub(crate) fn forget(&mut self, key: &str) {
+        self.versions.remove(key);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.versions.clear();
+    }
+}
+
+impl<T: Clone, S: BuildHasher> Cache<T, S> {
+    /// Keeps up to `versions` earlier values of each key, so a bad write can be undone
+    ///
+    /// A version is kept whenever an insert replaces a live value; updates
+    /// in place, such as [`Cache::increment`], are not recorded. A key's
+    /// history is dropped with its entry, and history is not persisted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new().with_history(2);
+    /// cache.insert("rates", "1.08", Duration::from_secs(60));
+    /// cache.insert("rates", "1.09", Duration::from_secs(60));
+    /// cache.insert("rates", "error: upstream timeout", Duration::from_secs(60));
+    ///
+    /// assert_eq!(cache.history("rates"), ["1.09", "1.08"]);
+    /// assert_eq!(cache.get_version("rates", 1), Some("1.09"));
+    ///
+    /// assert!(cache.revert("rates"));
+    /// assert_eq!(cache.get("rates"), Some("1.09"));
+    /// assert_eq!(cache.history("rates"), ["1.08"]);
+    /// ```
+    pub fn with_history(mut self, versions: usize) -> Self {
+        self.history = Some(History {
+            depth: versions,
+            versions: BTreeMap::new(),
+        });
+        self
+    }
+
+    /// Returns the earlier values kept for the live entry under `key`, newest first
+    pub fn history(&self, key: &str) -> Vec<T> {
+        if self.live_entry(key).is_none() {
+            return Vec::new();
+        }
+        self.history
+            .as_ref()
+            .and_then(|history| history.versions.get(key))
+            .map_or_else(Vec::new, |versions| versions.iter().cloned().collect())
+    }
+
+    /// Returns the value `n` versions back under `key`: 0 is the live value, 1 the one it replaced
+    ///
+    /// Returns None if there is no live entry or fewer versions are kept.
+    /// This does not count as a read.
+    pub fn get_version(&self, key: &str, n: usize) -> Option<T> {
+        let live = self.live_entry(key)?;
+        match n {
+            0 => Some(live.value.clone()),
+            n => self
+                .history
+                .as_ref()?
+                .versions
+                .get(key)?
+                .get(n - 1)
+                .cloned(),
+        }
+    }
+
+    /// Replaces the live value under `key` with the one it replaced, returning false if there is none
+    ///
+    /// The restored value keeps the live entry's expiry, and is taken off
+    /// the history so that reverting again goes one version further back.
+    pub fn revert(&mut self, key: &str) -> bool {
+        if self.live_entry(key).is_none() {
+            return false;
+        }
+        let Some(previous) = self
+            .history
+            .as_mut()
+            .and_then(|history| history.versions.get_mut(key))
+            .and_then(VecDeque::pop_front)
+        else {
+            return false;
+        };
+        let now = self.now();
+        let version = self.next_version();
+        let Some(entry) = self.entries.get_mut(key) else {
+            return false;
+        };
+        self.indexes
+            .update(key, Some(&entry.value), Some(&previous));
+        entry.value = previous;
+        entry.updated_at = now;
+        entry.version = version;
+        let expiry = entry.expiry;
+        self.feed
+            .emit(CacheEventKind::Update, key, now, Some(expiry));
+        true
+    }
+}
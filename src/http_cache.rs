@@ -0,0 +1,243 @@
+//! Client-side HTTP caching for [`reqwest`] via [`reqwest_middleware`]
+//!
+//! [`HttpCacheMiddleware`] caches `GET` responses according to their
+//! `Cache-Control` header and revalidates stale entries with `If-None-Match` /
+//! `If-Modified-Since` when the origin supplied an `ETag` or `Last-Modified`
+//! validator. Responses are keyed by URL only, so responses carrying a `Vary`
+//! header are never stored.
+
+use std::time::{Duration, SystemTime};
+
+use bytes::Bytes;
+use http::Extensions;
+use reqwest::header::{
+    HeaderMap, CACHE_CONTROL, CONTENT_LENGTH, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+    LAST_MODIFIED, VARY,
+};
+use reqwest::{Method, Request, Response, ResponseBuilderExt, StatusCode, Url};
+use reqwest_middleware::{Middleware, Next, Result};
+
+use crate::SharedCache;
+
+/// A stored response together with its freshness lifetime
+#[derive(Debug, Clone)]
+pub struct CachedHttpResponse {
+    url: Url,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    fresh_until: SystemTime,
+}
+
+impl CachedHttpResponse {
+    /// The buffered response body
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// The stored response headers
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Whether the response can still be served without revalidation
+    pub fn is_fresh(&self) -> bool {
+        SystemTime::now() < self.fresh_until
+    }
+
+    fn has_validators(&self) -> bool {
+        self.headers.contains_key(ETAG) || self.headers.contains_key(LAST_MODIFIED)
+    }
+
+    fn to_response(&self) -> Response {
+        let mut builder = http::Response::builder()
+            .status(self.status)
+            .url(self.url.clone());
+        if let Some(headers) = builder.headers_mut() {
+            *headers = self.headers.clone();
+        }
+        builder
+            .body(self.body.clone())
+            .expect("cached status and headers are valid")
+            .into()
+    }
+}
+
+#[derive(Debug, Default)]
+struct CacheControl {
+    max_age: Option<Duration>,
+    no_cache: bool,
+    no_store: bool,
+}
+
+impl CacheControl {
+    fn parse(headers: &HeaderMap) -> Self {
+        let mut control = CacheControl::default();
+        let directives = headers
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        for directive in directives {
+            let directive = directive.trim().to_ascii_lowercase();
+            match directive.split_once('=') {
+                Some(("max-age", seconds)) => {
+                    control.max_age = seconds
+                        .trim_matches('"')
+                        .parse()
+                        .ok()
+                        .map(Duration::from_secs);
+                }
+                _ if directive == "no-cache" => control.no_cache = true,
+                _ if directive == "no-store" => control.no_store = true,
+                _ => {}
+            }
+        }
+        control
+    }
+
+    fn fresh_for(&self) -> Duration {
+        if self.no_cache {
+            Duration::ZERO
+        } else {
+            self.max_age.unwrap_or(Duration::ZERO)
+        }
+    }
+}
+
+/// A [`Middleware`] that serves fresh `GET` responses from a [`SharedCache`]
+///
+/// # Example
+///
+/// ```no_run
+/// use memory_cache::http_cache::HttpCacheMiddleware;
+/// use reqwest_middleware::ClientBuilder;
+///
+/// # async fn run() -> reqwest_middleware::Result<()> {
+/// let client = ClientBuilder::new(reqwest::Client::new())
+///     .with(HttpCacheMiddleware::new())
+///     .build();
+///
+/// // The second request is answered from the cache while the first response is fresh
+/// let first = client.get("https://api.example.com/config").send().await?.text().await?;
+/// let second = client.get("https://api.example.com/config").send().await?.text().await?;
+/// assert_eq!(first, second);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct HttpCacheMiddleware {
+    cache: SharedCache<CachedHttpResponse>,
+    revalidation_window: Duration,
+}
+
+impl HttpCacheMiddleware {
+    /// Creates a middleware backed by a new empty cache
+    ///
+    /// Stale responses with validators are kept for one hour past their
+    /// freshness lifetime so they can be revalidated.
+    pub fn new() -> Self {
+        Self::with_cache(SharedCache::new())
+    }
+
+    /// Creates a middleware backed by an existing cache
+    pub fn with_cache(cache: SharedCache<CachedHttpResponse>) -> Self {
+        HttpCacheMiddleware {
+            cache,
+            revalidation_window: Duration::from_secs(3600),
+        }
+    }
+
+    /// Sets how long stale responses with validators are kept for revalidation
+    pub fn revalidation_window(mut self, window: Duration) -> Self {
+        self.revalidation_window = window;
+        self
+    }
+
+    /// Returns a handle to the underlying cache, e.g. for manual invalidation
+    pub fn cache(&self) -> SharedCache<CachedHttpResponse> {
+        self.cache.clone()
+    }
+
+    fn store(&self, key: &str, mut entry: CachedHttpResponse) {
+        let control = CacheControl::parse(&entry.headers);
+        let fresh_for = control.fresh_for();
+        let retain_for = if entry.has_validators() {
+            fresh_for + self.revalidation_window
+        } else {
+            fresh_for
+        };
+
+        if control.no_store || retain_for.is_zero() {
+            self.cache.invalidate(key);
+            return;
+        }
+        entry.fresh_until = SystemTime::now() + fresh_for;
+        self.cache.insert(key, entry, retain_for);
+    }
+}
+
+impl Default for HttpCacheMiddleware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for HttpCacheMiddleware {
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        if req.method() != Method::GET {
+            return next.run(req, extensions).await;
+        }
+
+        let key = req.url().to_string();
+        let cached = self.cache.get(&key);
+        if let Some(cached) = &cached {
+            if cached.is_fresh() {
+                return Ok(cached.to_response());
+            }
+            if let Some(etag) = cached.headers.get(ETAG) {
+                req.headers_mut().insert(IF_NONE_MATCH, etag.clone());
+            }
+            if let Some(last_modified) = cached.headers.get(LAST_MODIFIED) {
+                req.headers_mut()
+                    .insert(IF_MODIFIED_SINCE, last_modified.clone());
+            }
+        }
+
+        let response = next.run(req, extensions).await?;
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(mut cached) = cached {
+                // A 304 carries updated metadata for the stored response
+                for (name, value) in response.headers() {
+                    if name != CONTENT_LENGTH {
+                        cached.headers.insert(name.clone(), value.clone());
+                    }
+                }
+                self.store(&key, cached.clone());
+                return Ok(cached.to_response());
+            }
+            return Ok(response);
+        }
+
+        if response.status() != StatusCode::OK || response.headers().contains_key(VARY) {
+            return Ok(response);
+        }
+
+        let entry = CachedHttpResponse {
+            url: response.url().clone(),
+            status: response.status(),
+            headers: response.headers().clone(),
+            body: response.bytes().await?,
+            fresh_until: SystemTime::now(),
+        };
+        self.store(&key, entry.clone());
+        Ok(entry.to_response())
+    }
+}
@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use crate::{Cache, SharedCache};
+
+/// The outcome of [`IdempotencyStore::begin`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdempotencyStatus<R> {
+    /// First time the key is seen; the caller must process the request and
+    /// then call [`IdempotencyStore::complete`] or [`IdempotencyStore::abandon`]
+    New,
+    /// Another caller is processing the same key right now
+    InFlight,
+    /// The request was already processed with this result
+    Completed(R),
+}
+
+#[derive(Debug, Clone)]
+enum Slot<R> {
+    InFlight,
+    Completed(R),
+}
+
+/// Deduplicates retried requests by idempotency key
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::{IdempotencyStatus, IdempotencyStore};
+/// let store = IdempotencyStore::new(Duration::from_secs(30), Duration::from_secs(3600));
+///
+/// assert_eq!(store.begin("payment-123"), IdempotencyStatus::New);
+/// assert_eq!(store.begin("payment-123"), IdempotencyStatus::InFlight);
+///
+/// store.complete("payment-123", "charged");
+/// assert_eq!(store.begin("payment-123"), IdempotencyStatus::Completed("charged"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct IdempotencyStore<R> {
+    cache: SharedCache<Slot<R>>,
+    in_flight_ttl: Duration,
+    result_ttl: Duration,
+}
+
+impl<R: Clone> IdempotencyStore<R> {
+    /// Creates a store that remembers results for `result_ttl`
+    ///
+    /// `in_flight_ttl` bounds how long a key stays claimed when the caller
+    /// that began it never completes, e.g. because it crashed.
+    pub fn new(in_flight_ttl: Duration, result_ttl: Duration) -> Self {
+        IdempotencyStore {
+            cache: SharedCache::from_cache(Cache::new().with_disabled(false)),
+            in_flight_ttl,
+            result_ttl,
+        }
+    }
+
+    /// Claims `key` for processing unless it is in flight or already completed
+    pub fn begin(&self, key: &str) -> IdempotencyStatus<R> {
+        let mut cache = self.cache.lock();
+        match cache.get(key) {
+            Some(Slot::InFlight) => IdempotencyStatus::InFlight,
+            Some(Slot::Completed(result)) => IdempotencyStatus::Completed(result),
+            None => {
+                cache.insert(key, Slot::InFlight, self.in_flight_ttl);
+                IdempotencyStatus::New
+            }
+        }
+    }
+
+    /// Records the result for `key`, replaying it to later callers
+    pub fn complete(&self, key: &str, result: R) {
+        self.cache
+            .insert(key, Slot::Completed(result), self.result_ttl);
+    }
+
+    /// Releases the claim on `key` without a result so the request can be retried
+    pub fn abandon(&self, key: &str) {
+        let mut cache = self.cache.lock();
+        if let Some(Slot::InFlight) = cache.get(key) {
+            cache.invalidate(key);
+        }
+    }
+}
@@ -0,0 +1,445 @@
+//! Bulk loading and dumping of entries in other formats
+//!
+//! Used by `memory_cache import-env` to bootstrap config and secret caches,
+//! e.g. in CI jobs, from the environment or a dotenv file, by `import`
+//! and `export` to round-trip cache contents through CSV, and by `warm` to
+//! fill missing keys from an origin.
+
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::Cache;
+
+/// Parses the `NAME=value` assignments of a dotenv file
+///
+/// Blank lines, `#` comments and a leading `export` are skipped. Values may be
+/// wrapped in single quotes, taken literally, or double quotes, which
+/// understand `\n`, `\"` and `\\`.
+///
+/// # Example
+///
+/// ```
+/// use memory_cache::import::parse_dotenv;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let vars = parse_dotenv("# build config\nexport MYAPP_MODE=ci\nMYAPP_GREETING=\"hello\\nworld\"\n")?;
+/// assert_eq!(vars[0], ("MYAPP_MODE".to_string(), "ci".to_string()));
+/// assert_eq!(vars[1].1, "hello\nworld");
+/// # Ok(())
+/// # }
+/// ```
+pub fn parse_dotenv(contents: &str) -> Result<Vec<(String, String)>> {
+    let mut vars = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((name, value)) = line.split_once('=') else {
+            bail!("line {}: expected NAME=value", index + 1);
+        };
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            bail!("line {}: invalid variable name '{}'", index + 1, name);
+        }
+        let value = unquote(value.trim()).map_err(|err| anyhow!("line {}: {}", index + 1, err))?;
+        vars.push((name.to_string(), value));
+    }
+    Ok(vars)
+}
+
+/// Inserts every variable whose name starts with `prefix`, keyed by the rest of the name
+///
+/// Returns the number of entries inserted, stopping at the first one the
+/// cache rejects.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::import::import_vars;
+/// use memory_cache::Cache;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let mut cache = Cache::new();
+/// let vars = [("MYAPP_DB_URL".to_string(), "postgres://db".to_string()), ("HOME".to_string(), "/root".to_string())];
+/// assert_eq!(import_vars(&mut cache, vars, "MYAPP_", Duration::from_secs(3600))?, 1);
+/// assert_eq!(cache.get("DB_URL").as_deref(), Some("postgres://db"));
+/// # Ok(())
+/// # }
+/// ```
+pub fn import_vars(
+    cache: &mut Cache<String>,
+    vars: impl IntoIterator<Item = (String, String)>,
+    prefix: &str,
+    ttl: Duration,
+) -> Result<usize> {
+    let mut imported = 0;
+    for (name, value) in vars {
+        match name.strip_prefix(prefix) {
+            Some(key) if !key.is_empty() => {
+                cache.try_insert(key, value, ttl)?;
+                imported += 1;
+            }
+            _ => {}
+        }
+    }
+    Ok(imported)
+}
+
+fn unquote(value: &str) -> Result<String, &'static str> {
+    if let Some(inner) = value.strip_prefix('\'') {
+        return match inner.strip_suffix('\'') {
+            Some(inner) => Ok(inner.to_string()),
+            None => Err("unterminated single quote"),
+        };
+    }
+    let Some(inner) = value.strip_prefix('"') else {
+        return Ok(value.to_string());
+    };
+
+    let mut unquoted = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if chars.as_str().is_empty() => return Ok(unquoted),
+            '"' => return Err("unexpected text after closing quote"),
+            '\\' => match chars.next() {
+                Some('n') => unquoted.push('\n'),
+                Some(escaped @ ('"' | '\\')) => unquoted.push(escaped),
+                Some(other) => {
+                    unquoted.push('\\');
+                    unquoted.push(other);
+                }
+                None => break,
+            },
+            c => unquoted.push(c),
+        }
+    }
+    Err("unterminated double quote")
+}
+
+/// How exported CSV records say when an entry expires
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ExpiryColumn {
+    /// A `ttl` column with the remaining lifetime in seconds
+    Remaining,
+    /// An `expires_at` column with the expiry in seconds since the Unix epoch
+    Absolute,
+}
+
+impl ExpiryColumn {
+    fn name(self) -> &'static str {
+        match self {
+            ExpiryColumn::Remaining => "ttl",
+            ExpiryColumn::Absolute => "expires_at",
+        }
+    }
+}
+
+/// Writes the live entries of `cache` as CSV, ordered by key, returning how many were written
+///
+/// The header row is `key,value,ttl` or `key,value,expires_at`; fields are
+/// quoted as spreadsheets expect.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::import::{export_csv, import_csv, ExpiryColumn};
+/// use memory_cache::Cache;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let mut cache = Cache::new();
+/// cache.insert("greeting", "hello, world".to_string(), Duration::from_secs(60));
+///
+/// let mut csv = Vec::new();
+/// export_csv(&cache, &mut csv, ExpiryColumn::Absolute)?;
+/// assert!(String::from_utf8(csv.clone())?.starts_with("key,value,expires_at\ngreeting,\"hello, world\","));
+///
+/// let mut copy = Cache::new();
+/// assert_eq!(import_csv(&mut copy, &csv[..])?.inserted, 1);
+/// assert_eq!(copy.get("greeting").as_deref(), Some("hello, world"));
+///
+/// // Entries superseded by an epoch bump are left out, as get leaves them
+/// cache.insert("tenant/acme/plan", "gold".to_string(), Duration::from_secs(60));
+/// cache.bump_epoch("tenant/acme");
+/// assert_eq!(export_csv(&cache, std::io::sink(), ExpiryColumn::Remaining)?, 1);
+/// # Ok(())
+/// # }
+/// ```
+pub fn export_csv(
+    cache: &Cache<String>,
+    writer: impl Write,
+    expiry: ExpiryColumn,
+) -> Result<usize> {
+    export_csv_with_progress(cache, writer, expiry, |_, _| {})
+}
+
+/// Like [`export_csv`], calling `progress` with the entries written so far and the total after each one
+pub fn export_csv_with_progress(
+    cache: &Cache<String>,
+    mut writer: impl Write,
+    expiry: ExpiryColumn,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<usize> {
+    let now = cache.now();
+    let mut live: Vec<_> = cache
+        .entries
+        .iter()
+        .filter(|(key, _)| cache.live_entry(key).is_some())
+        .collect();
+    live.sort_unstable_by_key(|(key, _)| *key);
+
+    writeln!(writer, "key,value,{}", expiry.name())?;
+    for (done, (key, entry)) in live.iter().enumerate() {
+        let when = match expiry {
+            ExpiryColumn::Remaining => entry.expiry - now,
+            ExpiryColumn::Absolute => entry.expiry,
+        };
+        writeln!(
+            writer,
+            "{},{},{}",
+            csv_field(key),
+            csv_field(&entry.value),
+            when
+        )?;
+        progress(done + 1, live.len());
+    }
+    writer.flush()?;
+    Ok(live.len())
+}
+
+/// What a bulk import or [`warm`] did with each record or key
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ImportSummary {
+    pub inserted: usize,
+    /// Records that had already expired, or keys that were already cached
+    pub skipped: usize,
+    /// Records that were malformed, keys that failed to load, and values rejected by the cache
+    pub failed: usize,
+    /// Why each failed record or key failed, in order
+    pub errors: Vec<String>,
+}
+
+/// Inserts the records of a CSV file written by [`export_csv`]
+///
+/// The header row names the columns: `key` and `value`, plus either `ttl` in
+/// seconds or `expires_at` in seconds since the Unix epoch; other columns are
+/// ignored. Records that have already expired are skipped, and a record that
+/// cannot be inserted is counted as failed without stopping the import.
+pub fn import_csv(cache: &mut Cache<String>, reader: impl Read) -> Result<ImportSummary> {
+    import_csv_with_progress(cache, reader, |_, _| {})
+}
+
+/// Like [`import_csv`], calling `progress` with the records done so far and the total after each one
+pub fn import_csv_with_progress(
+    cache: &mut Cache<String>,
+    mut reader: impl Read,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<ImportSummary> {
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+    let mut records = parse_csv(&contents)?.into_iter();
+    let Some(header) = records.next() else {
+        return Ok(ImportSummary::default());
+    };
+    let column = |name: &str| header.iter().position(|column| column.trim() == name);
+    let (Some(key), Some(value)) = (column("key"), column("value")) else {
+        bail!("CSV header must name 'key' and 'value' columns");
+    };
+    let (expiry, expiry_column) = match (column("ttl"), column("expires_at")) {
+        (Some(ttl), None) => (ttl, ExpiryColumn::Remaining),
+        (None, Some(expires_at)) => (expires_at, ExpiryColumn::Absolute),
+        _ => bail!("CSV header must name exactly one of 'ttl' and 'expires_at'"),
+    };
+
+    let now = cache.now();
+    let total = records.len();
+    let mut summary = ImportSummary::default();
+    for (index, record) in records.enumerate() {
+        // The header is line 1
+        let line = index + 2;
+        let imported = (|| -> Result<bool> {
+            let field = |column: usize| {
+                record.get(column).ok_or_else(|| {
+                    anyhow!("record {}: expected at least {} fields", line, column + 1)
+                })
+            };
+            let when: u64 = field(expiry)?.trim().parse().map_err(|_| {
+                anyhow!(
+                    "record {}: '{}' is not a number of seconds",
+                    line,
+                    record[expiry]
+                )
+            })?;
+            let ttl = match expiry_column {
+                ExpiryColumn::Remaining => when,
+                ExpiryColumn::Absolute if when > now => when - now,
+                ExpiryColumn::Absolute => return Ok(false),
+            };
+            let key = field(key)?;
+            cache
+                .try_insert(key, field(value)?.clone(), Duration::from_secs(ttl))
+                .map_err(|err| anyhow!("record {}: {}", line, err))?;
+            Ok(true)
+        })();
+        match imported {
+            Ok(true) => summary.inserted += 1,
+            Ok(false) => summary.skipped += 1,
+            Err(err) => {
+                summary.failed += 1;
+                summary.errors.push(err.to_string());
+            }
+        }
+        progress(index + 1, total);
+    }
+    Ok(summary)
+}
+
+/// Loads each of `keys` that has no live entry in `cache` with `loader`, running up to `jobs` loads at once
+///
+/// Keys that are already live, or listed more than once, are skipped, and
+/// a key whose load fails or whose value the cache rejects is counted as
+/// failed without stopping the others. Loads run on threads of their own;
+/// the cache is only written from the calling thread, as each one finishes.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use anyhow::bail;
+/// use memory_cache::import::warm;
+/// use memory_cache::Cache;
+///
+/// let mut cache = Cache::new();
+/// cache.insert("user/1", "cached".to_string(), Duration::from_secs(60));
+/// let keys = ["user/1", "user/2", "user/3"].map(String::from);
+/// let summary = warm(&mut cache, &keys, Duration::from_secs(60), 4, |key| {
+///     if key == "user/3" {
+///         bail!("origin is down");
+///     }
+///     Ok(format!("loaded {}", key))
+/// });
+/// assert_eq!((summary.inserted, summary.skipped, summary.failed), (1, 1, 1));
+/// assert_eq!(cache.get("user/2"), Some("loaded user/2".to_string()));
+/// ```
+pub fn warm<F>(
+    cache: &mut Cache<String>,
+    keys: &[String],
+    ttl: Duration,
+    jobs: usize,
+    loader: F,
+) -> ImportSummary
+where
+    F: Fn(&str) -> Result<String> + Sync,
+{
+    warm_with_progress(cache, keys, ttl, jobs, loader, |_, _| {})
+}
+
+/// Like [`warm`], calling `progress` with the keys done so far and the total after each one
+pub fn warm_with_progress<F>(
+    cache: &mut Cache<String>,
+    keys: &[String],
+    ttl: Duration,
+    jobs: usize,
+    loader: F,
+    mut progress: impl FnMut(usize, usize),
+) -> ImportSummary
+where
+    F: Fn(&str) -> Result<String> + Sync,
+{
+    let mut listed = HashSet::new();
+    let missing: Vec<&str> = keys
+        .iter()
+        .filter(|key| listed.insert(key.as_str()) && !cache.contains_key(key))
+        .map(String::as_str)
+        .collect();
+    let total = keys.len();
+    let mut summary = ImportSummary {
+        skipped: total - missing.len(),
+        ..ImportSummary::default()
+    };
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, missing.len().max(1)) {
+            let (sender, next, missing, loader) = (sender.clone(), &next, &missing, &loader);
+            scope.spawn(move || {
+                while let Some(&key) = missing.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    if sender.send((key, loader(key))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        for (done, (key, loaded)) in receiver.into_iter().enumerate() {
+            let stored = loaded.and_then(|value| {
+                cache
+                    .try_insert(key, value, ttl)
+                    .map_err(anyhow::Error::from)
+            });
+            match stored {
+                Ok(()) => summary.inserted += 1,
+                Err(err) => {
+                    summary.failed += 1;
+                    summary.errors.push(format!("{}: {}", key, err));
+                }
+            }
+            progress(summary.skipped + done + 1, total);
+        }
+    });
+    summary
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// Splits RFC 4180 CSV into records; quoted fields may hold commas, quotes and newlines
+fn parse_csv(contents: &str) -> Result<Vec<Vec<String>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = contents.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        bail!("unterminated quoted field");
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::value::RawValue;
+
+/// A value that is kept as JSON until it is first read
+///
+/// Loading a `Cache<Lazy<T>>` only checks that each value is well-formed
+/// JSON and keeps its text, so caches with many rarely read entries load
+/// faster and hold less. [`Lazy::get`] decodes a value on first use, and
+/// clones share the decoded value, so each entry is decoded at most once.
+/// Values that were never read are saved back as the text they were loaded
+/// from. Only JSON is supported.
+///
+/// # Example
+///
+/// ```
+/// use serde::Deserialize;
+/// use memory_cache::{Cache, Lazy};
+///
+/// #[derive(Deserialize)]
+/// struct Profile {
+///     name: String,
+/// }
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let state = r#"{"entries":{"user/1":{"value":{"name":"alice"},"expiry":9999999999}}}"#;
+/// let mut cache: Cache<Lazy<Profile>> = serde_json::from_str(state)?;
+///
+/// let profile = cache.get("user/1").unwrap();
+/// assert!(!profile.is_decoded());
+/// assert_eq!(profile.get()?.name, "alice");
+/// assert!(cache.get("user/1").unwrap().is_decoded());
+/// # Ok(())
+/// # }
+/// ```
+pub struct Lazy<T>(Arc<Inner<T>>);
+
+struct Inner<T> {
+    // None for values created with Lazy::new
+    raw: Option<Box<RawValue>>,
+    value: OnceLock<T>,
+}
+
+impl<T> Lazy<T> {
+    /// Wraps a value that is already decoded
+    pub fn new(value: T) -> Self {
+        Lazy(Arc::new(Inner {
+            raw: None,
+            value: OnceLock::from(value),
+        }))
+    }
+
+    /// Returns true if the value has been decoded
+    pub fn is_decoded(&self) -> bool {
+        self.0.value.get().is_some()
+    }
+}
+
+impl<T: DeserializeOwned> Lazy<T> {
+    /// Returns the value, decoding it first if this is its first use
+    ///
+    /// Fails if the JSON does not describe a `T`; the value then stays
+    /// undecoded and the next call tries again.
+    pub fn get(&self) -> Result<&T, serde_json::Error> {
+        if let Some(value) = self.0.value.get() {
+            return Ok(value);
+        }
+        // Values without raw text are created decoded
+        let raw = self.0.raw.as_deref().map_or("null", RawValue::get);
+        let value = serde_json::from_str(raw)?;
+        // Another clone may have decoded it meanwhile; either result is the same
+        Ok(self.0.value.get_or_init(|| value))
+    }
+}
+
+impl<T> Clone for Lazy<T> {
+    fn clone(&self) -> Self {
+        Lazy(self.0.clone())
+    }
+}
+
+impl<T> fmt::Debug for Lazy<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lazy")
+            .field("decoded", &self.is_decoded())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Serialize> Serialize for Lazy<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match (self.0.value.get(), &self.0.raw) {
+            (Some(value), _) => value.serialize(serializer),
+            (None, Some(raw)) => raw.serialize(serializer),
+            // Lazy::new always stores a value
+            (None, None) => serializer.serialize_unit(),
+        }
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Lazy<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = Box::<RawValue>::deserialize(deserializer)?;
+        Ok(Lazy(Arc::new(Inner {
+            raw: Some(raw),
+            value: OnceLock::new(),
+        })))
+    }
+}
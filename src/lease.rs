@@ -0,0 +1,145 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{Cache, SharedCache};
+
+/// Hands out exclusive, expiring leases on keys
+///
+/// A lease is held by storing its fencing token under the key with
+/// insert-if-absent semantics, so at most one holder exists until the lease
+/// is released or its TTL lapses. Fencing tokens increase with every
+/// successful acquisition, letting downstream systems reject writes from a
+/// holder whose lease has already expired. Clones of a manager share its
+/// leases and its sequence of fencing tokens.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::LeaseManager;
+/// let leases = LeaseManager::new();
+///
+/// let guard = leases.acquire("nightly-report", Duration::from_secs(30)).unwrap();
+/// assert!(leases.acquire("nightly-report", Duration::from_secs(30)).is_none());
+///
+/// // Long-running work keeps the lease alive by renewing it
+/// assert!(guard.renew(Duration::from_secs(30)));
+///
+/// let first_token = guard.token();
+/// guard.release();
+/// let guard = leases.acquire("nightly-report", Duration::from_secs(30)).unwrap();
+/// assert!(guard.token() > first_token);
+/// ```
+#[derive(Debug, Clone)]
+pub struct LeaseManager {
+    cache: SharedCache<u64>,
+    next_token: Arc<AtomicU64>,
+}
+
+impl LeaseManager {
+    /// Creates a lease manager with its own cache
+    pub fn new() -> Self {
+        Self::with_cache(SharedCache::from_cache(Cache::new().with_disabled(false)))
+    }
+
+    /// Creates a lease manager that stores leases in an existing cache
+    ///
+    /// No lease can be acquired while `cache` is disabled. The fencing
+    /// tokens are counted by the manager, so share one between threads by
+    /// cloning it rather than creating a second manager over the same cache.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::{LeaseManager, SharedCache};
+    /// let first = LeaseManager::with_cache(SharedCache::new());
+    /// let second = first.clone();
+    ///
+    /// let lease = first.acquire("compaction", Duration::from_secs(30)).unwrap();
+    /// let token = lease.token();
+    /// lease.release();
+    /// let lease = second.acquire("compaction", Duration::from_secs(30)).unwrap();
+    /// assert!(lease.token() > token);
+    /// ```
+    pub fn with_cache(cache: SharedCache<u64>) -> Self {
+        LeaseManager {
+            cache,
+            next_token: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    /// Acquires the lease on `key` for `ttl`, or returns `None` if it is held
+    pub fn acquire(&self, key: &str, ttl: Duration) -> Option<LeaseGuard> {
+        let mut cache = self.cache.lock();
+        // Drawn under the cache lock, so tokens are stored in the order issued
+        let token = self.next_token.load(Ordering::SeqCst);
+        if !cache.insert_if_absent(key, token, ttl) {
+            return None;
+        }
+        self.next_token.fetch_add(1, Ordering::SeqCst);
+        Some(LeaseGuard {
+            cache: self.cache.clone(),
+            key: key.to_string(),
+            token,
+        })
+    }
+}
+
+impl Default for LeaseManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A held lease, released when dropped
+#[derive(Debug)]
+pub struct LeaseGuard {
+    cache: SharedCache<u64>,
+    key: String,
+    token: u64,
+}
+
+impl LeaseGuard {
+    /// The key this lease is held on
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// The fencing token of this lease
+    pub fn token(&self) -> u64 {
+        self.token
+    }
+
+    /// Returns true if the lease has neither expired nor been taken over
+    pub fn is_held(&self) -> bool {
+        self.cache.lock().peek(&self.key) == Some(&self.token)
+    }
+
+    /// Extends the lease to expire `ttl` from now
+    ///
+    /// Returns false, without renewing, if the lease has already been lost.
+    pub fn renew(&self, ttl: Duration) -> bool {
+        let mut cache = self.cache.lock();
+        if cache.peek(&self.key) != Some(&self.token) {
+            return false;
+        }
+        cache.insert(&self.key, self.token, ttl);
+        true
+    }
+
+    /// Releases the lease so another caller can acquire it
+    pub fn release(self) {
+        // Dropping performs the release
+    }
+}
+
+impl Drop for LeaseGuard {
+    fn drop(&mut self) {
+        let mut cache = self.cache.lock();
+        if cache.peek(&self.key) == Some(&self.token) {
+            cache.invalidate(&self.key);
+        }
+    }
+}
@@ -1,144 +1,127 @@
-use std::collections::HashMap;
-use std::fs;
-use std::time::Duration;
-use serde::{Serialize, Deserialize};
-use anyhow::Result;
+#![cfg_attr(not(feature = "std"), no_std)]
 
-/// A key-value cache with automatic expiration
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CacheEntry<T> {
-    value: T,
-    expiry: u64,
-}
+extern crate alloc;
 
-/// An in-memory cache that automatically evicts entries after their TTL expires
-///
-/// # Example
-///
-/// ```
-/// use std::time::Duration;
-/// use memory_cache::Cache;
-/// let mut cache = Cache::new();
-///
-/// // Store a value with 30 second TTL
-/// cache.insert("api_key", "secret123", Duration::from_secs(30));
-///
-/// // Retrieve the value
-/// assert_eq!(cache.get("api_key"), Some("secret123"));
-///
-/// // Manually invalidate
-/// cache.invalidate("api_key");
-/// assert_eq!(cache.get("api_key"), None);
-/// ```
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Cache<T> {
-    entries: HashMap<String, CacheEntry<T>>,
-}
+mod adaptive;
+#[cfg(all(feature = "archive", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+mod archive;
+#[cfg(all(feature = "audit", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+mod audit;
+#[cfg(feature = "axum")]
+pub mod axum;
+#[cfg(all(feature = "persistence", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+mod blob;
+mod breaker;
+mod cache;
+mod clock;
+#[cfg(feature = "codec")]
+mod codec;
+mod dedup;
+mod dns;
+mod epoch;
+mod error;
+#[cfg(all(feature = "persistence", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+mod event_log;
+mod feed;
+mod history;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "reqwest")]
+pub mod http_cache;
+#[cfg(feature = "std")]
+mod idempotency;
+#[cfg(feature = "persistence")]
+pub mod import;
+#[cfg(feature = "persistence")]
+mod lazy;
+#[cfg(feature = "std")]
+mod lease;
+#[cfg(all(feature = "mmap", unix))]
+mod mapped;
+#[cfg(feature = "std")]
+mod memoize;
+#[cfg(feature = "tower")]
+pub mod middleware;
+#[cfg(all(feature = "persistence", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+mod persist;
+mod policy;
+#[cfg(all(feature = "proxy", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+pub mod proxy;
+mod ratelimit;
+#[cfg(feature = "std")]
+mod retry;
+mod rng;
+#[cfg(feature = "zeroize")]
+mod sensitive;
+#[cfg(feature = "persistence")]
+mod session;
+#[cfg(feature = "std")]
+mod shared;
+#[cfg(all(feature = "simulation", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+pub mod simulation;
+mod stats;
+#[cfg(feature = "std")]
+mod token;
+#[cfg(feature = "proptest")]
+pub mod testing;
+mod tuning;
+mod typed;
+mod version;
+mod view;
 
-impl<T: Clone> Cache<T> {
-    /// Creates a new empty cache
-    ///
-    /// # Example
-    ///
-    /// ```
-    /// use memory_cache::Cache;
-    /// let cache: Cache<String> = Cache::new();
-    /// ```
-    pub fn new() -> Self {
-        Cache {
-            entries: HashMap::new(),
-        }
-    }
-
-    /// Inserts a value into the cache with a specified TTL
-    ///
-    /// # Example
-    ///
-    /// ```
-    /// use std::time::Duration;
-    /// use memory_cache::Cache;
-    /// let mut cache = Cache::new();
-    /// cache.insert("session", "token123", Duration::from_secs(60));
-    /// ```
-    pub fn insert(&mut self, key: &str, value: T, ttl: Duration) {
-        // Calculate the absolute expiry timestamp
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-
-        self.entries.insert(
-            key.to_string(),
-            CacheEntry {
-                value,
-                expiry: now + ttl.as_secs()
-            }
-        );
-    }
-
-    /// Retrieves a value from the cache, returning None if expired or not found
-    ///
-    /// # Example
-    ///
-    /// ```
-    /// use std::time::Duration;
-    /// use memory_cache::Cache;
-    /// let mut cache = Cache::new();
-    /// cache.insert("user", "alice", Duration::from_secs(30));
-    ///
-    /// if let Some(user) = cache.get("user") {
-    ///     println!("Found user: {}", user);
-    /// }
-    /// ```
-    pub fn get(&mut self, key: &str) -> Option<T> {
-        if let Some(entry) = self.entries.get(key) {
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
-            if now < entry.expiry {
-                return Some(entry.value.clone());
-            }
-            self.invalidate(key);
-        }
-        None
-    }
-
-    /// Manually removes an entry from the cache
-    ///
-    /// # Example
-    ///
-    /// ```
-    /// use std::time::Duration;
-    /// use memory_cache::Cache;
-    /// let mut cache = Cache::new();
-    /// cache.insert("temp", "data", Duration::from_secs(60));
-    /// cache.invalidate("temp");
-    /// assert_eq!(cache.get("temp"), None);
-    /// ```
-    pub fn invalidate(&mut self, key: &str) {
-        self.entries.remove(key);
-    }
-}
-
-const CACHE_FILE: &str = "cache_state.json";
-
-pub fn load_cache() -> Result<Cache<String>> {
-    match fs::read_to_string(CACHE_FILE) {
-        Ok(contents) => {
-            let cache: Cache<String> = serde_json::from_str(&contents)?;
-            Ok(cache)
-        }
-        Err(_) => {
-            let cache = Cache::new();
-            save_cache(&cache)?;
-            Ok(cache)
-        }
-    }
-}
-
-pub fn save_cache(cache: &Cache<String>) -> Result<()> {
-    let serialized = serde_json::to_string(cache)?;
-    fs::write(CACHE_FILE, serialized)?;
-    Ok(())
-}
\ No newline at end of file
+#[cfg(all(feature = "archive", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+pub use archive::{pack_cache, unpack_cache, unpack_cache_into, ArchiveHeader, Compression};
+#[cfg(all(feature = "audit", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+pub use audit::{append_audit, append_audit_to, tail_audit, tail_audit_from, AuditOp, AuditRecord};
+#[cfg(all(feature = "persistence", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+pub use blob::{BlobStore, DEFAULT_BLOB_THRESHOLD};
+pub use breaker::{BreakerState, CircuitBreaker};
+pub use cache::{Cache, CacheEntry, Collection, Counter, DefaultHashBuilder, FieldMap, Fields, ValueIndexer, KEY_SEPARATOR};
+pub use clock::Clock;
+#[cfg(feature = "cbor")]
+pub use codec::CborCodec;
+#[cfg(feature = "codec")]
+pub use codec::{Codec, CodecCache, Encoded, JsonCodec, RawCodec};
+pub use dedup::DedupCache;
+pub use dns::{DnsAnswer, DnsCache, Resolver};
+pub use error::{CacheError, RejectReason};
+#[cfg(all(feature = "persistence", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+pub use event_log::{EventLevel, EventLog};
+pub use feed::{CacheEvent, CacheEventKind};
+#[cfg(feature = "std")]
+pub use idempotency::{IdempotencyStatus, IdempotencyStore};
+#[cfg(feature = "persistence")]
+pub use lazy::Lazy;
+#[cfg(feature = "std")]
+pub use lease::{LeaseGuard, LeaseManager};
+#[cfg(all(feature = "mmap", unix))]
+pub use mapped::MappedState;
+#[cfg(feature = "std")]
+pub use memoize::memoize;
+#[cfg(all(feature = "persistence", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+pub use persist::{
+    is_transient, load_cache, load_cache_from, load_cache_from_slice, load_cache_with_report,
+    load_cache_with_report_from, load_shared_with_budget, load_shared_with_budget_from, save_cache, save_cache_to,
+    save_cache_with_history, save_cache_with_history_to, save_cache_with_report, save_cache_with_report_to, undo_save,
+    undo_save_at, LoadBudget, LoadPriority, PersistReport, CACHE_FILE, HISTORY_LEN, MAX_STATE_SIZE,
+};
+pub use policy::{ExpiryHook, InsertHook, Weigh};
+pub use ratelimit::{Decision, RateLimiter};
+#[cfg(feature = "std")]
+pub use retry::RetryPolicy;
+// For the memory_cache binary's bench command
+#[doc(hidden)]
+pub use rng::Rng;
+#[cfg(feature = "zeroize")]
+pub use sensitive::SensitiveCache;
+#[cfg(feature = "persistence")]
+pub use session::SessionStore;
+#[cfg(feature = "std")]
+pub use shared::{ScopeGuard, SharedCache};
+pub use stats::CacheStats;
+#[cfg(feature = "std")]
+pub use token::{Token, TokenCache};
+pub use tuning::{PatternStats, Recommendation, TuningReport};
+pub use typed::TypedCache;
+pub use version::Modified;
+pub use view::{MapView, MapViewIter};
@@ -1,15 +1,108 @@
-use std::time::Duration;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, IsTerminal, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use anyhow::{anyhow, bail, Result};
+use clap::{ArgEnum, Parser, Subcommand};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::de::{IgnoredAny, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
 
-use memory_cache::{load_cache, save_cache};
+use memory_cache::import::{
+    export_csv_with_progress, import_csv_with_progress, import_vars, parse_dotenv,
+    warm_with_progress, ExpiryColumn,
+};
+use memory_cache::proxy::Proxy;
+use memory_cache::simulation::Script;
+#[cfg(unix)]
+use memory_cache::MappedState;
+use memory_cache::{
+    append_audit, is_transient, load_cache, load_cache_from_slice, pack_cache, save_cache,
+    save_cache_with_history, tail_audit, undo_save, unpack_cache_into, AuditOp, AuditRecord,
+    BlobStore, Cache, Decision, EventLevel, EventLog, RateLimiter, RetryPolicy, Rng, CACHE_FILE,
+    KEY_SEPARATOR,
+};
 
 #[derive(Debug, Parser)]
 #[clap(author, version, about="Tis a tool for caching", long_about = None)]
 struct Cli {
     #[clap(subcommand)]
     command: Commands,
+
+    /// Appends a record of every operation to cache_audit.jsonl
+    #[clap(long, global = true, env = "MEMORY_CACHE_AUDIT")]
+    audit: bool,
+
+    /// Includes the SHA-256 of inserted values in audit records
+    #[clap(long, global = true, env = "MEMORY_CACHE_AUDIT_HASH_VALUES")]
+    audit_hash_values: bool,
+
+    /// Who to record in the audit log [default: $USER]
+    #[clap(long, global = true, env = "MEMORY_CACHE_ACTOR")]
+    actor: Option<String>,
+
+    /// Appends every insert, update, expiry and removal to this JSON Lines file, rotated at 10 MiB
+    #[clap(
+        long,
+        global = true,
+        value_name = "PATH",
+        env = "MEMORY_CACHE_EVENT_LOG"
+    )]
+    event_log: Option<PathBuf>,
+
+    /// Logs only loader panics with warn, removals as well with info, and writes too with debug
+    #[clap(
+        long,
+        global = true,
+        arg_enum,
+        value_name = "LEVEL",
+        default_value = "debug",
+        requires = "event-log"
+    )]
+    event_log_level: EventLogLevel,
+
+    /// Reports what a command would change without saving the cache or writing the audit log
+    #[clap(long, global = true)]
+    dry_run: bool,
+
+    /// Retries loading and saving the state file this many times on transient I/O errors, e.g. on a network filesystem
+    #[clap(
+        long,
+        global = true,
+        value_name = "N",
+        env = "MEMORY_CACHE_IO_RETRIES",
+        default_value = "0"
+    )]
+    io_retries: u32,
+
+    /// Refuses to insert values larger than this many bytes
+    #[clap(long, global = true, env = "MEMORY_CACHE_MAX_VALUE_SIZE")]
+    max_value_size: Option<usize>,
+
+    /// Logs more to stderr: -v for info, -vv for debug, -vvv for trace [default: warnings, or $RUST_LOG]
+    ///
+    /// Goes before the subcommand, whose -v is usually the value.
+    #[clap(short, long, parse(from_occurrences), conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Logs only errors; goes before the subcommand
+    #[clap(short, long)]
+    quiet: bool,
+
+    /// Colors tables; auto colors them on a terminal unless NO_COLOR is set
+    #[clap(
+        long,
+        global = true,
+        arg_enum,
+        value_name = "WHEN",
+        default_value = "auto"
+    )]
+    color: ColorWhen,
 }
 #[derive(Debug, Subcommand)]
 enum Commands {
@@ -18,44 +111,1891 @@ enum Commands {
         #[clap(short, long)]
         key: String,
 
-        #[clap(short, long)]
-        value: String,
+        #[clap(short, long, required_unless_present = "file")]
+        value: Option<String>,
+
+        /// Streams the value from a file; large values are kept in cache_blobs/ instead of the state file
+        #[clap(long, conflicts_with = "value")]
+        file: Option<PathBuf>,
 
         #[clap(short, long, default_value = "30")]
         ttl: u64,
+
+        /// Expires the entry at an RFC 3339 time instead of after a TTL, e.g. 2025-01-01T00:00:00Z
+        #[clap(long, value_name = "TIME", parse(try_from_str = parse_timestamp), conflicts_with = "ttl")]
+        expire_at: Option<SystemTime>,
     },
     Get {
         #[clap(short, long)]
         key: String,
+
+        /// Reads the key from a memory-mapped state file without loading or saving the cache (Unix only)
+        #[clap(long)]
+        mmap: bool,
     },
     Invalidate {
+        #[clap(short, long, required_unless_present_any = &["pattern", "older-than"])]
+        key: Option<String>,
+
+        /// Also removes every key below KEY, e.g. user/42/prefs for user/42
+        #[clap(long)]
+        subtree: bool,
+
+        /// Removes every key matching a glob instead, e.g. 'session/*'
+        #[clap(long, conflicts_with_all = &["key", "subtree"])]
+        pattern: Option<String>,
+
+        /// Removes every key last written more than this long ago instead, e.g. 1d
+        #[clap(long, value_name = "AGE", parse(try_from_str = parse_ttl), conflicts_with_all = &["key", "subtree", "pattern"])]
+        older_than: Option<Duration>,
+    },
+    #[clap(about = "removes every entry", long_about = None)]
+    Clear,
+    #[clap(about = "removes expired entries and unreferenced blob files", long_about = None)]
+    Prune,
+    #[clap(about = "makes the entry under a key never expire", long_about = None)]
+    Persist {
+        #[clap(short, long)]
+        key: String,
+    },
+    #[clap(about = "restores the state from before the last change, keeping up to 10 earlier states", long_about = None)]
+    Undo,
+    #[clap(about = "opens a value in $EDITOR and stores the result", long_about = None)]
+    Edit {
+        #[clap(short, long)]
+        key: String,
+
+        /// Replaces the remaining TTL, e.g. 90, 90s, 5m, 1h or 2d
+        #[clap(short, long, value_parser = parse_ttl)]
+        ttl: Option<Duration>,
+
+        /// Refuses to store the edited value unless it is valid JSON
+        #[clap(long)]
+        json: bool,
+    },
+    #[clap(about = "lists the keys below a prefix of /-separated keys", long_about = None)]
+    List {
+        #[clap(short, long, default_value = "")]
+        prefix: String,
+
+        /// Prints the whole subtree instead of only the direct children
+        #[clap(long)]
+        tree: bool,
+
+        /// Prints a table with the TTL, size and number of children of each child
+        #[clap(short, long, conflicts_with = "tree")]
+        long: bool,
+
+        /// Lists the keys below the prefix that expire within this long, e.g. 5m, soonest first
+        #[clap(long, value_name = "TTL", parse(try_from_str = parse_ttl), conflicts_with_all = &["tree", "long"])]
+        expiring_within: Option<Duration>,
+
+        /// Lists the keys below the prefix last written more than this long ago, e.g. 1d, oldest first
+        #[clap(long, value_name = "AGE", parse(try_from_str = parse_ttl), conflicts_with_all = &["tree", "long", "expiring-within"])]
+        older_than: Option<Duration>,
+
+        /// Lists the keys below the prefix not read for more than this long, e.g. 1h, most idle first
+        #[clap(long, value_name = "IDLE", parse(try_from_str = parse_ttl), conflicts_with_all = &["tree", "long", "expiring-within", "older-than"])]
+        idle_over: Option<Duration>,
+    },
+    #[clap(about = "prints a table of entry counts and sizes", long_about = None)]
+    Stats,
+    #[clap(about = "prints the TTLs and sizes of live entries picked at random", long_about = None)]
+    Sample {
+        /// How many entries to pick
+        #[clap(short = 'n', long, default_value = "10")]
+        count: usize,
+    },
+    #[clap(about = "recommends TTL changes per key pattern from the hits and misses recorded so far", long_about = None)]
+    Analyze,
+    #[clap(about = "pushes a value onto the front of the list stored under a key", long_about = None)]
+    Lpush {
+        #[clap(short, long)]
+        key: String,
+
+        #[clap(short, long)]
+        value: String,
+
+        #[clap(short, long, default_value = "30")]
+        ttl: u64,
+    },
+    #[clap(about = "pops the value at the back of the list stored under a key", long_about = None)]
+    Rpop {
         #[clap(short, long)]
         key: String,
     },
+    #[clap(about = "adds a member to the set stored under a key", long_about = None)]
+    Sadd {
+        #[clap(short, long)]
+        key: String,
+
+        #[clap(short, long)]
+        member: String,
+
+        #[clap(short, long, default_value = "30")]
+        ttl: u64,
+    },
+    #[clap(about = "removes a member from the set stored under a key", long_about = None)]
+    Srem {
+        #[clap(short, long)]
+        key: String,
+
+        #[clap(short, long)]
+        member: String,
+    },
+    #[clap(about = "lists the members of the set or list stored under a key", long_about = None)]
+    Smembers {
+        #[clap(short, long)]
+        key: String,
+    },
+    #[clap(about = "sets a field of the map stored under a key, with its own TTL", long_about = None)]
+    Hset {
+        #[clap(short, long)]
+        key: String,
+
+        #[clap(short, long)]
+        field: String,
+
+        #[clap(short, long)]
+        value: String,
+
+        #[clap(short, long, default_value = "30")]
+        ttl: u64,
+    },
+    #[clap(about = "gets a field of the map stored under a key", long_about = None)]
+    Hget {
+        #[clap(short, long)]
+        key: String,
+
+        #[clap(short, long)]
+        field: String,
+    },
+    #[clap(about = "removes a field from the map stored under a key", long_about = None)]
+    Hdel {
+        #[clap(short, long)]
+        key: String,
+
+        #[clap(short, long)]
+        field: String,
+    },
+    #[clap(about = "writes the cache to a portable .mcache archive", long_about = None)]
+    Pack {
+        #[clap(short, long, default_value = "cache.mcache")]
+        output: PathBuf,
+    },
+    #[clap(about = "replaces the cache with the contents of a .mcache archive", long_about = None)]
+    Unpack {
+        #[clap(short, long)]
+        input: PathBuf,
+    },
+    #[clap(about = "inserts environment variables, or those in a dotenv file, as entries", long_about = None)]
+    ImportEnv {
+        /// Imports only variables starting with PREFIX, keyed by the rest of the name
+        #[clap(short, long, default_value = "")]
+        prefix: String,
+
+        /// How long the entries live, e.g. 90, 90s, 5m, 1h or 2d
+        #[clap(short, long, default_value = "30s", value_parser = parse_ttl)]
+        ttl: Duration,
+
+        /// Reads the variables from a dotenv file instead of the environment
+        #[clap(short, long)]
+        file: Option<PathBuf>,
+    },
+    #[clap(about = "inserts the records of a file exported with `export`", long_about = None)]
+    Import {
+        #[clap(long, arg_enum, default_value = "csv")]
+        format: Format,
+
+        #[clap(short, long)]
+        input: PathBuf,
+    },
+    #[clap(about = "writes the live entries to stdout or a file", long_about = None)]
+    Export {
+        #[clap(long, arg_enum, default_value = "csv")]
+        format: Format,
+
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+
+        /// Writes an expires_at column (seconds since the Unix epoch) instead of the remaining ttl
+        #[clap(long)]
+        absolute: bool,
+    },
+    #[clap(about = "loads the listed keys that are missing from the cache by running a command for each", long_about = None)]
+    Warm {
+        /// A file of keys, one per line, or - for stdin
+        #[clap(short, long)]
+        input: PathBuf,
+
+        /// How long the loaded entries live, e.g. 90, 90s, 5m, 1h or 2d
+        #[clap(short, long, default_value = "30s", value_parser = parse_ttl)]
+        ttl: Duration,
+
+        /// How many loader commands run at once
+        #[clap(short, long, default_value = "1", parse(try_from_str = parse_jobs))]
+        jobs: usize,
+
+        /// The loader, whose output becomes the value; {} in an argument is replaced by the key, which is otherwise passed last
+        #[clap(last = true, required = true)]
+        loader: Vec<String>,
+    },
+    #[clap(about = "sliding-window rate limiting backed by the cache", long_about = None)]
+    Ratelimit {
+        #[clap(subcommand)]
+        command: RatelimitCommands,
+    },
+    #[clap(about = "replays a scripted run with a simulated clock, exiting with 1 if an expectation fails", long_about = None)]
+    Simulate {
+        #[clap(short, long)]
+        script: PathBuf,
+    },
+    #[clap(about = "checks the state file for problems, exiting with 1 if any remain", long_about = None)]
+    Doctor {
+        /// Rewrites the state file without expired entries, duplicate keys or unknown fields
+        #[clap(long)]
+        fix: bool,
+    },
+    #[clap(about = "measures throughput and latency of the library under a synthetic workload", long_about = None)]
+    Bench {
+        /// Operations to run; K, M and G suffixes multiply by a thousand, million and billion
+        #[clap(long, default_value = "1M", parse(try_from_str = parse_count))]
+        ops: u64,
+
+        /// Fraction of operations that are gets rather than inserts
+        #[clap(long, default_value = "0.9", parse(try_from_str = parse_ratio))]
+        read_ratio: f64,
+
+        /// Size of each inserted value in bytes
+        #[clap(long, default_value = "256")]
+        value_size: usize,
+
+        /// Distinct keys the operations are spread over
+        #[clap(long, default_value = "10K", parse(try_from_str = parse_count))]
+        keys: u64,
+
+        /// Seeds the workload so runs can be repeated exactly [default: random]
+        #[clap(long)]
+        seed: Option<u64>,
+    },
+    #[clap(about = "serves a caching forward proxy to an http:// upstream, never touching the state file", long_about = None)]
+    Proxy {
+        /// The server to forward to, e.g. http://localhost:3000/api
+        #[clap(long)]
+        upstream: String,
+
+        /// Address to listen on
+        #[clap(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+
+        /// Caches every successful GET for this long instead of as the response headers say, e.g. 5m
+        #[clap(long, value_parser = parse_ttl)]
+        ttl: Option<Duration>,
+
+        /// How long to cache responses whose headers say nothing about freshness
+        #[clap(long, default_value = "0", value_parser = parse_ttl)]
+        default_ttl: Duration,
+    },
+    #[clap(about = "reads the audit log", long_about = None)]
+    Audit {
+        #[clap(subcommand)]
+        command: AuditCommands,
+    },
+}
+impl Commands {
+    // Changes worth a history snapshot; reads, counters and exports are not
+    fn is_undoable(&self) -> bool {
+        matches!(
+            self,
+            Commands::Insert { .. }
+                | Commands::Invalidate { .. }
+                | Commands::Clear
+                | Commands::Prune
+                | Commands::Persist { .. }
+                | Commands::Edit { .. }
+                | Commands::Lpush { .. }
+                | Commands::Rpop { .. }
+                | Commands::Sadd { .. }
+                | Commands::Srem { .. }
+                | Commands::Hset { .. }
+                | Commands::Hdel { .. }
+                | Commands::Unpack { .. }
+                | Commands::ImportEnv { .. }
+                | Commands::Import { .. }
+                | Commands::Warm { .. }
+        )
+    }
+}
+#[derive(Debug, Clone, Copy, ArgEnum)]
+enum EventLogLevel {
+    Debug,
+    Info,
+    Warn,
+}
+#[derive(Debug, Clone, Copy, ArgEnum)]
+enum Format {
+    Csv,
+}
+#[derive(Debug, Clone, Copy, ArgEnum)]
+enum ColorWhen {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorWhen {
+    // An explicit --color wins over NO_COLOR, as https://no-color.org asks
+    fn enabled(self) -> bool {
+        match self {
+            ColorWhen::Always => true,
+            ColorWhen::Never => false,
+            ColorWhen::Auto => {
+                io::stdout().is_terminal()
+                    && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+        }
+    }
+}
+#[derive(Debug, Subcommand)]
+enum AuditCommands {
+    #[clap(about = "prints the most recent audit records", long_about = None)]
+    Tail {
+        #[clap(short = 'n', long, default_value = "10")]
+        lines: usize,
+    },
+}
+#[derive(Debug, Subcommand)]
+enum RatelimitCommands {
+    #[clap(about = "counts a request for the key, exiting with 1 if it is over the limit", long_about = None)]
+    Check {
+        #[clap(short, long)]
+        key: String,
+
+        #[clap(short, long)]
+        limit: u64,
+
+        #[clap(short, long, default_value = "60")]
+        window: u64,
+    },
 }
 
 //TODO - discuss original plans for the tool.
 fn main() -> Result<()> {
     let cli = Cli::parse();
-    let mut cache = load_cache().unwrap();
+    init_logging(&cli);
+    // Simulations never touch the real state file
+    if let Commands::Simulate { script } = &cli.command {
+        let script = Script::from_yaml(&fs::read_to_string(script)?)?;
+        let failures = script.run(&mut std::io::stdout())?;
+        if failures > 0 {
+            println!("{} expectation(s) failed", failures);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    // Benchmarks run against a fresh in-memory cache, never the state file
+    if let Commands::Bench {
+        ops,
+        read_ratio,
+        value_size,
+        keys,
+        seed,
+    } = cli.command
+    {
+        let seed = match seed {
+            Some(seed) => seed,
+            None => {
+                let mut bytes = [0u8; 8];
+                getrandom::fill(&mut bytes).map_err(|err| anyhow!("no randomness: {}", err))?;
+                u64::from_le_bytes(bytes)
+            }
+        };
+        run_bench(
+            ops,
+            read_ratio,
+            value_size,
+            keys.max(1),
+            seed,
+            cli.color.enabled(),
+        );
+        return Ok(());
+    }
+    // The proxy keeps its own cache in memory
+    if let Commands::Proxy {
+        upstream,
+        listen,
+        ttl,
+        default_ttl,
+    } = &cli.command
+    {
+        let mut proxy = Proxy::new(upstream)?.with_default_ttl(*default_ttl);
+        if let Some(ttl) = ttl {
+            proxy = proxy.with_ttl(*ttl);
+        }
+        let listener = TcpListener::bind(listen)
+            .map_err(|err| anyhow!("cannot listen on {}: {}", listen, err))?;
+        println!("Proxying http://{} to {}", listener.local_addr()?, upstream);
+        return proxy.serve(listener);
+    }
+    if let Commands::Audit {
+        command: AuditCommands::Tail { lines },
+    } = &cli.command
+    {
+        for record in tail_audit(*lines)? {
+            println!("{}", record);
+        }
+        return Ok(());
+    }
 
-    match cli.command {
-        Commands::Insert { key, value, ttl } => {
-            cache.insert(&key, value, Duration::from_secs(ttl));
-            println!("Inserted key '{}'", key);
+    let actor = cli
+        .actor
+        .clone()
+        .or_else(|| std::env::var("USER").ok())
+        .unwrap_or_else(|| "unknown".to_string());
+    let color = cli.color.enabled();
+    // Undo swaps state files, so it never loads the cache
+    if let Commands::Undo = cli.command {
+        if cli.dry_run {
+            println!("Dry run: would restore the state from before the last change");
+        } else if undo_save()? {
+            if cli.audit {
+                append_audit(&AuditRecord::new(&actor, AuditOp::Undo, ""))?;
+            }
+            println!("Restored the state from before the last change");
+        } else {
+            println!("Nothing to undo");
+        }
+        return Ok(());
+    }
+    let started = Instant::now();
+    // The state file may not load, so the doctor reads it itself
+    if let Commands::Doctor { fix } = cli.command {
+        let healthy = doctor(fix && !cli.dry_run)?;
+        if healthy {
+            if fix && cli.audit && !cli.dry_run {
+                append_audit(&AuditRecord::new(&actor, AuditOp::Doctor, CACHE_FILE))?;
+            }
+        } else {
+            std::process::exit(1);
         }
-        Commands::Get { key } => {
-            match cache.get(&key) {
-                Some(value) => println!("Value for key '{}': {}", key, value),
+        return Ok(());
+    }
+    // A mapped get skips parsing everything else in the state file
+    #[cfg(unix)]
+    if let Commands::Get { key, mmap: true } = &cli.command {
+        // Saves write the state file in place, so this relies on none
+        // happening during the lookup, as with any short-lived read
+        let state = unsafe { MappedState::open(CACHE_FILE)? };
+        let value = state.get(key)?;
+        log::debug!("looked up the mapped state in {:?}", started.elapsed());
+        match &value {
+            Some(value) => {
+                print!("Value for key '{}': ", key);
+                BlobStore::default().write_value(key, value, &mut io::stdout())?;
+                println!();
+            }
+            None => println!("No value found for key '{}'", key),
+        }
+        if cli.audit {
+            append_audit(&AuditRecord::new(&actor, AuditOp::Get, key).with_hit(value.is_some()))?;
+        }
+        return Ok(());
+    }
+    let io_retry = RetryPolicy::new(cli.io_retries.saturating_add(1));
+    let mut load_retries = 0;
+    let mut cache = io_retry
+        .retry_counting(&mut load_retries, load_cache, is_transient)?
+        .with_key_index()
+        .with_tuning_stats()
+        .with_stats();
+    cache.record_retries(load_retries);
+    log::debug!("loaded the cache in {:?}", started.elapsed());
+    if let Some(max) = cli.max_value_size {
+        cache = cache.with_max_value_size(max);
+    }
+    // Dry runs change nothing worth logging
+    if let (Some(path), false) = (&cli.event_log, cli.dry_run) {
+        let level = match cli.event_log_level {
+            EventLogLevel::Debug => EventLevel::Debug,
+            EventLogLevel::Info => EventLevel::Info,
+            EventLogLevel::Warn => EventLevel::Warn,
+        };
+        cache = cache.with_event_log(EventLog::open(path)?.with_level(level));
+    }
+    let blobs = BlobStore::default();
+    let before = if cli.dry_run {
+        Some(snapshot(&cache)?)
+    } else {
+        None
+    };
+    let undoable = cli.command.is_undoable();
+    let mut limited = false;
+
+    let record = match cli.command {
+        Commands::Insert {
+            key,
+            value,
+            file,
+            ttl,
+            expire_at,
+        } => {
+            let ttl = match expire_at {
+                // Rounded up, so the entry lasts until `at` rather than expiring early
+                Some(at) => match at.duration_since(SystemTime::now()) {
+                    Ok(left) if !left.is_zero() => left
+                        .as_secs()
+                        .saturating_add(u64::from(left.subsec_nanos() > 0)),
+                    _ => bail!("--expire-at time has already passed"),
+                },
+                None => ttl,
+            };
+            let mut record = AuditRecord::new(&actor, AuditOp::Insert, &key).with_ttl(ttl);
+            match (value, file) {
+                (Some(value), _) => {
+                    if cli.audit_hash_values {
+                        record = record.with_value_hash(&value);
+                    }
+                    cache.try_insert(&key, value, Duration::from_secs(ttl))?;
+                }
+                // A dry run writes no blob file, so it stands in for the contents
+                (None, Some(file)) if cli.dry_run => {
+                    File::open(&file)?;
+                    let placeholder = format!("(contents of {})", file.display());
+                    cache.try_insert(&key, placeholder, Duration::from_secs(ttl))?;
+                }
+                (None, Some(file)) => {
+                    blobs.insert_reader(
+                        &mut cache,
+                        &key,
+                        File::open(file)?,
+                        Duration::from_secs(ttl),
+                    )?;
+                }
+                // clap requires one of the two
+                (None, None) => unreachable!(),
+            }
+            done(cli.dry_run, format_args!("Inserted key '{}'", key));
+            record
+        }
+        Commands::Get { key, .. } => {
+            // One read, so the stats count one lookup
+            let value = cache.get(&key);
+            let hit = value.is_some();
+            match value {
+                Some(value) => {
+                    // Blob values stream straight from their file
+                    print!("Value for key '{}': ", key);
+                    blobs.write_value(&key, &value, &mut io::stdout())?;
+                    println!();
+                }
                 None => println!("No value found for key '{}'", key),
             }
+            AuditRecord::new(&actor, AuditOp::Get, &key).with_hit(hit)
+        }
+        Commands::Invalidate {
+            key,
+            subtree,
+            pattern,
+            older_than,
+        } => match (key, pattern, older_than) {
+            (_, _, Some(age)) => {
+                let removed = cache.invalidate_older_than(age);
+                done(
+                    cli.dry_run,
+                    format_args!(
+                        "Invalidated {} key(s) last written over {}s ago",
+                        removed,
+                        age.as_secs()
+                    ),
+                );
+                let target = format!("older than {}s", age.as_secs());
+                AuditRecord::new(&actor, AuditOp::Invalidate, &target)
+            }
+            (_, Some(pattern), None) => {
+                let removed = cache.invalidate_matching(&pattern);
+                done(
+                    cli.dry_run,
+                    format_args!("Invalidated {} key(s) matching '{}'", removed, pattern),
+                );
+                AuditRecord::new(&actor, AuditOp::Invalidate, &pattern)
+            }
+            (Some(key), None, None) => {
+                if subtree {
+                    let removed = cache.invalidate_subtree(&key);
+                    done(
+                        cli.dry_run,
+                        format_args!("Invalidated {} key(s) under '{}'", removed, key),
+                    );
+                } else {
+                    cache.invalidate(&key);
+                    done(cli.dry_run, format_args!("Invalidated key '{}'", key));
+                }
+                AuditRecord::new(&actor, AuditOp::Invalidate, &key)
+            }
+            // clap requires one of the three
+            (None, None, None) => unreachable!(),
+        },
+        Commands::Clear => {
+            cache.clear();
+            done(cli.dry_run, format_args!("Cleared the cache"));
+            AuditRecord::new(&actor, AuditOp::Clear, "")
+        }
+        Commands::Prune => {
+            let removed = cache.prune_expired();
+            done(
+                cli.dry_run,
+                format_args!("Pruned {} expired entries", removed),
+            );
+            AuditRecord::new(&actor, AuditOp::Prune, "")
+        }
+        Commands::Persist { key } => {
+            let found = cache.persist(&key);
+            if found {
+                done(cli.dry_run, format_args!("Key '{}' no longer expires", key));
+            } else {
+                println!("No value found for key '{}'", key);
+            }
+            AuditRecord::new(&actor, AuditOp::Persist, &key).with_hit(found)
+        }
+        Commands::Edit { key, ttl, json } => {
+            let (Some(value), Some(remaining)) = (cache.get(&key), cache.ttl(&key)) else {
+                bail!("no value found for key '{}'", key);
+            };
+            let ttl = ttl.unwrap_or(remaining);
+            let edited = edit_in_editor(&key, &value)?;
+            if json {
+                serde_json::from_str::<serde_json::Value>(&edited).map_err(|err| {
+                    anyhow!("edited value is not valid JSON, nothing stored: {}", err)
+                })?;
+            }
+            let mut record = AuditRecord::new(&actor, AuditOp::Edit, &key).with_ttl(ttl.as_secs());
+            if cli.audit_hash_values {
+                record = record.with_value_hash(&edited);
+            }
+            if edited == value && ttl == remaining {
+                println!("No changes to key '{}'", key);
+            } else {
+                cache.try_insert(&key, edited, ttl)?;
+                done(
+                    cli.dry_run,
+                    format_args!("Updated key '{}' (expires in {})", key, format_ttl(ttl)),
+                );
+            }
+            record
+        }
+        Commands::List {
+            prefix,
+            tree,
+            long,
+            expiring_within,
+            older_than,
+            idle_over,
+        } => {
+            if let Some(within) = expiring_within {
+                let rows = cache
+                    .expiring_within(within)
+                    .into_iter()
+                    .filter(|(key, _)| in_subtree(&prefix, key))
+                    .map(|(key, ttl)| {
+                        let style = (ttl < EXPIRING_SOON).then_some(YELLOW);
+                        vec![(key, style), (format!("{}s", ttl.as_secs()), style)]
+                    })
+                    .collect();
+                print_table(&["KEY", "TTL"], rows, color);
+            } else if let Some(age) = older_than {
+                let rows = cache
+                    .older_than(age)
+                    .into_iter()
+                    .filter(|(key, _)| in_subtree(&prefix, key))
+                    .map(|(key, age)| vec![(key, None), (format!("{}s", age.as_secs()), None)])
+                    .collect();
+                print_table(&["KEY", "AGE"], rows, color);
+            } else if let Some(idle) = idle_over {
+                let rows = cache
+                    .idle_keys(idle)
+                    .into_iter()
+                    .filter(|(key, _)| in_subtree(&prefix, key))
+                    .map(|(key, idle)| vec![(key, None), (format!("{}s", idle.as_secs()), None)])
+                    .collect();
+                print_table(&["KEY", "IDLE"], rows, color);
+            } else if tree {
+                print_tree(&cache, &prefix, 0);
+            } else if long {
+                let rows = cache
+                    .list_children(&prefix)
+                    .into_iter()
+                    .map(|child| {
+                        let path = if prefix.is_empty() {
+                            child.clone()
+                        } else {
+                            format!("{}{}{}", prefix, KEY_SEPARATOR, child)
+                        };
+                        let children = cache.list_children(&path).len().to_string();
+                        match cache.ttl(&path) {
+                            Some(ttl) => {
+                                let size = cache.peek(&path).map_or(0, |value| value.len());
+                                let style = (ttl < EXPIRING_SOON).then_some(YELLOW);
+                                vec![
+                                    (child, style),
+                                    (format_ttl(ttl), style),
+                                    (size.to_string(), None),
+                                    (children, None),
+                                ]
+                            }
+                            None => vec![
+                                (child, None),
+                                ("-".to_string(), None),
+                                ("-".to_string(), None),
+                                (children, None),
+                            ],
+                        }
+                    })
+                    .collect();
+                print_table(&["NAME", "TTL", "SIZE", "CHILDREN"], rows, color);
+            } else {
+                for child in cache.list_children(&prefix) {
+                    println!("{}", child);
+                }
+            }
+            AuditRecord::new(&actor, AuditOp::List, &prefix)
+        }
+        Commands::Stats => {
+            let entries = snapshot(&cache)?;
+            let (mut live, mut expiring, mut bytes) = (0, 0, 0);
+            for (key, entry) in &entries {
+                if let Some(ttl) = cache.ttl(key) {
+                    live += 1;
+                    if ttl < EXPIRING_SOON {
+                        expiring += 1;
+                    }
+                }
+                bytes += entry["value"].as_str().map_or(0, str::len);
+            }
+            let expiring_style = (expiring > 0).then_some(YELLOW);
+            let mut rows = vec![
+                vec![
+                    ("entries".to_string(), None),
+                    (entries.len().to_string(), None),
+                ],
+                vec![("live".to_string(), None), (live.to_string(), None)],
+                vec![
+                    ("expired".to_string(), None),
+                    ((entries.len() - live).to_string(), None),
+                ],
+                vec![
+                    (
+                        format!("expiring within {}s", EXPIRING_SOON.as_secs()),
+                        expiring_style,
+                    ),
+                    (expiring.to_string(), expiring_style),
+                ],
+                vec![("value bytes".to_string(), None), (bytes.to_string(), None)],
+            ];
+            // Totals over every command run against the state file
+            let totals = cache.stats();
+            let hit_ratio = totals
+                .hit_ratio()
+                .map_or("-".to_string(), |ratio| format!("{:.0}%", ratio * 100.0));
+            let counts = [
+                ("hits", totals.hits),
+                ("misses", totals.misses),
+                ("writes", totals.inserts),
+                ("expirations", totals.expirations),
+                ("evictions", totals.evictions),
+                ("invalidations", totals.invalidations),
+                ("bytes written", totals.bytes_written),
+                ("retries", totals.retries),
+            ];
+            for (metric, count) in counts {
+                rows.push(vec![(metric.to_string(), None), (count.to_string(), None)]);
+            }
+            rows.push(vec![("hit ratio".to_string(), None), (hit_ratio, None)]);
+            print_table(&["METRIC", "VALUE"], rows, color);
+            AuditRecord::new(&actor, AuditOp::Stats, "")
         }
-        Commands::Invalidate { key } => {
-            cache.invalidate(&key);
-            println!("Invalidated key '{}'", key);
+        Commands::Sample { count } => {
+            let mut keys = cache.random_keys(count);
+            keys.sort_unstable();
+            let rows = keys
+                .into_iter()
+                .map(|key| {
+                    let ttl = cache.ttl(&key).unwrap_or_default();
+                    let size = cache.peek(&key).map_or(0, String::len);
+                    let style = (ttl < EXPIRING_SOON).then_some(YELLOW);
+                    vec![
+                        (key, style),
+                        (format_ttl(ttl), style),
+                        (size.to_string(), None),
+                    ]
+                })
+                .collect();
+            print_table(&["KEY", "TTL", "SIZE"], rows, color);
+            AuditRecord::new(&actor, AuditOp::Sample, "")
         }
+        Commands::Analyze => {
+            let report = cache.tuning_report();
+            let mut recommendations = Vec::new();
+            let rows = report
+                .patterns
+                .iter()
+                .map(|(pattern, stats)| {
+                    let recommendation = stats.recommendation();
+                    if let Some(recommendation) = recommendation {
+                        recommendations.push(format!("{}: {}", pattern, recommendation));
+                    }
+                    let style = recommendation.is_some().then_some(YELLOW);
+                    let average =
+                        |duration: Option<Duration>| duration.map_or("-".to_string(), format_ttl);
+                    vec![
+                        (pattern.clone(), style),
+                        ((stats.hits + stats.misses).to_string(), None),
+                        (
+                            stats
+                                .hit_ratio()
+                                .map_or("-".to_string(), |ratio| format!("{:.0}", ratio * 100.0)),
+                            None,
+                        ),
+                        (average(stats.average_ttl()), None),
+                        (average(stats.average_lifetime()), None),
+                        (stats.expirations.to_string(), None),
+                    ]
+                })
+                .collect();
+            print_table(
+                &[
+                    "PATTERN",
+                    "READS",
+                    "HIT %",
+                    "AVG TTL",
+                    "AVG LIFETIME",
+                    "EXPIRED",
+                ],
+                rows,
+                color,
+            );
+            if recommendations.is_empty() {
+                println!("No recommendations: too few reads recorded, or the TTLs fit");
+            }
+            for recommendation in recommendations {
+                println!("{}", recommendation);
+            }
+            AuditRecord::new(&actor, AuditOp::Analyze, "")
+        }
+        Commands::Lpush { key, value, ttl } => {
+            let mut record = AuditRecord::new(&actor, AuditOp::Lpush, &key).with_ttl(ttl);
+            if cli.audit_hash_values {
+                record = record.with_value_hash(&value);
+            }
+            match cache.lpush(&key, value, Duration::from_secs(ttl)) {
+                Some(len) => done(
+                    cli.dry_run,
+                    format_args!("Pushed onto key '{}' ({} value(s))", key, len),
+                ),
+                None => bail!(
+                    "key '{}' does not hold a list, the new list was rejected or the cache is disabled",
+                    key
+                ),
+            }
+            record
+        }
+        Commands::Rpop { key } => {
+            let value = cache.rpop(&key);
+            match &value {
+                Some(value) => done(
+                    cli.dry_run,
+                    format_args!("Popped from key '{}': {}", key, value),
+                ),
+                None => println!("No value to pop for key '{}'", key),
+            }
+            AuditRecord::new(&actor, AuditOp::Rpop, &key).with_hit(value.is_some())
+        }
+        Commands::Sadd { key, member, ttl } => {
+            let mut record = AuditRecord::new(&actor, AuditOp::Sadd, &key).with_ttl(ttl);
+            if cli.audit_hash_values {
+                record = record.with_value_hash(&member);
+            }
+            match cache.sadd(&key, member, Duration::from_secs(ttl)) {
+                Some(true) => done(cli.dry_run, format_args!("Added member to key '{}'", key)),
+                Some(false) => println!("Key '{}' already has that member", key),
+                None => bail!(
+                    "key '{}' does not hold a set, the new set was rejected or the cache is disabled",
+                    key
+                ),
+            }
+            record
+        }
+        Commands::Srem { key, member } => {
+            let removed = cache.srem(&key, &member);
+            if removed {
+                done(
+                    cli.dry_run,
+                    format_args!("Removed member from key '{}'", key),
+                );
+            } else {
+                println!("Key '{}' has no such member", key);
+            }
+            AuditRecord::new(&actor, AuditOp::Srem, &key).with_hit(removed)
+        }
+        Commands::Smembers { key } => {
+            let members = cache.smembers(&key);
+            for member in &members {
+                println!("{}", member);
+            }
+            AuditRecord::new(&actor, AuditOp::Smembers, &key).with_hit(!members.is_empty())
+        }
+        Commands::Hset {
+            key,
+            field,
+            value,
+            ttl,
+        } => {
+            let mut record = AuditRecord::new(&actor, AuditOp::Hset, &key).with_ttl(ttl);
+            if cli.audit_hash_values {
+                record = record.with_value_hash(&value);
+            }
+            match cache.hset(&key, &field, value, Duration::from_secs(ttl)) {
+                Some(_) => done(
+                    cli.dry_run,
+                    format_args!("Set field '{}' of key '{}'", field, key),
+                ),
+                None => bail!(
+                    "key '{}' does not hold a field map, the new map was rejected or the cache is disabled",
+                    key
+                ),
+            }
+            record
+        }
+        Commands::Hget { key, field } => {
+            let value = cache.hget(&key, &field);
+            match &value {
+                Some(value) => println!("Value for field '{}' of key '{}': {}", field, key, value),
+                None => println!("No value found for field '{}' of key '{}'", field, key),
+            }
+            AuditRecord::new(&actor, AuditOp::Hget, &key).with_hit(value.is_some())
+        }
+        Commands::Hdel { key, field } => {
+            let removed = cache.hdel(&key, &field);
+            if removed {
+                done(
+                    cli.dry_run,
+                    format_args!("Removed field '{}' of key '{}'", field, key),
+                );
+            } else {
+                println!("Key '{}' has no field '{}'", key, field);
+            }
+            AuditRecord::new(&actor, AuditOp::Hdel, &key).with_hit(removed)
+        }
+        Commands::Pack { output } => {
+            if cli.dry_run {
+                println!("Dry run: would pack the cache into {}", output.display());
+            } else {
+                // Packed in memory first, so a refused pack leaves no file behind
+                let mut archive = Vec::new();
+                let header = pack_cache(&cache, &mut archive)?;
+                fs::write(&output, archive)?;
+                println!(
+                    "Packed {} entries into {} (sha256 {})",
+                    header.entries,
+                    output.display(),
+                    header.payload_sha256
+                );
+            }
+            AuditRecord::new(&actor, AuditOp::Pack, &output.display().to_string())
+        }
+        Commands::Unpack { input } => {
+            let (header, inserted) = unpack_cache_into(&mut cache, File::open(&input)?)?;
+            done(
+                cli.dry_run,
+                format_args!(
+                    "Unpacked {} entries packed on {} at {}",
+                    inserted, header.host, header.created_at
+                ),
+            );
+            AuditRecord::new(&actor, AuditOp::Unpack, &input.display().to_string())
+        }
+        Commands::ImportEnv { prefix, ttl, file } => {
+            let vars = match &file {
+                Some(file) => parse_dotenv(&fs::read_to_string(file)?)?,
+                // Variables that are not valid UTF-8 cannot be cache entries
+                None => std::env::vars_os()
+                    .filter_map(|(name, value)| {
+                        Some((name.into_string().ok()?, value.into_string().ok()?))
+                    })
+                    .collect(),
+            };
+            let imported = import_vars(&mut cache, vars, &prefix, ttl)?;
+            done(
+                cli.dry_run,
+                format_args!("Imported {} variable(s) with prefix '{}'", imported, prefix),
+            );
+            AuditRecord::new(&actor, AuditOp::Import, &prefix).with_ttl(ttl.as_secs())
+        }
+        Commands::Import { format, input } => {
+            let mut progress = Progress::new("Importing");
+            let summary = match format {
+                Format::Csv => {
+                    import_csv_with_progress(&mut cache, File::open(&input)?, |done, total| {
+                        progress.update(done, total)
+                    })?
+                }
+            };
+            progress.finish();
+            done(
+                cli.dry_run,
+                format_args!(
+                    "Imported from {}: {} inserted, {} skipped, {} failed",
+                    input.display(),
+                    summary.inserted,
+                    summary.skipped,
+                    summary.failed
+                ),
+            );
+            report_errors(&summary.errors);
+            AuditRecord::new(&actor, AuditOp::Import, &input.display().to_string())
+        }
+        Commands::Export {
+            format,
+            output,
+            absolute,
+        } => {
+            let expiry = if absolute {
+                ExpiryColumn::Absolute
+            } else {
+                ExpiryColumn::Remaining
+            };
+            let path = output.as_ref().map(|output| output.display().to_string());
+            match (format, &output) {
+                (Format::Csv, Some(output)) if cli.dry_run => {
+                    println!("Dry run: would export the cache to {}", output.display());
+                }
+                (Format::Csv, Some(output)) => {
+                    let mut progress = Progress::new("Exporting");
+                    let exported = export_csv_with_progress(
+                        &cache,
+                        File::create(output)?,
+                        expiry,
+                        |done, total| progress.update(done, total),
+                    )?;
+                    progress.finish();
+                    println!("Exported {} entries to {}", exported, output.display());
+                }
+                // Nothing else goes to stdout, so the output can be piped
+                (Format::Csv, None) => {
+                    let mut progress = Progress::new("Exporting");
+                    let exported = export_csv_with_progress(
+                        &cache,
+                        io::stdout().lock(),
+                        expiry,
+                        |done, total| progress.update(done, total),
+                    )?;
+                    progress.finish();
+                    eprintln!("Exported {} entries", exported);
+                }
+            }
+            AuditRecord::new(&actor, AuditOp::Export, path.as_deref().unwrap_or("-"))
+        }
+        Commands::Warm {
+            input,
+            ttl,
+            jobs,
+            loader,
+        } => {
+            let keys = if input.as_os_str() == "-" {
+                io::read_to_string(io::stdin())?
+            } else {
+                fs::read_to_string(&input)?
+            };
+            let keys: Vec<String> = keys
+                .lines()
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(String::from)
+                .collect();
+            let mut progress = Progress::new("Warming");
+            let summary = warm_with_progress(
+                &mut cache,
+                &keys,
+                ttl,
+                jobs,
+                |key| {
+                    // Loaders may have side effects, so a dry run only shows which keys would load
+                    if cli.dry_run {
+                        Ok(format!("(output of {} for {})", loader[0], key))
+                    } else {
+                        run_loader(&loader, key)
+                    }
+                },
+                |done, total| progress.update(done, total),
+            );
+            progress.finish();
+            done(
+                cli.dry_run,
+                format_args!(
+                    "Warmed from {}: {} inserted, {} skipped, {} failed",
+                    input.display(),
+                    summary.inserted,
+                    summary.skipped,
+                    summary.failed
+                ),
+            );
+            report_errors(&summary.errors);
+            AuditRecord::new(&actor, AuditOp::Warm, &input.display().to_string())
+                .with_ttl(ttl.as_secs())
+        }
+        Commands::Ratelimit {
+            command: RatelimitCommands::Check { key, limit, window },
+        } => {
+            let mut limiter = RateLimiter::from_cache(cache, limit, Duration::from_secs(window));
+            match limiter.check(&key) {
+                Decision::Allowed { remaining } => {
+                    println!("Allowed key '{}' ({} remaining)", key, remaining)
+                }
+                Decision::Denied { retry_after } => {
+                    println!(
+                        "Rate limited key '{}', retry after {}s",
+                        key,
+                        retry_after.as_secs()
+                    );
+                    limited = true;
+                }
+            }
+            cache = limiter.into_cache();
+            AuditRecord::new(&actor, AuditOp::RatelimitCheck, &key)
+        }
+        Commands::Simulate { .. }
+        | Commands::Bench { .. }
+        | Commands::Proxy { .. }
+        | Commands::Doctor { .. }
+        | Commands::Audit { .. }
+        | Commands::Undo => unreachable!(),
+    };
+    match before {
+        Some(before) => report_changes(&before, &snapshot(&cache)?),
+        None => {
+            // Record the operation before persisting it, so nothing is saved unaudited
+            if cli.audit {
+                append_audit(&record)?;
+            }
+            let pruned = blobs.prune(&cache)?;
+            if pruned > 0 {
+                log::info!("removed {} unreferenced blob file(s)", pruned);
+            }
+            let started = Instant::now();
+            let mut save_retries = 0;
+            io_retry.retry_counting(
+                &mut save_retries,
+                || {
+                    if undoable {
+                        save_cache_with_history(&cache)
+                    } else {
+                        save_cache(&cache)
+                    }
+                },
+                is_transient,
+            )?;
+            if save_retries > 0 {
+                log::warn!("saving the cache took {} retries", save_retries);
+            }
+            log::debug!("saved the cache in {:?}", started.elapsed());
+        }
+    }
+
+    if limited {
+        std::process::exit(1);
     }
-    save_cache(&cache)?;
 
     Ok(())
 }
+
+// Writes log records to stderr; the binary has no use for anything fancier
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{} {}] {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn init_logging(cli: &Cli) {
+    let level = match (cli.quiet, cli.verbose) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => std::env::var("RUST_LOG")
+            .ok()
+            .and_then(|level| level.parse().ok())
+            .unwrap_or(LevelFilter::Warn),
+        (false, 1) => LevelFilter::Info,
+        (false, 2) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+    // Only fails if a logger is already set, which nothing else here does
+    let _ = log::set_logger(&StderrLogger);
+    log::set_max_level(level);
+}
+
+// Entries with less than this left are highlighted in tables
+const EXPIRING_SOON: Duration = Duration::from_secs(60);
+
+const YELLOW: &str = "33";
+
+// Prints rows of cells under a header, padding each column to its widest
+// cell; a cell with an ANSI color code is wrapped in it when color is on
+fn print_table(header: &[&str], rows: Vec<Vec<(String, Option<&str>)>>, color: bool) {
+    let mut widths: Vec<usize> = header.iter().map(|title| title.chars().count()).collect();
+    for row in &rows {
+        for (width, (text, _)) in widths.iter_mut().zip(row) {
+            *width = (*width).max(text.chars().count());
+        }
+    }
+    let header = header
+        .iter()
+        .map(|title| (title.to_string(), Some("1")))
+        .collect();
+    for row in std::iter::once(header).chain(rows) {
+        let last = row.len().saturating_sub(1);
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(column, ((text, style), width))| {
+                // Padding the last column would only leave trailing spaces
+                let width = if column == last { 0 } else { *width };
+                let padded = format!("{:width$}", text, width = width);
+                match style {
+                    Some(code) if color => format!("\x1b[{}m{}\x1b[0m", code, padded),
+                    _ => padded,
+                }
+            })
+            .collect();
+        println!("{}", line.join("  "));
+    }
+}
+
+// Failed import records listed after the summary; the rest are only counted
+const MAX_REPORTED_ERRORS: usize = 5;
+
+fn report_errors(errors: &[String]) {
+    for error in errors.iter().take(MAX_REPORTED_ERRORS) {
+        eprintln!("  {}", error);
+    }
+    if errors.len() > MAX_REPORTED_ERRORS {
+        eprintln!("  ... and {} more", errors.len() - MAX_REPORTED_ERRORS);
+    }
+}
+
+// Runs a `warm` loader for `key`, taking its standard output, less one
+// trailing newline, as the value
+fn run_loader(loader: &[String], key: &str) -> Result<String> {
+    let mut args: Vec<String> = loader[1..]
+        .iter()
+        .map(|arg| arg.replace("{}", key))
+        .collect();
+    if !loader[1..].iter().any(|arg| arg.contains("{}")) {
+        args.push(key.to_string());
+    }
+    let output = Command::new(&loader[0])
+        .args(&args)
+        .output()
+        .map_err(|err| anyhow!("could not run '{}': {}", loader[0], err))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match stderr.trim() {
+            "" => bail!("'{}' exited with {}", loader[0], output.status),
+            stderr => bail!("'{}' exited with {}: {}", loader[0], output.status, stderr),
+        }
+    }
+    let mut value = String::from_utf8(output.stdout)
+        .map_err(|_| anyhow!("'{}' printed a value that is not UTF-8", loader[0]))?;
+    if value.ends_with('\n') {
+        value.pop();
+    }
+    Ok(value)
+}
+
+// A percentage bar on stderr, drawn only when stderr is a terminal
+struct Progress {
+    label: &'static str,
+    shown: Option<usize>,
+    enabled: bool,
+}
+
+impl Progress {
+    fn new(label: &'static str) -> Self {
+        Progress {
+            label,
+            shown: None,
+            enabled: io::stderr().is_terminal(),
+        }
+    }
+
+    fn update(&mut self, done: usize, total: usize) {
+        let percent = (done * 100).checked_div(total).unwrap_or(100);
+        // Redrawing on every record would make the bar the bottleneck
+        if !self.enabled || self.shown == Some(percent) {
+            return;
+        }
+        self.shown = Some(percent);
+        let filled = percent / 5;
+        let mut stderr = io::stderr().lock();
+        let _ = write!(
+            stderr,
+            "\r{} [{}{}] {:>3}% ({}/{})",
+            self.label,
+            "#".repeat(filled),
+            " ".repeat(20 - filled),
+            percent,
+            done,
+            total
+        );
+        let _ = stderr.flush();
+    }
+
+    fn finish(&mut self) {
+        if self.enabled && self.shown.is_some() {
+            eprintln!();
+        }
+    }
+}
+
+// The entries as they would be saved, keyed by cache key, leaving out
+// access times: every read moves them, so they are not worth reporting
+fn snapshot(cache: &Cache<String>) -> Result<BTreeMap<String, serde_json::Value>> {
+    let mut state = serde_json::to_value(cache)?;
+    let mut entries: BTreeMap<String, serde_json::Value> =
+        serde_json::from_value(state["entries"].take())?;
+    for entry in entries.values_mut() {
+        if let Some(entry) = entry.as_object_mut() {
+            entry.remove("accessed_at");
+        }
+    }
+    Ok(entries)
+}
+
+// Prints what a command changed, unless it is a dry run, which reports the
+// changes itself
+fn done(dry_run: bool, message: fmt::Arguments<'_>) {
+    if !dry_run {
+        println!("{}", message);
+    }
+}
+
+// Prints how the saved state would differ, one key per line
+fn report_changes(
+    before: &BTreeMap<String, serde_json::Value>,
+    after: &BTreeMap<String, serde_json::Value>,
+) {
+    let added: Vec<_> = after
+        .keys()
+        .filter(|key| !before.contains_key(*key))
+        .collect();
+    let removed: Vec<_> = before
+        .keys()
+        .filter(|key| !after.contains_key(*key))
+        .collect();
+    let updated: Vec<_> = after
+        .iter()
+        .filter(|(key, entry)| before.get(*key).is_some_and(|previous| previous != *entry))
+        .map(|(key, _)| key)
+        .collect();
+    println!(
+        "Dry run: would add {}, update {} and remove {} entries",
+        added.len(),
+        updated.len(),
+        removed.len()
+    );
+    for (sign, keys) in [("+", added), ("~", updated), ("-", removed)] {
+        for key in keys {
+            println!("{} {}", sign, key);
+        }
+    }
+}
+
+// Round-trips `value` through $VISUAL or $EDITOR (vi if neither is set) via a temporary file
+fn edit_in_editor(key: &str, value: &str) -> Result<String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // Editors are often configured with arguments, e.g. "code --wait"
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or_else(|| anyhow!("$EDITOR is empty"))?;
+
+    // Created exclusively and readable only by the owner, since values may be
+    // secrets; removed when dropped, however this returns
+    let mut file = tempfile::Builder::new()
+        .prefix("memory_cache-edit-")
+        .suffix(".txt")
+        .tempfile()?;
+    file.write_all(value.as_bytes())?;
+    file.flush()?;
+    let status = Command::new(program).args(words).arg(file.path()).status();
+    // Editors often replace the file rather than write to it, so it is read back by path
+    let edited = fs::read_to_string(file.path());
+    drop(file);
+
+    let status = status.map_err(|err| anyhow!("could not run editor '{}': {}", editor, err))?;
+    if !status.success() {
+        bail!(
+            "editor exited with {}, key '{}' left unchanged",
+            status,
+            key
+        );
+    }
+    let mut edited = edited?;
+    // Most editors end the file with a newline the original value did not have
+    if !value.ends_with('\n') && edited.ends_with('\n') {
+        edited.pop();
+        if edited.ends_with('\r') {
+            edited.pop();
+        }
+    }
+    Ok(edited)
+}
+
+// Seconds left, or "never" for entries that do not expire
+fn format_ttl(ttl: Duration) -> String {
+    if ttl == Duration::MAX {
+        "never".to_string()
+    } else {
+        format!("{}s", ttl.as_secs())
+    }
+}
+
+// Parses TTLs such as 90, 90s, 5m, 1h or 2d
+fn parse_ttl(value: &str) -> Result<Duration, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("invalid TTL '{}', expected e.g. 90s, 5m, 1h or 2d", value))?;
+    let unit = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "unknown TTL unit '{}', expected s, m, h or d",
+                unit
+            ))
+        }
+    };
+    Ok(Duration::from_secs(amount.saturating_mul(unit)))
+}
+
+// Parses RFC 3339 times such as 2025-01-01T00:00:00Z or 2025-01-01T09:30:00+05:30,
+// dropping fractions of a second
+fn parse_timestamp(value: &str) -> Result<SystemTime, String> {
+    let invalid = || {
+        format!(
+            "invalid time '{}', expected e.g. 2025-01-01T00:00:00Z",
+            value
+        )
+    };
+    let bytes = value.as_bytes();
+    if bytes.len() < 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !matches!(bytes[10], b'T' | b't' | b' ')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+    {
+        return Err(invalid());
+    }
+    let number = |text: &str, range| number_in(text, range).ok_or_else(invalid);
+    let (year, month, day) = (
+        number(value, 0..4)?,
+        number(value, 5..7)?,
+        number(value, 8..10)?,
+    );
+    let (hour, minute, second) = (
+        number(value, 11..13)?,
+        number(value, 14..16)?,
+        number(value, 17..19)?,
+    );
+    let mut rest = value.get(19..).ok_or_else(invalid)?;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(fraction.len());
+        rest = &fraction[digits..];
+    }
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ if rest.len() == 6 && rest.as_bytes()[3] == b':' => {
+            let sign = match rest.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return Err(invalid()),
+            };
+            let offset = &rest[1..];
+            sign * (number(offset, 0..2)? * 3600 + number(offset, 3..5)? * 60)
+        }
+        _ => return Err(invalid()),
+    };
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let month_days = [
+        31,
+        if leap { 29 } else { 28 },
+        31,
+        30,
+        31,
+        30,
+        31,
+        31,
+        30,
+        31,
+        30,
+        31,
+    ];
+    if !(1..=12).contains(&month)
+        || !(1..=month_days[month as usize - 1]).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return Err(invalid());
+    }
+
+    // Days since 1970-01-01 in the proleptic Gregorian calendar, counting
+    // years from March so the leap day comes last
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * m + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let secs = days * 86_400 + hour * 3600 + minute * 60 + second - offset;
+    let secs = u64::try_from(secs).map_err(|_| format!("time '{}' is before 1970", value))?;
+    Ok(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+// The decimal number at `range` of `text`, if that is all digits
+fn number_in(text: &str, range: std::ops::Range<usize>) -> Option<i64> {
+    let digits = text.get(range)?;
+    digits
+        .bytes()
+        .all(|b| b.is_ascii_digit())
+        .then(|| digits.parse().ok())
+        .flatten()
+}
+
+// The keys and expiry times of the state file's entries object in file
+// order, duplicates included
+struct RawEntries(Vec<(String, u64)>);
+
+// The part of a persisted entry that says whether it is still live
+#[derive(Deserialize)]
+struct RawEntry {
+    expiry: u64,
+}
+
+impl<'de> Deserialize<'de> for RawEntries {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeysVisitor;
+
+        impl<'de> Visitor<'de> for KeysVisitor {
+            type Value = RawEntries;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a map of entries")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<RawEntries, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(key) = map.next_key()? {
+                    let entry: RawEntry = map.next_value()?;
+                    entries.push((key, entry.expiry));
+                }
+                Ok(RawEntries(entries))
+            }
+        }
+
+        deserializer.deserialize_map(KeysVisitor)
+    }
+}
+
+// What a cache saves besides its entries
+const SAVED_FIELDS: [&str; 4] = ["epochs", "version", "tuning", "stats"];
+
+// The parts of a state file that the cache itself silently tidies up on load
+#[derive(Deserialize)]
+struct RawState {
+    entries: RawEntries,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, IgnoredAny>,
+}
+
+// Checks the state file and prints each problem, returning whether none are left
+//
+// Expired entries, duplicate keys and unknown fields all go away when the
+// cache is loaded and saved again, so `fix` does just that. Odd keys are
+// only reported, as there is no telling what they should have been. Entries
+// are judged by what the file holds, not by what a cache loaded under this
+// environment would serve, which MEMORY_CACHE_DISABLED makes nothing.
+fn doctor(fix: bool) -> Result<bool> {
+    println!("Checking {}", CACHE_FILE);
+    let contents = match fs::read(CACHE_FILE) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            println!("No state file; the next command will create an empty one");
+            return Ok(true);
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let raw: RawState = match serde_json::from_slice(&contents) {
+        Ok(raw) => raw,
+        Err(err) => {
+            println!("error: the state file does not parse: {}", err);
+            println!("`memory_cache undo` restores the state from before the last change");
+            return Ok(false);
+        }
+    };
+    let mut cache = match load_cache_from_slice(&contents) {
+        Ok(cache) => cache,
+        Err(err) => {
+            println!("error: the state file does not load: {:#}", err);
+            println!("`memory_cache undo` restores the state from before the last change");
+            return Ok(false);
+        }
+    };
+
+    // Problems a rewrite fixes, then ones it does not
+    let mut fixable = 0;
+    let mut remaining = 0;
+    // State files carry no format version; a field we do not know means another writer
+    for field in raw
+        .unknown
+        .keys()
+        .filter(|field| !SAVED_FIELDS.contains(&field.as_str()))
+    {
+        println!(
+            "warning: unknown top-level field {:?} is ignored; it may come from a newer version",
+            field
+        );
+        fixable += 1;
+    }
+    if cache.is_disabled() {
+        println!("note: MEMORY_CACHE_DISABLED is set, so other commands see no entries");
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    // Each key's count and the expiry of its last, used, entry
+    let mut seen = BTreeMap::new();
+    for (key, expiry) in &raw.entries.0 {
+        let (count, last) = seen.entry(key.as_str()).or_insert((0, 0));
+        *count += 1;
+        *last = *expiry;
+    }
+    for (key, (count, expiry)) in &seen {
+        if *count > 1 {
+            println!(
+                "warning: key {:?} appears {} times; only the last one is used",
+                key, count
+            );
+            fixable += 1;
+        }
+        // Expired entries are on their way out, whatever their keys
+        if let Some(problem) = key_problem(key).filter(|_| now < *expiry) {
+            println!("warning: key {:?} {}", key, problem);
+            remaining += 1;
+        }
+    }
+    let total = seen.len();
+    let expired = seen.values().filter(|(_, expiry)| *expiry <= now).count();
+    if total > 0 {
+        println!(
+            "{} entries, {} expired ({:.0}%), {} bytes",
+            total,
+            expired,
+            expired as f64 * 100.0 / total as f64,
+            contents.len()
+        );
+    }
+    // A few expired entries are normal; a state file of mostly dead ones is worth compacting
+    if expired * 2 > total {
+        println!("warning: most entries have expired; pruning them would shrink the state file");
+        fixable += 1;
+    }
+    if let Err(problem) = cache.check_invariants() {
+        println!("error: {}", problem);
+        remaining += 1;
+    }
+
+    if fix && fixable > 0 {
+        let pruned = cache.prune_expired();
+        save_cache_with_history(&cache)?;
+        println!(
+            "Rewrote the state file, dropping {} expired entries; `memory_cache undo` reverts this",
+            pruned
+        );
+        fixable = 0;
+    }
+    match fixable + remaining {
+        0 => println!("No problems found"),
+        problems if fixable > 0 => println!(
+            "{} problem(s) found; --fix rewrites the state file to solve {} of them",
+            problems, fixable
+        ),
+        problems => println!("{} problem(s) found", problems),
+    }
+    Ok(fixable + remaining == 0)
+}
+
+// Keys are free-form, but ones like these are usually a bug in whatever wrote them
+fn key_problem(key: &str) -> Option<&'static str> {
+    if key.is_empty() {
+        Some("is empty")
+    } else if key.chars().any(char::is_control) {
+        Some("contains control characters")
+    } else if key.trim() != key {
+        Some("has leading or trailing whitespace")
+    } else if key.starts_with(KEY_SEPARATOR) || key.ends_with(KEY_SEPARATOR) {
+        Some("starts or ends with the key separator")
+    } else if key.split(KEY_SEPARATOR).any(str::is_empty) {
+        Some("has an empty path segment")
+    } else {
+        None
+    }
+}
+
+// Accepts a plain count or one with a K, M or G suffix, e.g. 1M
+fn parse_count(value: &str) -> Result<u64, String> {
+    let (amount, unit) = match value.char_indices().last() {
+        Some((split, 'k' | 'K')) => (&value[..split], 1_000),
+        Some((split, 'm' | 'M')) => (&value[..split], 1_000_000),
+        Some((split, 'g' | 'G')) => (&value[..split], 1_000_000_000),
+        _ => (value, 1),
+    };
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("invalid count '{}', expected e.g. 5000, 10K or 1M", value))?;
+    Ok(amount.saturating_mul(unit))
+}
+
+fn parse_jobs(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(jobs) if jobs > 0 => Ok(jobs),
+        _ => Err(format!(
+            "invalid job count '{}', expected at least 1",
+            value
+        )),
+    }
+}
+
+fn parse_ratio(value: &str) -> Result<f64, String> {
+    match value.parse() {
+        Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(ratio),
+        _ => Err(format!("invalid ratio '{}', expected 0 to 1", value)),
+    }
+}
+
+// Runs `ops` gets and inserts over `keys` prefilled keys and prints the throughput and latency percentiles
+fn run_bench(ops: u64, read_ratio: f64, value_size: usize, keys: u64, seed: u64, color: bool) {
+    let ttl = Duration::from_secs(3600);
+    let value = "x".repeat(value_size);
+    let names: Vec<String> = (0..keys).map(|index| format!("bench/{}", index)).collect();
+    let mut cache = Cache::new();
+    for name in &names {
+        cache.insert(name, value.clone(), ttl);
+    }
+
+    let mut rng = Rng::new(seed);
+    // One latency per operation, in nanoseconds
+    let mut gets: Vec<u64> = Vec::new();
+    let mut inserts: Vec<u64> = Vec::new();
+    let started = Instant::now();
+    for _ in 0..ops {
+        let key = &names[(rng.next_u64() % keys) as usize];
+        if rng.next_f64() < read_ratio {
+            let op = Instant::now();
+            std::hint::black_box(cache.get(key));
+            gets.push(op.elapsed().as_nanos() as u64);
+        } else {
+            // Cloning the value is the caller's cost, not the cache's
+            let value = value.clone();
+            let op = Instant::now();
+            cache.insert(key, value, ttl);
+            inserts.push(op.elapsed().as_nanos() as u64);
+        }
+    }
+    let elapsed = started.elapsed();
+
+    println!(
+        "{} ops ({:.0}% gets, {}-byte values, {} keys, seed {}) in {:.2?}: {:.0} ops/s",
+        ops,
+        read_ratio * 100.0,
+        value_size,
+        keys,
+        seed,
+        elapsed,
+        ops as f64 / elapsed.as_secs_f64()
+    );
+    let rows = [("get", gets), ("insert", inserts)]
+        .into_iter()
+        .filter(|(_, latencies)| !latencies.is_empty())
+        .map(|(op, mut latencies)| {
+            latencies.sort_unstable();
+            let percentile = |p: f64| {
+                let rank = ((latencies.len() as f64 * p).ceil() as usize).max(1);
+                format_latency(latencies[rank - 1])
+            };
+            vec![
+                (op.to_string(), None),
+                (latencies.len().to_string(), None),
+                (percentile(0.5), None),
+                (percentile(0.9), None),
+                (percentile(0.99), None),
+                (percentile(0.999), None),
+                (format_latency(latencies[latencies.len() - 1]), None),
+            ]
+        })
+        .collect();
+    print_table(
+        &["OP", "COUNT", "P50", "P90", "P99", "P99.9", "MAX"],
+        rows,
+        color,
+    );
+}
+
+fn format_latency(nanos: u64) -> String {
+    match nanos {
+        0..=999 => format!("{}ns", nanos),
+        1_000..=999_999 => format!("{:.1}µs", nanos as f64 / 1e3),
+        _ => format!("{:.1}ms", nanos as f64 / 1e6),
+    }
+}
+
+// Whether `key` is `prefix` or below it; every key is below the empty prefix
+fn in_subtree(prefix: &str, key: &str) -> bool {
+    prefix.is_empty()
+        || key
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(KEY_SEPARATOR))
+}
+
+fn print_tree(cache: &Cache<String>, prefix: &str, depth: usize) {
+    for child in cache.list_children(prefix) {
+        println!("{}{}", "  ".repeat(depth), child);
+        let path = if prefix.is_empty() {
+            child
+        } else {
+            format!("{}{}{}", prefix, KEY_SEPARATOR, child)
+        };
+        print_tree(cache, &path, depth + 1);
+    }
+}
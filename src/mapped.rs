@@ -0,0 +1,234 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::marker::PhantomData;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::{ptr, slice};
+
+use anyhow::Result;
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde::Deserialize;
+
+use crate::clock::system_now;
+use crate::policy::disabled_by_env;
+
+/// A state file mapped into memory for reading single keys
+///
+/// Looking a key up scans the file without building a cache: values of
+/// other keys are skipped over, keys and values are not allocated, and a
+/// string value without escapes is returned as a slice of the mapping. This
+/// suits short-lived processes that read one or two keys from a large state
+/// file; loading the cache is faster when many keys are read.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::{save_cache_to, Cache, MappedState};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let path = std::env::temp_dir().join(format!("memory_cache-mapped-{}.json", std::process::id()));
+/// let mut cache = Cache::new();
+/// cache.insert("greeting", "hello".to_string(), Duration::from_secs(60));
+/// save_cache_to(&cache, &path)?;
+///
+/// // Nothing else writes to the file while it is mapped
+/// let state = unsafe { MappedState::open(&path)? };
+/// assert_eq!(state.get("greeting")?.as_deref(), Some("hello"));
+/// assert_eq!(state.get("missing")?, None);
+/// # drop(state);
+/// # std::fs::remove_file(&path)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct MappedState {
+    // Null for an empty or missing file, which maps nothing
+    data: *const u8,
+    len: usize,
+    disabled: bool,
+}
+
+// The mapping is read-only and owned by the MappedState
+unsafe impl Send for MappedState {}
+unsafe impl Sync for MappedState {}
+
+impl MappedState {
+    /// Maps the state file at `path`; a missing file reads as an empty cache
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while it is mapped. Saves
+    /// rewrite the state file in place, so no process may save to it until
+    /// the `MappedState` is dropped; truncation makes reads fault.
+    pub unsafe fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut state = MappedState {
+            data: ptr::null(),
+            len: 0,
+            disabled: disabled_by_env(),
+        };
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(state),
+            Err(err) => return Err(err.into()),
+        };
+        let len = usize::try_from(file.metadata()?.len())?;
+        // mmap rejects empty mappings
+        if len == 0 {
+            return Ok(state);
+        }
+        let data = libc::mmap(
+            ptr::null_mut(),
+            len,
+            libc::PROT_READ,
+            libc::MAP_PRIVATE,
+            file.as_raw_fd(),
+            0,
+        );
+        if data == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error().into());
+        }
+        state.data = data as *const u8;
+        state.len = len;
+        Ok(state)
+    }
+
+    /// Returns the mapped contents of the state file
+    pub fn as_bytes(&self) -> &[u8] {
+        if self.data.is_null() {
+            return &[];
+        }
+        // The mapping lives as long as self
+        unsafe { slice::from_raw_parts(self.data, self.len) }
+    }
+
+    /// Returns the live value for `key`, or None if it is expired or not found
+    ///
+    /// A value is borrowed from the mapping unless it has to be unescaped.
+    /// Like a loaded cache, nothing is found while `MEMORY_CACHE_DISABLED`
+    /// is set. Fails if the file is not a valid state file.
+    pub fn get(&self, key: &str) -> Result<Option<Cow<'_, str>>> {
+        let bytes = self.as_bytes();
+        if self.disabled || bytes.is_empty() {
+            return Ok(None);
+        }
+        let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+        let entry = deserializer.deserialize_map(StateVisitor { key })?;
+        deserializer.end()?;
+        Ok(entry
+            .filter(|entry| system_now() < entry.expiry)
+            .map(|entry| entry.value.0))
+    }
+}
+
+impl Drop for MappedState {
+    fn drop(&mut self) {
+        if !self.data.is_null() {
+            unsafe {
+                libc::munmap(self.data as *mut libc::c_void, self.len);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for MappedState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MappedState")
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+// A string borrowed from the input where it has no escapes
+struct Text<'a>(Cow<'a, str>);
+
+impl<'de: 'a, 'a> Deserialize<'de> for Text<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TextVisitor<'a>(PhantomData<Text<'a>>);
+
+        impl<'de: 'a, 'a> Visitor<'de> for TextVisitor<'a> {
+            type Value = Text<'a>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a string")
+            }
+
+            fn visit_borrowed_str<E: de::Error>(self, value: &'de str) -> Result<Self::Value, E> {
+                Ok(Text(Cow::Borrowed(value)))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                Ok(Text(Cow::Owned(value.to_owned())))
+            }
+        }
+
+        deserializer.deserialize_str(TextVisitor(PhantomData))
+    }
+}
+
+#[derive(Deserialize)]
+struct Entry<'a> {
+    #[serde(borrow)]
+    value: Text<'a>,
+    expiry: u64,
+}
+
+// Finds "entries" in the top-level object, skipping its other fields
+struct StateVisitor<'k> {
+    key: &'k str,
+}
+
+impl<'de> Visitor<'de> for StateVisitor<'_> {
+    type Value = Option<Entry<'de>>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a cache state")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut found = None;
+        while let Some(field) = map.next_key::<Text<'de>>()? {
+            if field.0 == "entries" {
+                found = map.next_value_seed(EntriesSeed { key: self.key })?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(found)
+    }
+}
+
+// Decodes the entry for one key, skipping the rest
+struct EntriesSeed<'k> {
+    key: &'k str,
+}
+
+impl<'de> DeserializeSeed<'de> for EntriesSeed<'_> {
+    type Value = Option<Entry<'de>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for EntriesSeed<'_> {
+    type Value = Option<Entry<'de>>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a map of entries")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        // The whole map is read so that, as when loading, a repeated key keeps its last entry
+        let mut found = None;
+        while let Some(key) = map.next_key::<Text<'de>>()? {
+            if key.0 == self.key {
+                found = Some(map.next_value()?);
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(found)
+    }
+}
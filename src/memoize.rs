@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+use crate::SharedCache;
+
+/// Wraps `f` so that its results are cached in `cache` for `ttl`, keyed by `key_fn` of the argument
+///
+/// Functions of several arguments take them as a tuple. The lock is not
+/// held while `f` runs, so concurrent calls with the same missing key may
+/// each run it, and the last result is kept. Pass a clone of a cache kept
+/// elsewhere to invalidate results or share them between memoized
+/// functions, with keys that tell the functions apart.
+///
+/// # Example
+///
+/// ```
+/// use std::cell::Cell;
+/// use std::time::Duration;
+/// use memory_cache::{memoize, SharedCache};
+///
+/// let calls = Cell::new(0);
+/// let cache = SharedCache::new();
+/// let price = memoize(
+///     cache.clone(),
+///     |(sku, currency): &(u32, &str)| format!("price/{}/{}", sku, currency),
+///     Duration::from_secs(30),
+///     |(sku, currency): (u32, &str)| {
+///         calls.set(calls.get() + 1);
+///         format!("{} {}", sku * 100, currency)
+///     },
+/// );
+///
+/// assert_eq!(price((7, "EUR")), "700 EUR");
+/// assert_eq!(price((7, "EUR")), "700 EUR");
+/// assert_eq!(calls.get(), 1);
+///
+/// cache.invalidate("price/7/EUR");
+/// price((7, "EUR"));
+/// assert_eq!(calls.get(), 2);
+/// ```
+pub fn memoize<A, T, K, F>(cache: SharedCache<T>, key_fn: K, ttl: Duration, f: F) -> impl Fn(A) -> T
+where
+    T: Clone,
+    K: Fn(&A) -> String,
+    F: Fn(A) -> T,
+{
+    move |argument| {
+        let key = key_fn(&argument);
+        if let Some(value) = cache.get(&key) {
+            return value;
+        }
+        let value = f(argument);
+        cache.insert(&key, value.clone(), ttl);
+        value
+    }
+}
@@ -0,0 +1,412 @@
+//! Response caching middleware for [`tower`] services
+//!
+//! [`CacheLayer`] wraps any HTTP service and stores successful responses in a
+//! [`SharedCache`], keyed by a user-supplied extractor. Requests for which the
+//! extractor returns `None` always go to the inner service. With
+//! [`CacheLayer::with_conditional_requests`], responses also carry `ETag`,
+//! `Last-Modified` and `Cache-Control` headers so that browsers and HTTP
+//! caches in front of the service can revalidate instead of refetching.
+
+use std::collections::hash_map::DefaultHasher;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, UNIX_EPOCH};
+
+use bytes::Bytes;
+use http::header::{self, HeaderValue};
+use http::{HeaderMap, Method, Request, Response, StatusCode};
+use http_body::Body;
+use http_body_util::{BodyExt, Full};
+use tower::{Layer, Service};
+
+use crate::{Cache, SharedCache};
+
+/// Error type returned by [`CacheService`]
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A cache of buffered responses, shared between all clones of a [`CacheLayer`]
+pub type SharedResponseCache = SharedCache<CachedResponse>;
+
+/// A fully buffered response as stored in the cache
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    // Hash of the status and body, for the ETag
+    digest: u64,
+}
+
+impl CachedResponse {
+    /// The status code of the cached response
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The headers of the cached response
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The buffered body of the cached response
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    fn new(status: StatusCode, headers: HeaderMap, body: Bytes) -> Self {
+        // DefaultHasher::new always uses the same keys, so the same response
+        // gets the same ETag after a restart
+        let mut hasher = DefaultHasher::new();
+        status.hash(&mut hasher);
+        body.hash(&mut hasher);
+        CachedResponse {
+            status,
+            headers,
+            body,
+            digest: hasher.finish(),
+        }
+    }
+
+    fn to_response(&self) -> Response<Full<Bytes>> {
+        let mut response = Response::new(Full::new(self.body.clone()));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RouteTtls {
+    default: Duration,
+    routes: Vec<(String, Duration)>,
+}
+
+impl RouteTtls {
+    // The longest matching path prefix wins
+    fn for_path(&self, path: &str) -> Duration {
+        self.routes
+            .iter()
+            .filter(|(prefix, _)| path.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, ttl)| *ttl)
+            .unwrap_or(self.default)
+    }
+}
+
+/// A [`Layer`] that caches successful responses of the wrapped service
+///
+/// # Example
+///
+/// ```
+/// use std::convert::Infallible;
+/// use std::time::Duration;
+/// use bytes::Bytes;
+/// use http::{Method, Request, Response};
+/// use http_body_util::Full;
+/// use memory_cache::middleware::CacheLayer;
+/// use tower::{service_fn, ServiceBuilder, ServiceExt};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<(), memory_cache::middleware::BoxError> {
+/// // Only GET requests are cached, keyed by their URI
+/// let layer = CacheLayer::new(
+///     |req: &Request<()>| (req.method() == Method::GET).then(|| req.uri().to_string()),
+///     Duration::from_secs(30),
+/// )
+/// .route_ttl("/static", Duration::from_secs(3600));
+/// let cache = layer.cache();
+///
+/// let service = ServiceBuilder::new().layer(layer).service(service_fn(|_req| async {
+///     Ok::<_, Infallible>(Response::new(Full::new(Bytes::from("hello"))))
+/// }));
+///
+/// let request = Request::get("/users/42").body(()).unwrap();
+/// let response = service.oneshot(request).await?;
+/// assert!(response.status().is_success());
+/// assert!(cache.get("/users/42").is_some());
+/// # Ok(())
+/// # }
+/// ```
+pub struct CacheLayer<K> {
+    cache: SharedResponseCache,
+    key_fn: Arc<K>,
+    ttls: Arc<RouteTtls>,
+    conditional: bool,
+}
+
+impl<K> CacheLayer<K> {
+    /// Creates a layer that caches responses for `ttl`, keyed by `key_fn`
+    ///
+    /// `key_fn` returns `None` for requests that must not be cached.
+    pub fn new(key_fn: K, ttl: Duration) -> Self {
+        CacheLayer {
+            cache: SharedCache::new(),
+            key_fn: Arc::new(key_fn),
+            ttls: Arc::new(RouteTtls {
+                default: ttl,
+                routes: Vec::new(),
+            }),
+            conditional: false,
+        }
+    }
+
+    /// Uses an existing cache instead of creating a new one
+    pub fn with_cache(mut self, cache: SharedResponseCache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Overrides the TTL for requests whose path starts with `prefix`
+    pub fn route_ttl(mut self, prefix: &str, ttl: Duration) -> Self {
+        Arc::make_mut(&mut self.ttls)
+            .routes
+            .push((prefix.to_string(), ttl));
+        self
+    }
+
+    /// Adds validators to cached responses and answers matching `If-None-Match` requests with 304 Not Modified
+    ///
+    /// Responses served from or stored in the cache get an `ETag` derived
+    /// from the status and body, so it changes with them but not across
+    /// restarts, a `Last-Modified` with the time the entry was written and
+    /// `Cache-Control: max-age` with its remaining TTL. Headers the inner
+    /// service set itself are kept as they are. A GET or HEAD hit whose `If-None-Match` lists the response's
+    /// `ETag` gets an empty 304 response instead of the body.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::convert::Infallible;
+    /// use std::time::Duration;
+    /// use bytes::Bytes;
+    /// use http::{header, Request, Response, StatusCode};
+    /// use http_body_util::Full;
+    /// use memory_cache::middleware::CacheLayer;
+    /// use tower::{service_fn, ServiceBuilder, ServiceExt};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> Result<(), memory_cache::middleware::BoxError> {
+    /// let build = || {
+    ///     let layer = CacheLayer::new(|req: &Request<()>| Some(req.uri().to_string()), Duration::from_secs(60))
+    ///         .with_conditional_requests();
+    ///     ServiceBuilder::new().layer(layer).service(service_fn(|_req| async {
+    ///         Ok::<_, Infallible>(Response::new(Full::new(Bytes::from("large report"))))
+    ///     }))
+    /// };
+    /// let service = build();
+    ///
+    /// let first = service.clone().oneshot(Request::get("/report").body(()).unwrap()).await?;
+    /// let etag = first.headers()[header::ETAG].clone();
+    /// assert_eq!(first.headers()[header::CACHE_CONTROL], "max-age=60");
+    ///
+    /// let request = Request::get("/report").header(header::IF_NONE_MATCH, etag.clone()).body(()).unwrap();
+    /// let again = service.oneshot(request).await?;
+    /// assert_eq!(again.status(), StatusCode::NOT_MODIFIED);
+    ///
+    /// // A fresh cache, as after a restart, tags the same response the same way
+    /// let restarted = build().oneshot(Request::get("/report").body(()).unwrap()).await?;
+    /// assert_eq!(restarted.headers()[header::ETAG], etag);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_conditional_requests(mut self) -> Self {
+        self.conditional = true;
+        self
+    }
+
+    /// Returns a handle to the underlying cache, e.g. for manual invalidation
+    pub fn cache(&self) -> SharedResponseCache {
+        self.cache.clone()
+    }
+}
+
+impl<K> Clone for CacheLayer<K> {
+    fn clone(&self) -> Self {
+        CacheLayer {
+            cache: self.cache.clone(),
+            key_fn: self.key_fn.clone(),
+            ttls: self.ttls.clone(),
+            conditional: self.conditional,
+        }
+    }
+}
+
+impl<S, K> Layer<S> for CacheLayer<K> {
+    type Service = CacheService<S, K>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CacheService {
+            inner,
+            cache: self.cache.clone(),
+            key_fn: self.key_fn.clone(),
+            ttls: self.ttls.clone(),
+            conditional: self.conditional,
+        }
+    }
+}
+
+/// The [`Service`] produced by [`CacheLayer`]
+pub struct CacheService<S, K> {
+    inner: S,
+    cache: SharedResponseCache,
+    key_fn: Arc<K>,
+    ttls: Arc<RouteTtls>,
+    conditional: bool,
+}
+
+impl<S: Clone, K> Clone for CacheService<S, K> {
+    fn clone(&self) -> Self {
+        CacheService {
+            inner: self.inner.clone(),
+            cache: self.cache.clone(),
+            key_fn: self.key_fn.clone(),
+            ttls: self.ttls.clone(),
+            conditional: self.conditional,
+        }
+    }
+}
+
+impl<S, K, ReqBody, ResBody> Service<Request<ReqBody>> for CacheService<S, K>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send,
+    K: Fn(&Request<ReqBody>) -> Option<String>,
+    ReqBody: Send + 'static,
+    ResBody: Body + Send + 'static,
+    ResBody::Data: Send,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = Response<Full<Bytes>>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let key = (self.key_fn)(&request);
+        // Nests under whatever request span the host already has, so hits and
+        // misses show up in its traces
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!("memory_cache.response", key = key.as_deref(), hit = false);
+        if let Some(key) = &key {
+            let mut cache = self.cache.lock();
+            if let Some(hit) = cache.get(key) {
+                #[cfg(feature = "tracing")]
+                span.record("hit", true);
+                let mut response = hit.to_response();
+                if self.conditional {
+                    add_validators(response.headers_mut(), &cache, key);
+                    let revalidating = matches!(*request.method(), Method::GET | Method::HEAD);
+                    if revalidating && none_match(request.headers(), response.headers()) {
+                        response = not_modified(response.headers());
+                    }
+                }
+                return Box::pin(async move { Ok(response) });
+            }
+        }
+
+        let ttl = self.ttls.for_path(request.uri().path());
+        let cache = self.cache.clone();
+        let conditional = self.conditional;
+        // Use the service that was driven to readiness and leave a fresh clone behind
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        let future = async move {
+            let response = inner.call(request).await.map_err(Into::into)?;
+            let (mut parts, body) = response.into_parts();
+            let body = body.collect().await.map_err(Into::into)?.to_bytes();
+
+            if let Some(key) = key {
+                if parts.status.is_success() {
+                    let cached =
+                        CachedResponse::new(parts.status, parts.headers.clone(), body.clone());
+                    let mut cache = cache.lock();
+                    cache.insert(&key, cached, ttl);
+                    if conditional {
+                        add_validators(&mut parts.headers, &cache, &key);
+                    }
+                }
+            }
+            Ok(Response::from_parts(parts, Full::new(body)))
+        };
+        #[cfg(feature = "tracing")]
+        let future = tracing::Instrument::instrument(future, span);
+        Box::pin(future)
+    }
+}
+
+// Sets ETag, Last-Modified and Cache-Control from the live entry under
+// `key`, leaving any the response already has
+fn add_validators(headers: &mut HeaderMap, cache: &Cache<CachedResponse>, key: &str) {
+    let Some(entry) = cache.live_entry(key) else {
+        return;
+    };
+    if !headers.contains_key(header::ETAG) {
+        // Weak, since the body may be re-encoded on the way out
+        let etag = format!("W/\"{:016x}\"", entry.value.digest);
+        if let Ok(etag) = HeaderValue::from_str(&etag) {
+            headers.insert(header::ETAG, etag);
+        }
+    }
+    // Entries saved before write times were recorded have none
+    if !headers.contains_key(header::LAST_MODIFIED) && entry.updated_at > 0 {
+        let written = UNIX_EPOCH + Duration::from_secs(entry.updated_at);
+        if let Ok(date) = HeaderValue::from_str(&httpdate::fmt_http_date(written)) {
+            headers.insert(header::LAST_MODIFIED, date);
+        }
+    }
+    if !headers.contains_key(header::CACHE_CONTROL) {
+        if let Some(ttl) = cache.ttl(key).filter(|ttl| *ttl != Duration::MAX) {
+            let max_age = format!("max-age={}", ttl.as_secs());
+            if let Ok(max_age) = HeaderValue::from_str(&max_age) {
+                headers.insert(header::CACHE_CONTROL, max_age);
+            }
+        }
+    }
+}
+
+// Whether any entity tag in the request's If-None-Match matches the
+// response's ETag, compared weakly as RFC 9110 requires for GET and HEAD
+fn none_match(request: &HeaderMap, response: &HeaderMap) -> bool {
+    let Some(etag) = response
+        .get(header::ETAG)
+        .and_then(|etag| etag.to_str().ok())
+    else {
+        return false;
+    };
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag);
+    request
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|tag| tag.trim() == "*" || opaque(tag) == etag)
+}
+
+// An empty 304 carrying the headers RFC 9110 says it must repeat
+fn not_modified(headers: &HeaderMap) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::new()));
+    *response.status_mut() = StatusCode::NOT_MODIFIED;
+    for name in [
+        header::CACHE_CONTROL,
+        header::CONTENT_LOCATION,
+        header::DATE,
+        header::ETAG,
+        header::EXPIRES,
+        header::LAST_MODIFIED,
+        header::VARY,
+    ] {
+        for value in headers.get_all(&name) {
+            response.headers_mut().append(name.clone(), value.clone());
+        }
+    }
+    response
+}
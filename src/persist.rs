@@ -0,0 +1,605 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::value::RawValue;
+
+use crate::cache::{is_zero, Tombstones};
+use crate::clock::system_now;
+use crate::epoch::Epochs;
+use crate::tuning::TuningStats;
+use crate::{Cache, CacheEntry, CacheStats, SharedCache};
+
+/// The state file [`load_cache`] and [`save_cache`] use, relative to the working directory
+pub const CACHE_FILE: &str = "cache_state.json";
+const HISTORY_DIR: &str = "cache_history";
+
+/// Number of earlier state files kept by [`save_cache_with_history`]
+pub const HISTORY_LEN: usize = 10;
+
+/// Largest state file, in bytes, that loading will accept
+///
+/// Nesting depth needs no separate limit: serde_json rejects input nested
+/// more than 128 levels deep, including inside fields the cache ignores.
+pub const MAX_STATE_SIZE: u64 = 64 * 1024 * 1024;
+
+pub fn load_cache() -> Result<Cache<String>> {
+    load_cache_from(CACHE_FILE)
+}
+
+pub fn save_cache(cache: &Cache<String>) -> Result<()> {
+    save_cache_to(cache, CACHE_FILE)
+}
+
+/// Returns true if a load or save failed in a way worth retrying with a [`RetryPolicy`](crate::RetryPolicy)
+///
+/// Timeouts, interruptions and dropped connections, as network filesystems
+/// report them, are transient; a missing directory, a denied permission or
+/// a corrupt state file is not.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::{is_transient, load_cache_from, save_cache_to, Cache, RetryPolicy};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let path = std::env::temp_dir().join(format!("memory_cache-retry-{}.json", std::process::id()));
+/// let mut cache = Cache::new();
+/// cache.insert("config", "v1".to_string(), Duration::from_secs(3600));
+///
+/// let policy = RetryPolicy::new(3);
+/// policy.retry(|| save_cache_to(&cache, &path), is_transient)?;
+/// let loaded = policy.retry(|| load_cache_from(&path), is_transient)?;
+/// assert_eq!(loaded.peek("config"), Some(&"v1".to_string()));
+/// # std::fs::remove_file(&path)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<io::Error>())
+        .any(|err| {
+            matches!(
+                err.kind(),
+                ErrorKind::Interrupted
+                    | ErrorKind::TimedOut
+                    | ErrorKind::WouldBlock
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::NotConnected
+                    | ErrorKind::BrokenPipe
+                    | ErrorKind::ResourceBusy
+                    | ErrorKind::StaleNetworkFileHandle
+            )
+        })
+}
+
+/// What a load or save with a report did, for logging its cost
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PersistReport {
+    /// Time spent reading and parsing, or serializing and writing
+    pub duration: Duration,
+    /// Size of the state file read or written
+    pub bytes: u64,
+    /// Entries loaded or saved
+    pub entries: usize,
+    /// Expired entries left out
+    pub skipped_expired: usize,
+}
+
+/// Loads like [`load_cache`], dropping expired entries and reporting what it did
+pub fn load_cache_with_report() -> Result<(Cache<String>, PersistReport)> {
+    load_cache_with_report_from(CACHE_FILE)
+}
+
+/// Saves like [`save_cache`], leaving out expired entries and reporting what it did
+pub fn save_cache_with_report(cache: &Cache<String>) -> Result<PersistReport> {
+    save_cache_with_report_to(cache, CACHE_FILE)
+}
+
+/// Loads like [`load_cache_from`], dropping expired entries and reporting what it did
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::{load_cache_with_report_from, save_cache_with_report_to, Cache};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let path = std::env::temp_dir().join(format!("memory_cache-report-{}.json", std::process::id()));
+/// let mut cache = Cache::new();
+/// cache.insert("config", "v1".to_string(), Duration::from_secs(3600));
+/// cache.insert("gone", "v0".to_string(), Duration::ZERO);
+///
+/// let saved = save_cache_with_report_to(&cache, &path)?;
+/// assert_eq!((saved.entries, saved.skipped_expired), (1, 1));
+///
+/// let (_, loaded) = load_cache_with_report_from(&path)?;
+/// assert_eq!((loaded.entries, loaded.bytes), (1, saved.bytes));
+/// # std::fs::remove_file(&path)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn load_cache_with_report_from(
+    path: impl AsRef<Path>,
+) -> Result<(Cache<String>, PersistReport)> {
+    let started = Instant::now();
+    let (mut cache, bytes) = match read_state(path.as_ref())? {
+        Some(contents) => (load_cache_from_slice(&contents)?, contents.len() as u64),
+        None => (load_cache_from(path)?, 0),
+    };
+    // A loaded cache has no staleness window, so this drops every expired entry
+    let skipped_expired = cache.prune_expired();
+    let report = PersistReport {
+        duration: started.elapsed(),
+        bytes,
+        entries: cache.entries.len(),
+        skipped_expired,
+    };
+    Ok((cache, report))
+}
+
+/// Saves like [`save_cache_to`], leaving out expired entries and reporting what it did
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::{load_cache_from, load_shared_with_budget_from, save_cache_with_report_to, Cache, LoadBudget};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let path = std::env::temp_dir().join(format!("memory_cache-report-epochs-{}.json", std::process::id()));
+/// let mut cache = Cache::new();
+/// cache.insert("tenant/acme/plan", "old".to_string(), Duration::from_secs(3600));
+/// cache.bump_epoch("tenant/acme");
+/// cache.insert("tenant/globex/plan", "pro".to_string(), Duration::from_secs(3600));
+/// let known = cache.version("tenant/globex/plan").unwrap();
+/// cache.invalidate("tenant/globex/plan");
+/// save_cache_with_report_to(&cache, &path)?;
+///
+/// // Entries a bump superseded stay invalidated after loading
+/// let mut loaded = load_cache_from(&path)?;
+/// assert_eq!(loaded.get("tenant/acme/plan"), None);
+///
+/// // New writes get versions later than any handed out before saving
+/// loaded.insert("tenant/globex/plan", "free".to_string(), Duration::from_secs(3600));
+/// assert!(loaded.version("tenant/globex/plan").unwrap() > known);
+/// let (shared, rest) = load_shared_with_budget_from(&path, &LoadBudget::new())?;
+/// rest.join().unwrap()?;
+/// assert_eq!(shared.get("tenant/acme/plan"), None);
+/// # std::fs::remove_file(&path)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn save_cache_with_report_to(
+    cache: &Cache<String>,
+    path: impl AsRef<Path>,
+) -> Result<PersistReport> {
+    let started = Instant::now();
+    let now = cache.now();
+    let live = LiveState {
+        entries: LiveEntries { cache, now },
+        epochs: &cache.epochs,
+        version: cache.version,
+        tuning: cache.tuning.as_ref(),
+        stats: cache.stats,
+    };
+    let serialized = serde_json::to_string(&live)?;
+    fs::write(path, &serialized)?;
+    let entries = cache
+        .entries
+        .values()
+        .filter(|entry| now < entry.expiry)
+        .count();
+    Ok(PersistReport {
+        duration: started.elapsed(),
+        bytes: serialized.len() as u64,
+        entries,
+        skipped_expired: cache.entries.len() - entries,
+    })
+}
+
+/// How much of a state file [`load_shared_with_budget_from`] loads before returning
+///
+/// Loading up front stops at whichever limit is reached first; with no
+/// limits everything is loaded before returning.
+#[derive(Debug, Clone, Default)]
+pub struct LoadBudget {
+    max_entries: Option<usize>,
+    max_bytes: Option<u64>,
+    max_time: Option<Duration>,
+    priority: LoadPriority,
+}
+
+/// Which entries a [`LoadBudget`] loads first
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LoadPriority {
+    /// Entries read most recently
+    #[default]
+    MostRecentlyUsed,
+    /// Entries closest to expiring
+    SoonestExpiry,
+}
+
+impl LoadBudget {
+    /// Creates a budget with no limits
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads at most `max` entries up front
+    pub fn with_max_entries(mut self, max: usize) -> Self {
+        self.max_entries = Some(max);
+        self
+    }
+
+    /// Loads at most `max` bytes of keys and serialized values up front
+    pub fn with_max_bytes(mut self, max: u64) -> Self {
+        self.max_bytes = Some(max);
+        self
+    }
+
+    /// Stops loading up front once `max` has passed since loading began, parsing included
+    pub fn with_max_time(mut self, max: Duration) -> Self {
+        self.max_time = Some(max);
+        self
+    }
+
+    /// Sets which entries are loaded first
+    pub fn with_priority(mut self, priority: LoadPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    fn allows(&self, entries: usize, bytes: u64, started: Instant) -> bool {
+        self.max_entries.is_none_or(|max| entries < max)
+            && self.max_bytes.is_none_or(|max| bytes < max)
+            && self.max_time.is_none_or(|max| started.elapsed() < max)
+    }
+}
+
+/// Loads like [`load_shared_with_budget_from`], from [`CACHE_FILE`]
+pub fn load_shared_with_budget(
+    budget: &LoadBudget,
+) -> Result<(SharedCache<String>, JoinHandle<Result<usize>>)> {
+    load_shared_with_budget_from(CACHE_FILE, budget)
+}
+
+/// Loads the state file at `path` up to `budget`, finishing on a background thread
+///
+/// The returned cache can serve requests straight away: the entries the
+/// budget ranks first are in it, and the thread adds the rest in batches,
+/// returning how many it added. Keys written in the meantime keep the new
+/// value, and keys removed in the meantime, whether one by one, by
+/// [`Cache::clear`] or by the `invalidate_` methods, stay removed. Expired
+/// entries are left out. The whole file is still read and checked before
+/// returning, but values outside the budget are only decoded by the thread.
+/// The rest of the saved state, such as [`Cache::with_stats`] totals, is
+/// kept as [`load_cache_from`] keeps it.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::{load_shared_with_budget_from, save_cache_to, Cache, LoadBudget};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let path = std::env::temp_dir().join(format!("memory_cache-budget-{}.json", std::process::id()));
+/// let mut cache = Cache::new().with_clock(|| 1_000).with_stats();
+/// cache.insert("old", "v1".to_string(), Duration::MAX);
+/// let mut cache = cache.with_clock(|| 2_000);
+/// cache.insert("recent", "v2".to_string(), Duration::MAX);
+/// save_cache_to(&cache, &path)?;
+///
+/// let budget = LoadBudget::new().with_max_entries(1);
+/// let (shared, rest) = load_shared_with_budget_from(&path, &budget)?;
+/// assert!(shared.lock().contains_key("recent"));
+/// assert_eq!(shared.lock().stats().inserts, 2);
+///
+/// assert_eq!(rest.join().unwrap()?, 1);
+/// assert!(shared.lock().contains_key("old"));
+///
+/// // A key removed before the thread gets to it is not brought back
+/// let (shared, rest) = load_shared_with_budget_from(&path, &budget)?;
+/// shared.invalidate("old");
+/// assert_eq!(rest.join().unwrap()?, 0);
+/// assert!(!shared.lock().contains_key("old"));
+/// # std::fs::remove_file(&path)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn load_shared_with_budget_from(
+    path: impl AsRef<Path>,
+    budget: &LoadBudget,
+) -> Result<(SharedCache<String>, JoinHandle<Result<usize>>)> {
+    let started = Instant::now();
+    let Some(contents) = read_state(path.as_ref())? else {
+        let shared = SharedCache::from_cache(load_cache_from(path)?);
+        return Ok((shared, thread::spawn(|| Ok(0))));
+    };
+    if contents.len() as u64 > MAX_STATE_SIZE {
+        bail!("state file is larger than {} bytes", MAX_STATE_SIZE);
+    }
+    let state: RawState = serde_json::from_slice(&contents)?;
+    drop(contents);
+    let now = system_now();
+    let mut entries: Vec<_> = state
+        .entries
+        .into_iter()
+        .filter(|(_, entry)| now < entry.expiry)
+        .collect();
+    // Ties go by key so the split does not depend on hash order
+    match budget.priority {
+        LoadPriority::MostRecentlyUsed => entries.sort_unstable_by(|(a, x), (b, y)| {
+            y.accessed_at.cmp(&x.accessed_at).then_with(|| a.cmp(b))
+        }),
+        LoadPriority::SoonestExpiry => entries
+            .sort_unstable_by(|(a, x), (b, y)| x.expiry.cmp(&y.expiry).then_with(|| a.cmp(b))),
+    }
+    let mut entries = entries.into_iter();
+    let mut cache = Cache::new();
+    cache.epochs = state.epochs;
+    cache.version = state.version;
+    cache.tuning = state.tuning;
+    cache.stats = state.stats;
+    let (mut loaded, mut bytes) = (0, 0);
+    while budget.allows(loaded, bytes, started) {
+        let Some((key, entry)) = entries.next() else {
+            break;
+        };
+        bytes += (key.len() + entry.value.get().len()) as u64;
+        cache.restore(key, decode(entry)?);
+        loaded += 1;
+    }
+    if entries.len() > 0 {
+        cache.tombstones = Some(Tombstones::default());
+    }
+    let shared = SharedCache::from_cache(cache);
+    let handle = shared.clone();
+    let finish = thread::spawn(move || {
+        let mut added = 0;
+        // Batches are decoded outside the lock, leaving it free for requests
+        let restored = loop {
+            let batch = match entries
+                .by_ref()
+                .take(1024)
+                .map(|(key, entry)| Ok((key, decode(entry)?)))
+                .collect::<Result<Vec<_>>>()
+            {
+                Ok(batch) => batch,
+                Err(err) => break Err(err),
+            };
+            if batch.is_empty() {
+                break Ok(added);
+            }
+            let mut cache = handle.lock();
+            for (key, entry) in batch {
+                added += usize::from(cache.restore(key, entry));
+            }
+        };
+        handle.lock().tombstones = None;
+        restored
+    });
+    Ok((shared, finish))
+}
+
+// A state file with its values left undecoded
+#[derive(Deserialize)]
+struct RawState {
+    entries: HashMap<String, CacheEntry<Box<RawValue>>>,
+    #[serde(default)]
+    epochs: Epochs,
+    #[serde(default)]
+    version: u64,
+    #[serde(default)]
+    tuning: Option<TuningStats>,
+    #[serde(default)]
+    stats: Option<CacheStats>,
+}
+
+fn decode(entry: CacheEntry<Box<RawValue>>) -> Result<CacheEntry<String>> {
+    Ok(CacheEntry {
+        value: serde_json::from_str(entry.value.get())?,
+        expiry: entry.expiry,
+        created_at: entry.created_at,
+        updated_at: entry.updated_at,
+        accessed_at: entry.accessed_at,
+        epoch: entry.epoch,
+        version: entry.version,
+    })
+}
+
+// The state file format of a cache, without its expired entries
+#[derive(Serialize)]
+struct LiveState<'a> {
+    entries: LiveEntries<'a>,
+    #[serde(skip_serializing_if = "Epochs::is_empty")]
+    epochs: &'a Epochs,
+    #[serde(skip_serializing_if = "is_zero")]
+    version: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tuning: Option<&'a TuningStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<CacheStats>,
+}
+
+struct LiveEntries<'a> {
+    cache: &'a Cache<String>,
+    now: u64,
+}
+
+impl Serialize for LiveEntries<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let live = self
+            .cache
+            .entries
+            .iter()
+            .filter(|(_, entry)| self.now < entry.expiry);
+        serializer.collect_map(live)
+    }
+}
+
+/// Loads a cache from the state file at `path`, creating an empty one if it is missing
+///
+/// Any other failure, including a corrupt file, is returned as an error and
+/// leaves the file untouched.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(path = %path.as_ref().display()))
+)]
+pub fn load_cache_from(path: impl AsRef<Path>) -> Result<Cache<String>> {
+    match read_state(path.as_ref())? {
+        Some(contents) => load_cache_from_slice(&contents),
+        None => {
+            let cache = Cache::new();
+            save_cache_to(&cache, path)?;
+            Ok(cache)
+        }
+    }
+}
+
+// Reads a state file, or None if there is none
+fn read_state(path: &Path) -> Result<Option<Vec<u8>>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    // Read one byte past the limit so a file that grows while we read is caught too
+    let mut contents = Vec::new();
+    file.take(MAX_STATE_SIZE + 1).read_to_end(&mut contents)?;
+    Ok(Some(contents))
+}
+
+/// Parses a cache from the contents of a state file
+///
+/// Fails on input larger than [`MAX_STATE_SIZE`] or that is not a valid state file.
+///
+/// # Example
+///
+/// ```
+/// use memory_cache::load_cache_from_slice;
+/// assert!(load_cache_from_slice(br#"{"entries":{}}"#).is_ok());
+/// assert!(load_cache_from_slice(b"not json").is_err());
+/// ```
+pub fn load_cache_from_slice(contents: &[u8]) -> Result<Cache<String>> {
+    if contents.len() as u64 > MAX_STATE_SIZE {
+        bail!("state file is larger than {} bytes", MAX_STATE_SIZE);
+    }
+    let cache = serde_json::from_slice(contents)?;
+    Ok(cache)
+}
+
+/// Writes a cache to the state file at `path`
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(path = %path.as_ref().display(), entries = cache.entries.len())
+    )
+)]
+pub fn save_cache_to(cache: &Cache<String>, path: impl AsRef<Path>) -> Result<()> {
+    let serialized = serde_json::to_string(cache)?;
+    fs::write(path, serialized)?;
+    Ok(())
+}
+
+/// Saves like [`save_cache`], first keeping the current state file so [`undo_save`] can restore it
+pub fn save_cache_with_history(cache: &Cache<String>) -> Result<()> {
+    save_cache_with_history_to(cache, CACHE_FILE, HISTORY_DIR)
+}
+
+/// Restores the state file from before the last [`save_cache_with_history`], returning false if there is none
+pub fn undo_save() -> Result<bool> {
+    undo_save_at(CACHE_FILE, HISTORY_DIR)
+}
+
+/// Saves `cache` to `path`, moving the file it replaces into `history_dir`
+///
+/// Only the newest [`HISTORY_LEN`] earlier states are kept.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::{load_cache_from, save_cache_to, save_cache_with_history_to, undo_save_at, Cache};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let dir = std::env::temp_dir().join(format!("memory_cache-history-{}", std::process::id()));
+/// let (path, history) = (dir.join("state.json"), dir.join("history"));
+/// std::fs::create_dir_all(&dir)?;
+///
+/// let mut cache = Cache::new();
+/// cache.insert("config", "v1".to_string(), Duration::from_secs(3600));
+/// save_cache_to(&cache, &path)?;
+///
+/// cache.clear();
+/// save_cache_with_history_to(&cache, &path, &history)?;
+/// assert!(undo_save_at(&path, &history)?);
+/// assert_eq!(load_cache_from(&path)?.get("config").as_deref(), Some("v1"));
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn save_cache_with_history_to(
+    cache: &Cache<String>,
+    path: impl AsRef<Path>,
+    history_dir: impl AsRef<Path>,
+) -> Result<()> {
+    let (path, history_dir) = (path.as_ref(), history_dir.as_ref());
+    if path.exists() {
+        fs::create_dir_all(history_dir)?;
+        let history = history_files(history_dir)?;
+        let next = history.last().map_or(0, |(seq, _)| seq + 1);
+        fs::copy(path, history_dir.join(format!("{:020}.json", next)))?;
+        // Counting the copy just made, keep HISTORY_LEN
+        let excess = (history.len() + 1).saturating_sub(HISTORY_LEN);
+        for (_, old) in &history[..excess] {
+            fs::remove_file(old)?;
+        }
+    }
+    save_cache_to(cache, path)
+}
+
+/// Moves the newest state file in `history_dir` back to `path`, returning false if there is none
+pub fn undo_save_at(path: impl AsRef<Path>, history_dir: impl AsRef<Path>) -> Result<bool> {
+    let history = history_files(history_dir.as_ref())?;
+    let Some((_, latest)) = history.last() else {
+        return Ok(false);
+    };
+    // Refuse to restore something that would not load
+    load_cache_from_slice(&fs::read(latest)?)?;
+    fs::rename(latest, path)?;
+    Ok(true)
+}
+
+// Earlier state files by sequence number, oldest first
+fn history_files(history_dir: &Path) -> Result<Vec<(u64, PathBuf)>> {
+    let entries = match fs::read_dir(history_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut history = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let seq = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse().ok());
+        if let Some(seq) = seq {
+            history.push((seq, path));
+        }
+    }
+    history.sort_unstable();
+    Ok(history)
+}
@@ -0,0 +1,309 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::hash::BuildHasher;
+use core::time::Duration;
+
+use crate::{Cache, CacheError, RejectReason};
+
+/// A check run before a value is stored, given the key, value and TTL
+///
+/// See [`Cache::with_insert_hook`].
+pub type InsertHook<T> = fn(&str, &T, Duration) -> Result<(), RejectReason>;
+
+/// A callback given a key and how long its entry has left to live
+///
+/// See [`Cache::with_expiry_hook`].
+pub type ExpiryHook = fn(&str, Duration);
+
+pub(crate) type Weigher<T> = fn(&T) -> usize;
+
+// The checks a value has to pass before it is stored
+#[derive(Clone)]
+pub(crate) struct Admission<T> {
+    pub(crate) max_value_size: Option<(usize, Weigher<T>)>,
+    pub(crate) hooks: Vec<InsertHook<T>>,
+}
+
+impl<T> Admission<T> {
+    // Whether every value passes, so there is nothing to check
+    pub(crate) fn is_empty(&self) -> bool {
+        self.max_value_size.is_none() && self.hooks.is_empty()
+    }
+
+    pub(crate) fn check(&self, key: &str, value: &T, ttl: Duration) -> Result<(), CacheError> {
+        if let Some((max, weigh)) = self.max_value_size {
+            let size = weigh(value);
+            if size > max {
+                return Err(CacheError::ValueTooLarge {
+                    key: key.to_string(),
+                    size,
+                    max,
+                });
+            }
+        }
+        // Hooks run in order, stopping at the first rejection
+        for hook in &self.hooks {
+            hook(key, value, ttl).map_err(|reason| CacheError::Rejected {
+                key: key.to_string(),
+                reason,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> Default for Admission<T> {
+    fn default() -> Self {
+        Admission {
+            max_value_size: None,
+            hooks: Vec::new(),
+        }
+    }
+}
+
+impl<T: Clone, S: BuildHasher> Cache<T, S> {
+    /// Adds a check that every value must pass before it is stored
+    ///
+    /// Hooks run in the order they were added, on every method that stores a
+    /// value. A rejected value is not stored: [`Cache::try_insert`] reports
+    /// the [`CacheError`], while the other methods behave as if the value was
+    /// never offered. Like the clock, hooks are not persisted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::{Cache, RejectReason};
+    ///
+    /// let mut cache = Cache::new().with_insert_hook(|key, _value: &&str, _ttl| {
+    ///     if key.starts_with("tenant:") {
+    ///         Ok(())
+    ///     } else {
+    ///         Err(RejectReason::new("keys must start with 'tenant:'"))
+    ///     }
+    /// });
+    ///
+    /// assert!(cache.try_insert("tenant:a:user", "alice", Duration::from_secs(30)).is_ok());
+    /// assert!(cache.try_insert("user", "bob", Duration::from_secs(30)).is_err());
+    /// assert_eq!(cache.get("user"), None);
+    /// ```
+    pub fn with_insert_hook(mut self, hook: InsertHook<T>) -> Self {
+        self.admission.hooks.push(hook);
+        self
+    }
+
+    /// Calls `hook` whenever [`Cache::get`] returns a value with at most `within` left to live
+    ///
+    /// This lets applications refresh credentials and the like before they
+    /// lapse, on the request that notices rather than on a timer. Only one
+    /// hook is kept; like the clock, it is not persisted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    ///
+    /// static REFRESHES: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// let mut cache = Cache::new().with_clock(|| 1_000).with_expiry_hook(Duration::from_secs(300), |_key, _left| {
+    ///     REFRESHES.fetch_add(1, Ordering::SeqCst);
+    /// });
+    /// cache.insert("oauth/token", "abc", Duration::from_secs(120));
+    /// cache.insert("oauth/refresh", "def", Duration::from_secs(86_400));
+    ///
+    /// cache.get("oauth/token");
+    /// cache.get("oauth/refresh");
+    /// assert_eq!(REFRESHES.load(Ordering::SeqCst), 1);
+    /// ```
+    pub fn with_expiry_hook(mut self, within: Duration, hook: ExpiryHook) -> Self {
+        self.expiry_hook = Some((within.as_secs(), hook));
+        self
+    }
+
+    /// Turns the cache into a pass-through that never stores anything
+    ///
+    /// While disabled, every lookup misses and every store is skipped, so
+    /// callers fall back to their source of truth; existing entries are kept
+    /// for when the cache is enabled again. Operators can disable every cache
+    /// in a process without code changes by setting `MEMORY_CACHE_DISABLED=1`,
+    /// which is read when a cache is created or loaded.
+    ///
+    /// [`Cache::insert_if_absent`], [`Cache::increment`] and the collection
+    /// writes such as [`Cache::lpush`] report failure while the cache is
+    /// disabled. The caches that leases, idempotency keys,
+    /// rate limits and sessions keep for themselves ignore the variable, since
+    /// their guarantees depend on storing entries.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::{Cache, FieldMap};
+    /// let mut cache = Cache::new().with_disabled(true);
+    /// cache.insert("user", "alice", Duration::from_secs(30));
+    /// assert_eq!(cache.get("user"), None);
+    /// assert!(!cache.insert_if_absent("user", "alice", Duration::from_secs(30)));
+    ///
+    /// let mut lists: Cache<Vec<String>> = Cache::new().with_disabled(true);
+    /// assert_eq!(lists.lpush("jobs", "first", Duration::from_secs(30)), None);
+    /// assert_eq!(lists.sadd("seen", "msg-1", Duration::from_secs(30)), None);
+    /// let mut maps: Cache<FieldMap> = Cache::new().with_disabled(true);
+    /// assert_eq!(maps.hset("user/42", "name", "alice", Duration::from_secs(30)), None);
+    /// ```
+    pub fn with_disabled(mut self, disabled: bool) -> Self {
+        self.disabled = disabled;
+        self
+    }
+
+    /// Disables or re-enables the cache at runtime; see [`Cache::with_disabled`]
+    pub fn set_disabled(&mut self, disabled: bool) {
+        self.disabled = disabled;
+    }
+
+    /// Returns true if the cache is a pass-through
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
+    /// Refuses to store values whose [`Weigh::weight`] exceeds `max` bytes
+    ///
+    /// Oversized values are handled like values rejected by an insert hook;
+    /// [`Cache::try_insert`] reports them as [`CacheError::ValueTooLarge`].
+    /// The limit is not persisted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::{Cache, CacheError};
+    /// let mut cache = Cache::new().with_max_value_size(8);
+    ///
+    /// assert!(cache.try_insert("short", "12345678".to_string(), Duration::from_secs(30)).is_ok());
+    /// assert!(matches!(
+    ///     cache.try_insert("long", "123456789".to_string(), Duration::from_secs(30)),
+    ///     Err(CacheError::ValueTooLarge { size: 9, max: 8, .. })
+    /// ));
+    /// ```
+    pub fn with_max_value_size(mut self, max: usize) -> Self
+    where
+        T: Weigh,
+    {
+        self.admission.max_value_size = Some((max, T::weight));
+        self
+    }
+
+    /// Serves expired values for up to `max_staleness` when a loader fails
+    ///
+    /// Applies to [`Cache::try_get_or_insert_with`]. Expired entries stay in
+    /// memory until the staleness window has passed as well, but [`Cache::get`]
+    /// still treats them as missing. The setting is not persisted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    ///
+    /// static NOW: AtomicU64 = AtomicU64::new(1_000);
+    ///
+    /// let mut cache = Cache::new()
+    ///     .with_clock(|| NOW.load(Ordering::SeqCst))
+    ///     .with_stale_on_error(Duration::from_secs(300));
+    /// cache.insert("rates", "1.08", Duration::from_secs(60));
+    ///
+    /// // The origin is down after the entry expires
+    /// NOW.fetch_add(120, Ordering::SeqCst);
+    /// let rates = cache.try_get_or_insert_with("rates", Duration::from_secs(60), || Err("origin down"));
+    /// assert_eq!(rates, Ok("1.08"));
+    ///
+    /// // Past the staleness window the error comes through
+    /// NOW.fetch_add(300, Ordering::SeqCst);
+    /// let rates = cache.try_get_or_insert_with("rates", Duration::from_secs(60), || Err("origin down"));
+    /// assert_eq!(rates, Err("origin down"));
+    /// ```
+    pub fn with_stale_on_error(mut self, max_staleness: Duration) -> Self {
+        self.max_staleness = max_staleness.as_secs();
+        self
+    }
+
+    /// Shrinks the entry map whenever removals leave less than `min_load` of its capacity in use
+    ///
+    /// The map is shrunk to twice the number of entries, so it can grow a
+    /// little again before reallocating. `min_load` is capped at 0.25 so that
+    /// a shrunk map is not shrunk again by the next removal, and maps with
+    /// room for at most 1024 entries are left alone. See
+    /// [`Cache::shrink_to_fit`]; like the clock, this is not persisted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new().with_auto_shrink(0.1);
+    /// for job in 0..100_000 {
+    ///     cache.insert(&format!("job/{}", job), job, Duration::from_secs(60));
+    /// }
+    /// let burst = cache.capacity();
+    ///
+    /// cache.invalidate_matching("job/*");
+    /// assert!(cache.capacity() < burst / 10);
+    /// ```
+    pub fn with_auto_shrink(mut self, min_load: f64) -> Self {
+        self.auto_shrink = Some(min_load.clamp(0.0, 0.25));
+        self
+    }
+
+    pub(crate) fn shrink_if_sparse(&mut self) {
+        let Some(min_load) = self.auto_shrink else {
+            return;
+        };
+        let capacity = self.entries.capacity();
+        if capacity > SHRINK_MIN_CAPACITY && (self.entries.len() as f64) < capacity as f64 * min_load {
+            self.entries.shrink_to(self.entries.len() * 2);
+        }
+    }
+}
+
+// Maps this small are not worth rehashing to save memory
+const SHRINK_MIN_CAPACITY: usize = 1024;
+
+/// Values with a size in bytes, for [`Cache::with_max_value_size`]
+pub trait Weigh {
+    /// The approximate number of bytes the value occupies
+    fn weight(&self) -> usize;
+}
+
+impl Weigh for String {
+    fn weight(&self) -> usize {
+        self.len()
+    }
+}
+
+impl Weigh for &str {
+    fn weight(&self) -> usize {
+        self.len()
+    }
+}
+
+impl Weigh for Vec<u8> {
+    fn weight(&self) -> usize {
+        self.len()
+    }
+}
+
+// MEMORY_CACHE_DISABLED=1 (or true) turns every new or loaded cache into a pass-through
+#[cfg(feature = "std")]
+pub(crate) fn disabled_by_env() -> bool {
+    matches!(
+        std::env::var("MEMORY_CACHE_DISABLED").as_deref(),
+        Ok("1") | Ok("true")
+    )
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn disabled_by_env() -> bool {
+    false
+}
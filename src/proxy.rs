@@ -0,0 +1,532 @@
+//! A small caching forward proxy for development
+//!
+//! [`Proxy`] accepts HTTP/1.1 requests on a local socket and forwards them
+//! to one upstream server. Successful `GET` responses are cached by URL for
+//! as long as their `Cache-Control` or `Expires` headers allow, or for a
+//! fixed TTL that overrides them, and concurrent requests for the same URL
+//! share a single upstream request. Responses carrying a `Vary` header are
+//! never stored, since the cache is keyed by URL only. Every response gets
+//! an `X-Cache: HIT` or `X-Cache: MISS` header.
+//!
+//! Only plain `http://` upstreams are supported, as the crate has no TLS
+//! stack; connections are closed after each response.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::SharedCache;
+
+// Guards against clients and upstreams that never finish their headers
+const MAX_HEAD_LINES: usize = 100;
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+// Headers that describe a single connection and are not forwarded
+const HOP_BY_HOP: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// A buffered upstream response as stored in the cache
+#[derive(Debug, Clone)]
+pub struct ProxyResponse {
+    status: u16,
+    reason: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl ProxyResponse {
+    /// The status code of the response
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The first value of the header `name`, compared case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header(&self.headers, name)
+    }
+
+    /// The buffered body of the response
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+}
+
+// The outcome of an upstream request, handed to the requests that waited on it
+#[derive(Debug, Default)]
+struct Flight {
+    response: Mutex<Option<Result<ProxyResponse, String>>>,
+    done: Condvar,
+}
+
+/// A forward proxy to one upstream server that caches its `GET` responses
+///
+/// Clones share the cache and the requests in flight.
+///
+/// # Example
+///
+/// ```
+/// use std::io::{Read, Write};
+/// use std::net::{TcpListener, TcpStream};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use memory_cache::proxy::Proxy;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// // An upstream that counts the requests it serves
+/// let upstream = TcpListener::bind("127.0.0.1:0")?;
+/// let address = upstream.local_addr()?;
+/// let served = Arc::new(AtomicUsize::new(0));
+/// let counter = served.clone();
+/// std::thread::spawn(move || {
+///     for stream in upstream.incoming() {
+///         let mut stream = stream.unwrap();
+///         stream.read(&mut [0; 1024]).unwrap();
+///         counter.fetch_add(1, Ordering::SeqCst);
+///         let response = "HTTP/1.1 200 OK\r\nCache-Control: max-age=60\r\nContent-Length: 2\r\n\r\nok";
+///         stream.write_all(response.as_bytes()).unwrap();
+///     }
+/// });
+///
+/// let proxy = Proxy::new(&format!("http://{}", address))?.with_default_ttl(Duration::from_secs(5));
+/// let listener = TcpListener::bind("127.0.0.1:0")?;
+/// let local = listener.local_addr()?;
+/// std::thread::spawn(move || proxy.serve(listener));
+///
+/// for expected in ["X-Cache: MISS", "X-Cache: HIT"] {
+///     let mut client = TcpStream::connect(local)?;
+///     client.write_all(b"GET /config HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+///     let mut response = String::new();
+///     client.read_to_string(&mut response)?;
+///     assert!(response.contains(expected));
+///     assert!(response.ends_with("\r\n\r\nok"));
+/// }
+/// assert_eq!(served.load(Ordering::SeqCst), 1);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Proxy {
+    host: String,
+    port: u16,
+    base_path: String,
+    cache: SharedCache<ProxyResponse>,
+    ttl: Option<Duration>,
+    default_ttl: Duration,
+    in_flight: Arc<Mutex<HashMap<String, Arc<Flight>>>>,
+}
+
+impl Proxy {
+    /// Creates a proxy to `upstream`, an `http://` URL whose path is prefixed to every request's
+    ///
+    /// Fails for other schemes, including `https://`.
+    pub fn new(upstream: &str) -> Result<Self> {
+        let Some(rest) = upstream.strip_prefix("http://") else {
+            if upstream.starts_with("https://") {
+                bail!(
+                    "'{}' needs TLS, which this build does not support; use an http:// upstream",
+                    upstream
+                );
+            }
+            bail!("'{}' is not an http:// URL", upstream);
+        };
+        let (authority, base_path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port
+                    .parse()
+                    .with_context(|| format!("'{}' has an invalid port", upstream))?;
+                (host, port)
+            }
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            bail!("'{}' has no host", upstream);
+        }
+        Ok(Proxy {
+            host: host.to_string(),
+            port,
+            base_path: base_path.trim_end_matches('/').to_string(),
+            cache: SharedCache::new(),
+            ttl: None,
+            default_ttl: Duration::ZERO,
+            in_flight: Arc::default(),
+        })
+    }
+
+    /// Caches every successful `GET` response for `ttl`, whatever its headers say
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Caches responses without `Cache-Control` or `Expires` headers for `ttl` instead of not at all
+    pub fn with_default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = ttl;
+        self
+    }
+
+    /// Returns a handle to the underlying cache, keyed by upstream URL
+    pub fn cache(&self) -> SharedCache<ProxyResponse> {
+        self.cache.clone()
+    }
+
+    /// Serves connections from `listener`, each on its own thread, until accepting fails
+    pub fn serve(&self, listener: TcpListener) -> Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let proxy = self.clone();
+            // Upstream failures have already been answered with a 502, and
+            // there is no one left to tell about broken client connections
+            std::thread::spawn(move || proxy.handle(stream));
+        }
+        Ok(())
+    }
+
+    // Answers the one request on `stream`
+    fn handle(&self, stream: TcpStream) -> Result<()> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let (request_line, headers) = read_head(&mut reader)?;
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            bail!("malformed request line '{}'", request_line);
+        };
+        // A client configured to use us as its proxy sends absolute URLs
+        let path = match target.strip_prefix("http://") {
+            Some(rest) => &rest[rest.find('/').unwrap_or(rest.len())..],
+            None => target,
+        };
+        let path = if path.is_empty() { "/" } else { path };
+
+        let result = if method == "GET" {
+            self.get(path, &headers)
+        } else {
+            let body = read_body(&mut reader, &headers, Framing::Empty)?;
+            self.forward(method, path, &headers, &body)
+                .map(|response| (response, false))
+        };
+        let mut stream = stream;
+        match result {
+            Ok((response, hit)) => write_response(&mut stream, &response, hit, method == "HEAD"),
+            Err(err) => {
+                let body = format!("upstream request failed: {:#}\n", err);
+                let response = ProxyResponse {
+                    status: 502,
+                    reason: "Bad Gateway".to_string(),
+                    headers: vec![("Content-Type".to_string(), "text/plain".to_string())],
+                    body: body.into_bytes(),
+                };
+                write_response(&mut stream, &response, false, false)?;
+                Err(err)
+            }
+        }
+    }
+
+    // Serves a GET from the cache, or from one upstream request shared by
+    // everyone asking for the same URL meanwhile; the flag says whether it
+    // was a hit
+    fn get(&self, path: &str, headers: &[(String, String)]) -> Result<(ProxyResponse, bool)> {
+        let url = self.url(path);
+        let (flight, leader) = {
+            let mut in_flight = self.in_flight.lock().expect("in-flight map poisoned");
+            // Checked under the lock so a request finishing meanwhile is seen either way
+            if let Some(hit) = self.cache.get(&url) {
+                return Ok((hit, true));
+            }
+            match in_flight.get(&url) {
+                Some(flight) => (flight.clone(), false),
+                None => {
+                    let flight = Arc::new(Flight::default());
+                    in_flight.insert(url.clone(), flight.clone());
+                    (flight, true)
+                }
+            }
+        };
+
+        if !leader {
+            let mut response = flight.response.lock().expect("flight poisoned");
+            while response.is_none() {
+                response = flight.done.wait(response).expect("flight poisoned");
+            }
+            let response = response.as_ref().expect("checked above").clone();
+            return response
+                .map(|response| (response, false))
+                .map_err(|err| anyhow!(err));
+        }
+
+        let result = self.forward("GET", path, headers, &[]);
+        if let Ok(response) = &result {
+            let ttl = self.ttl_for(response);
+            if !ttl.is_zero() {
+                self.cache.insert(&url, response.clone(), ttl);
+            }
+        }
+        *flight.response.lock().expect("flight poisoned") =
+            Some(result.as_ref().cloned().map_err(|err| format!("{:#}", err)));
+        flight.done.notify_all();
+        self.in_flight
+            .lock()
+            .expect("in-flight map poisoned")
+            .remove(&url);
+        result.map(|response| (response, false))
+    }
+
+    // How long to cache `response` for; zero means not at all
+    fn ttl_for(&self, response: &ProxyResponse) -> Duration {
+        if response.status != 200 || response.header("vary").is_some() {
+            return Duration::ZERO;
+        }
+        self.ttl
+            .or_else(|| freshness(&response.headers))
+            .unwrap_or(self.default_ttl)
+    }
+
+    fn url(&self, path: &str) -> String {
+        match self.port {
+            80 => format!("http://{}{}{}", self.host, self.base_path, path),
+            port => format!("http://{}:{}{}{}", self.host, port, self.base_path, path),
+        }
+    }
+
+    // Sends one request upstream and buffers the response
+    fn forward(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(String, String)],
+        body: &[u8],
+    ) -> Result<ProxyResponse> {
+        let upstream = TcpStream::connect((self.host.as_str(), self.port))
+            .with_context(|| format!("cannot connect to {}:{}", self.host, self.port))?;
+        upstream.set_read_timeout(Some(TIMEOUT))?;
+        upstream.set_write_timeout(Some(TIMEOUT))?;
+
+        let mut request = format!("{} {}{} HTTP/1.1\r\n", method, self.base_path, path);
+        let host = match self.port {
+            80 => self.host.clone(),
+            port => format!("{}:{}", self.host, port),
+        };
+        request.push_str(&format!("Host: {}\r\nConnection: close\r\n", host));
+        for (name, value) in headers {
+            let lower = name.to_ascii_lowercase();
+            if lower != "host" && lower != "content-length" && !HOP_BY_HOP.contains(&lower.as_str())
+            {
+                request.push_str(&format!("{}: {}\r\n", name, value));
+            }
+        }
+        if !body.is_empty() {
+            request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        request.push_str("\r\n");
+        let mut writer = &upstream;
+        writer.write_all(request.as_bytes())?;
+        writer.write_all(body)?;
+
+        let mut reader = BufReader::new(&upstream);
+        let (status_line, headers) = read_head(&mut reader)?;
+        let mut parts = status_line.splitn(3, ' ');
+        let status = parts
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| anyhow!("malformed status line '{}'", status_line))?;
+        let reason = parts.next().unwrap_or_default().to_string();
+        // Responses to HEAD, 1xx, 204 and 304 never have a body
+        let has_body =
+            method != "HEAD" && !(100..200).contains(&status) && status != 204 && status != 304;
+        let body = if has_body {
+            read_body(&mut reader, &headers, Framing::UntilClose)?
+        } else {
+            Vec::new()
+        };
+        Ok(ProxyResponse {
+            status,
+            reason,
+            headers,
+            body,
+        })
+    }
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+// How long a response may be served from a shared cache according to its
+// headers, or None if they do not say
+fn freshness(headers: &[(String, String)]) -> Option<Duration> {
+    let mut max_age = None;
+    let mut shared_max_age = None;
+    let directives = headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("cache-control"))
+        .flat_map(|(_, value)| value.split(','));
+    for directive in directives {
+        let directive = directive.trim().to_ascii_lowercase();
+        let seconds = |value: &str| {
+            value
+                .trim_matches('"')
+                .parse()
+                .ok()
+                .map(Duration::from_secs)
+        };
+        match directive.split_once('=') {
+            Some(("max-age", value)) => max_age = seconds(value),
+            Some(("s-maxage", value)) => shared_max_age = seconds(value),
+            _ if matches!(directive.as_str(), "no-store" | "no-cache" | "private") => {
+                return Some(Duration::ZERO)
+            }
+            _ => {}
+        }
+    }
+    if let Some(max_age) = shared_max_age.or(max_age) {
+        // Time already spent in caches further upstream counts against it
+        let age = header(headers, "age")
+            .and_then(|age| age.trim().parse().ok())
+            .map_or(Duration::ZERO, Duration::from_secs);
+        return Some(max_age.saturating_sub(age));
+    }
+    let expires = header(headers, "expires")?;
+    // An Expires that cannot be parsed means already expired
+    let Ok(expires) = httpdate::parse_http_date(expires) else {
+        return Some(Duration::ZERO);
+    };
+    let date = header(headers, "date")
+        .and_then(|date| httpdate::parse_http_date(date).ok())
+        .unwrap_or_else(SystemTime::now);
+    Some(expires.duration_since(date).unwrap_or(Duration::ZERO))
+}
+
+// Reads a request or status line and the header lines after it
+fn read_head(reader: &mut impl BufRead) -> Result<(String, Vec<(String, String)>)> {
+    let mut start = String::new();
+    if reader.read_line(&mut start)? == 0 {
+        bail!("connection closed before a message arrived");
+    }
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            bail!("connection closed in the middle of the headers");
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEAD_LINES {
+            bail!("more than {} header lines", MAX_HEAD_LINES);
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| anyhow!("malformed header line '{}'", line))?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+    Ok((start.trim_end().to_string(), headers))
+}
+
+// Where a body ends when neither Transfer-Encoding nor Content-Length says
+enum Framing {
+    // Requests have no body then
+    Empty,
+    // Responses run until the connection closes
+    UntilClose,
+}
+
+// Reads a message body framed by chunked encoding, Content-Length or `framing`
+fn read_body(
+    reader: &mut impl BufRead,
+    headers: &[(String, String)],
+    framing: Framing,
+) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    let chunked = header(headers, "transfer-encoding")
+        .is_some_and(|encoding| encoding.to_ascii_lowercase().contains("chunked"));
+    if chunked {
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            let size = line.split(';').next().unwrap_or_default().trim();
+            let size = usize::from_str_radix(size, 16)
+                .map_err(|_| anyhow!("malformed chunk size '{}'", size))?;
+            if size == 0 {
+                // Trailers are dropped
+                while !matches!(read_line(reader)?.as_str(), "" | "\r\n" | "\n") {}
+                return Ok(body);
+            }
+            let start = body.len();
+            body.resize(start + size, 0);
+            reader.read_exact(&mut body[start..])?;
+            read_line(reader)?;
+        }
+    }
+    match header(headers, "content-length") {
+        Some(length) => {
+            let length: u64 = length
+                .parse()
+                .map_err(|_| anyhow!("malformed Content-Length '{}'", length))?;
+            reader.take(length).read_to_end(&mut body)?;
+            if (body.len() as u64) < length {
+                bail!(
+                    "connection closed after {} of {} body bytes",
+                    body.len(),
+                    length
+                );
+            }
+        }
+        None => {
+            if let Framing::UntilClose = framing {
+                reader.read_to_end(&mut body)?;
+            }
+        }
+    }
+    Ok(body)
+}
+
+fn read_line(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    Ok(line)
+}
+
+fn write_response(
+    stream: &mut TcpStream,
+    response: &ProxyResponse,
+    hit: bool,
+    head: bool,
+) -> Result<()> {
+    let mut head_text = format!("HTTP/1.1 {} {}\r\n", response.status, response.reason);
+    for (name, value) in &response.headers {
+        let lower = name.to_ascii_lowercase();
+        if lower != "content-length" && !HOP_BY_HOP.contains(&lower.as_str()) {
+            head_text.push_str(&format!("{}: {}\r\n", name, value));
+        }
+    }
+    let cache = if hit { "HIT" } else { "MISS" };
+    head_text.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\nX-Cache: {}\r\n\r\n",
+        response.body.len(),
+        cache
+    ));
+    stream.write_all(head_text.as_bytes())?;
+    if !head {
+        stream.write_all(&response.body)?;
+    }
+    stream.flush()?;
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use alloc::format;
+use core::time::Duration;
+
+use crate::{Cache, Counter};
+
+const KEY_PREFIX: &str = "ratelimit";
+
+/// The outcome of [`RateLimiter::check`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// The request is within the limit and has been counted
+    Allowed { remaining: u64 },
+    /// The limit is exhausted; the earliest point at which retrying may succeed
+    Denied { retry_after: Duration },
+}
+
+impl Decision {
+    /// Returns true if the request was allowed
+    pub fn is_allowed(&self) -> bool {
+        matches!(self, Decision::Allowed { .. })
+    }
+}
+
+/// A sliding-window rate limiter backed by TTL'd counters in a [`Cache`]
+///
+/// Requests are counted per fixed window under `ratelimit:<key>:<window>`.
+/// The previous window's count is weighted by how much of it still overlaps
+/// the sliding window, which approximates a true sliding log at the cost of
+/// two counters per key.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::{Decision, RateLimiter};
+/// let mut limiter: RateLimiter = RateLimiter::new(2, Duration::from_secs(60));
+///
+/// assert_eq!(limiter.check("user_xyz"), Decision::Allowed { remaining: 1 });
+/// assert_eq!(limiter.check("user_xyz"), Decision::Allowed { remaining: 0 });
+/// assert!(!limiter.check("user_xyz").is_allowed());
+/// ```
+#[derive(Debug)]
+pub struct RateLimiter<T = u64> {
+    cache: Cache<T>,
+    limit: u64,
+    window: u64,
+}
+
+impl<T: Clone + Counter> RateLimiter<T> {
+    /// Creates a limiter allowing `limit` requests per `window` with its own cache
+    pub fn new(limit: u64, window: Duration) -> Self {
+        Self::from_cache(Cache::new(), limit, window)
+    }
+
+    /// Creates a limiter that keeps its counters in an existing cache
+    ///
+    /// Windows are tracked in whole seconds, with a minimum of one second.
+    /// A disabled cache is re-enabled, since it would allow every request.
+    pub fn from_cache(cache: Cache<T>, limit: u64, window: Duration) -> Self {
+        RateLimiter {
+            cache: cache.with_disabled(false),
+            limit,
+            window: window.as_secs().max(1),
+        }
+    }
+
+    /// Counts a request for `key` if it is within the limit
+    pub fn check(&mut self, key: &str) -> Decision {
+        let now = self.cache.now();
+        let index = now / self.window;
+        let elapsed = now % self.window;
+
+        let current_key = format!("{}:{}:{}", KEY_PREFIX, key, index);
+        let current = self.count(&current_key);
+        let previous = match index.checked_sub(1) {
+            Some(previous) => self.count(&format!("{}:{}:{}", KEY_PREFIX, key, previous)),
+            None => 0,
+        };
+
+        // Weight the previous window by the part still inside the sliding window
+        let estimate = previous.saturating_mul(self.window - elapsed) / self.window + current;
+        if estimate >= self.limit {
+            return Decision::Denied {
+                retry_after: Duration::from_secs(self.window - elapsed),
+            };
+        }
+
+        self.cache
+            .increment(&current_key, 1, Duration::from_secs(self.window * 2));
+        Decision::Allowed {
+            remaining: self.limit - estimate - 1,
+        }
+    }
+
+    /// Returns the cache holding the counters, e.g. to persist it
+    pub fn into_cache(self) -> Cache<T> {
+        self.cache
+    }
+
+    fn count(&mut self, key: &str) -> u64 {
+        self.cache
+            .get(key)
+            .and_then(|value| value.count())
+            .unwrap_or(0)
+    }
+}
@@ -0,0 +1,183 @@
+use core::hash::BuildHasher;
+use std::collections::hash_map::RandomState;
+use std::thread;
+use std::time::Duration;
+
+use crate::Cache;
+
+/// How often and how patiently to retry a failing operation, such as a loader or a save
+///
+/// Retry `n` waits `initial` doubled `n - 1` times, capped at `max`; with
+/// jitter, which is on by default, a random time between half of that and
+/// all of it, so that callers failing together do not retry together.
+///
+/// # Example
+///
+/// ```
+/// use std::io::{Error, ErrorKind};
+/// use std::time::Duration;
+/// use memory_cache::RetryPolicy;
+///
+/// let policy = RetryPolicy::new(3).with_backoff(Duration::from_millis(1), Duration::from_millis(10));
+/// let mut calls = 0;
+/// let result = policy.retry(
+///     || {
+///         calls += 1;
+///         if calls < 3 {
+///             Err(Error::from(ErrorKind::TimedOut))
+///         } else {
+///             Ok("loaded")
+///         }
+///     },
+///     |err| err.kind() == ErrorKind::TimedOut,
+/// );
+/// assert_eq!(result.unwrap(), "loaded");
+/// assert_eq!(calls, 3);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial: Duration,
+    max: Duration,
+    jitter: bool,
+}
+
+impl RetryPolicy {
+    /// Makes up to `max_attempts` attempts in all, backing off from 100ms to at most 10s
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_attempts` is zero.
+    pub fn new(max_attempts: u32) -> Self {
+        assert!(
+            max_attempts > 0,
+            "a retry policy needs at least one attempt"
+        );
+        RetryPolicy {
+            max_attempts,
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(10),
+            jitter: true,
+        }
+    }
+
+    /// Waits `initial` before the first retry, doubling up to `max` for later ones
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial = initial;
+        self.max = max.max(initial);
+        self
+    }
+
+    /// Waits exactly the backoff between attempts, e.g. for reproducible tests
+    pub fn without_jitter(mut self) -> Self {
+        self.jitter = false;
+        self
+    }
+
+    /// The most attempts an operation gets, the first included
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// How long to wait before retry `retry`, counting from 1, without jitter
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let doublings = retry.saturating_sub(1).min(31);
+        self.initial.saturating_mul(1 << doublings).min(self.max)
+    }
+
+    /// Runs `op` until it succeeds, fails with an error `retryable` refuses, or runs out of attempts
+    ///
+    /// Returns the last result.
+    pub fn retry<T, E>(
+        &self,
+        op: impl FnMut() -> Result<T, E>,
+        retryable: impl Fn(&E) -> bool,
+    ) -> Result<T, E> {
+        self.retry_counting(&mut 0, op, retryable)
+    }
+
+    /// Retries as [`RetryPolicy::retry`] does, adding the number of retries made to `retries`
+    pub fn retry_counting<T, E>(
+        &self,
+        retries: &mut u64,
+        mut op: impl FnMut() -> Result<T, E>,
+        retryable: impl Fn(&E) -> bool,
+    ) -> Result<T, E> {
+        let mut attempt = 1;
+        loop {
+            match op() {
+                Err(err) if attempt < self.max_attempts && retryable(&err) => {
+                    thread::sleep(self.delay(attempt));
+                    attempt += 1;
+                    *retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn delay(&self, retry: u32) -> Duration {
+        let backoff = self.backoff(retry);
+        if !self.jitter {
+            return backoff;
+        }
+        // Every RandomState is freshly keyed, which is random enough here
+        let random = RandomState::new().hash_one(retry);
+        let half = backoff / 2;
+        half + Duration::from_nanos(random % (half.as_nanos() as u64).saturating_add(1))
+    }
+}
+
+impl<T: Clone, S: BuildHasher> Cache<T, S> {
+    /// Like [`Cache::try_get_or_insert_with`], retrying a failed load as `policy` says
+    ///
+    /// Only errors `retryable` accepts are retried. The cache stays borrowed
+    /// while the load backs off, so under a
+    /// [`SharedCache`](crate::SharedCache) this keeps the lock. Retries are
+    /// counted in [`CacheStats::retries`](crate::CacheStats::retries).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::{Cache, RetryPolicy};
+    /// let mut cache = Cache::new().with_stats();
+    /// let policy = RetryPolicy::new(2).with_backoff(Duration::from_millis(1), Duration::from_millis(1));
+    ///
+    /// let mut flaky = vec![Ok("hello".to_string()), Err("connection reset")];
+    /// let value = cache.try_get_or_insert_with_retry(
+    ///     "greeting",
+    ///     Duration::from_secs(60),
+    ///     &policy,
+    ///     || flaky.pop().unwrap(),
+    ///     |_| true,
+    /// );
+    /// assert_eq!(value.as_deref(), Ok("hello"));
+    /// assert_eq!(cache.stats().retries, 1);
+    /// ```
+    pub fn try_get_or_insert_with_retry<F, E>(
+        &mut self,
+        key: &str,
+        ttl: Duration,
+        policy: &RetryPolicy,
+        load: F,
+        retryable: impl Fn(&E) -> bool,
+    ) -> Result<T, E>
+    where
+        F: FnMut() -> Result<T, E>,
+    {
+        let mut retries = 0;
+        let result = self.try_get_or_insert_with(key, ttl, || {
+            policy.retry_counting(&mut retries, load, retryable)
+        });
+        self.record_retries(retries);
+        result
+    }
+
+    /// Adds `retries` made on the cache's behalf, such as retried loads of its state file, to [`CacheStats::retries`](crate::CacheStats::retries)
+    pub fn record_retries(&mut self, retries: u64) {
+        if let Some(stats) = &mut self.stats {
+            stats.retries += retries;
+        }
+    }
+}
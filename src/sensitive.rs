@@ -0,0 +1,73 @@
+use core::fmt;
+use core::time::Duration;
+
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::Cache;
+
+/// A cache for secrets that wipes values from memory when they leave it
+///
+/// Values are zeroized when they are overwritten, expire, are invalidated or
+/// the cache is dropped, and copies handed out by [`get`](SensitiveCache::get)
+/// are zeroized when the caller drops them. `Debug` output lists keys only.
+///
+/// Only heap-allocated values such as `String` or `Vec<u8>` are fully
+/// covered: inline values like integers can be left behind in memory when the
+/// map grows and moves its entries. Keys are not treated as secret.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::SensitiveCache;
+/// let mut secrets = SensitiveCache::new();
+/// secrets.insert("api_key", "secret123".to_string(), Duration::from_secs(30));
+///
+/// assert_eq!(secrets.get("api_key").as_deref().map(String::as_str), Some("secret123"));
+/// assert!(!format!("{:?}", secrets).contains("secret123"));
+///
+/// // The stored copy is zeroized as it is removed
+/// secrets.invalidate("api_key");
+/// ```
+pub struct SensitiveCache<T: Zeroize> {
+    cache: Cache<Zeroizing<T>>,
+}
+
+impl<T: Clone + Zeroize> SensitiveCache<T> {
+    /// Creates a new empty cache
+    pub fn new() -> Self {
+        SensitiveCache {
+            cache: Cache::new(),
+        }
+    }
+
+    /// Inserts a value into the cache with a specified TTL
+    pub fn insert(&mut self, key: &str, value: T, ttl: Duration) {
+        self.cache.insert(key, Zeroizing::new(value), ttl);
+    }
+
+    /// Retrieves a copy of a value that is zeroized when dropped, or None if
+    /// expired or not found
+    pub fn get(&mut self, key: &str) -> Option<Zeroizing<T>> {
+        self.cache.get(key)
+    }
+
+    /// Removes and zeroizes an entry
+    pub fn invalidate(&mut self, key: &str) {
+        self.cache.invalidate(key);
+    }
+}
+
+impl<T: Clone + Zeroize> Default for SensitiveCache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Zeroize> fmt::Debug for SensitiveCache<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SensitiveCache")
+            .field("keys", &self.cache.entries.keys())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,202 @@
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{Cache, SharedCache};
+
+const KEY_PREFIX: &str = "session:";
+
+/// Server-side session storage with sliding expiration
+///
+/// Session payloads are stored as JSON strings under `session:<id>`, so any
+/// serde type can be used and a persisted string cache keeps sessions across
+/// restarts. Every successful [`load`](SessionStore::load) or
+/// [`refresh`](SessionStore::refresh) pushes the expiry out by the TTL again.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use serde::{Deserialize, Serialize};
+/// use memory_cache::SessionStore;
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Login {
+///     user: String,
+/// }
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let sessions = SessionStore::new(Duration::from_secs(1800));
+/// let id = sessions.create(&Login { user: "alice".into() })?;
+///
+/// let login: Login = sessions.load(&id)?.unwrap();
+/// assert_eq!(login.user, "alice");
+///
+/// sessions.destroy(&id);
+/// assert!(sessions.load::<Login>(&id)?.is_none());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    cache: SharedCache<String>,
+    ttl: Duration,
+}
+
+impl SessionStore {
+    /// Creates a session store whose sessions expire after `ttl` of inactivity
+    pub fn new(ttl: Duration) -> Self {
+        Self::with_cache(
+            SharedCache::from_cache(Cache::new().with_disabled(false)),
+            ttl,
+        )
+    }
+
+    /// Creates a session store that keeps sessions in an existing cache
+    ///
+    /// Sessions cannot be created while `cache` is disabled.
+    pub fn with_cache(cache: SharedCache<String>, ttl: Duration) -> Self {
+        SessionStore { cache, ttl }
+    }
+
+    /// Stores a new session and returns its randomly generated id
+    pub fn create<T: Serialize>(&self, data: &T) -> Result<String> {
+        let payload = serde_json::to_string(data)?;
+        loop {
+            let id = new_session_id()?;
+            let mut cache = self.cache.lock();
+            if cache.is_disabled() {
+                bail!("the session cache is disabled");
+            }
+            if cache.insert_if_absent(&key(&id), payload.clone(), self.ttl) {
+                return Ok(id);
+            }
+        }
+    }
+
+    /// Loads a session, extending its lifetime, or returns `None` if it expired
+    pub fn load<T: DeserializeOwned>(&self, id: &str) -> Result<Option<T>> {
+        let mut cache = self.cache.lock();
+        let key = key(id);
+        match cache.get(&key) {
+            Some(payload) => {
+                let data = serde_json::from_str(&payload)?;
+                cache.insert(&key, payload, self.ttl);
+                Ok(Some(data))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Replaces the payload of a live session, returning false if it expired
+    pub fn save<T: Serialize>(&self, id: &str, data: &T) -> Result<bool> {
+        let payload = serde_json::to_string(data)?;
+        let mut cache = self.cache.lock();
+        let key = key(id);
+        if cache.get(&key).is_none() {
+            return Ok(false);
+        }
+        cache.insert(&key, payload, self.ttl);
+        Ok(true)
+    }
+
+    /// Extends the lifetime of a live session, returning false if it expired
+    pub fn refresh(&self, id: &str) -> bool {
+        let mut cache = self.cache.lock();
+        let key = key(id);
+        match cache.get(&key) {
+            Some(payload) => {
+                cache.insert(&key, payload, self.ttl);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes a session
+    pub fn destroy(&self, id: &str) {
+        self.cache.invalidate(&key(id));
+    }
+}
+
+fn key(id: &str) -> String {
+    format!("{}{}", KEY_PREFIX, id)
+}
+
+// 128 random bits, hex encoded
+fn new_session_id() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes).map_err(|err| anyhow::anyhow!("no randomness: {}", err))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+#[cfg(feature = "tower-sessions")]
+mod tower_sessions_adapter {
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use time::OffsetDateTime;
+    use tower_sessions_core::session::{Id, Record};
+    use tower_sessions_core::session_store::{self, Error};
+
+    use super::{key, SessionStore};
+
+    impl SessionStore {
+        fn store_record(
+            &self,
+            record: &Record,
+            only_if_absent: bool,
+        ) -> session_store::Result<bool> {
+            let payload =
+                serde_json::to_string(record).map_err(|err| Error::Encode(err.to_string()))?;
+            let ttl = (record.expiry_date - OffsetDateTime::now_utc())
+                .try_into()
+                .unwrap_or(Duration::ZERO);
+
+            let mut cache = self.cache.lock();
+            if cache.is_disabled() {
+                return Err(Error::Backend("the session cache is disabled".into()));
+            }
+            let key = key(&record.id.to_string());
+            if only_if_absent {
+                return Ok(cache.insert_if_absent(&key, payload, ttl));
+            }
+            cache.insert(&key, payload, ttl);
+            Ok(true)
+        }
+    }
+
+    /// Backs [`tower_sessions`](https://docs.rs/tower-sessions) with this cache
+    ///
+    /// Records expire at their own `expiry_date`; the store's TTL is not used.
+    #[async_trait]
+    impl session_store::SessionStore for SessionStore {
+        async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+            while !self.store_record(record, true)? {
+                record.id = Id::default();
+            }
+            Ok(())
+        }
+
+        async fn save(&self, record: &Record) -> session_store::Result<()> {
+            self.store_record(record, false)?;
+            Ok(())
+        }
+
+        async fn load(&self, id: &Id) -> session_store::Result<Option<Record>> {
+            match self.cache.get(&key(&id.to_string())) {
+                Some(payload) => serde_json::from_str(&payload)
+                    .map(Some)
+                    .map_err(|err| Error::Decode(err.to_string())),
+                None => Ok(None),
+            }
+        }
+
+        async fn delete(&self, id: &Id) -> session_store::Result<()> {
+            self.destroy(&id.to_string());
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,579 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, TryLockError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::{Cache, CacheError, CacheEventKind};
+
+// The longest a timed lock sleeps between attempts
+const MAX_LOCK_BACKOFF: Duration = Duration::from_millis(1);
+
+/// A cheaply cloneable, thread-safe handle to a [`Cache`]
+///
+/// All clones refer to the same underlying cache, which makes the handle
+/// suitable for sharing between threads or as web framework state.
+///
+/// A panic on a thread holding the lock, such as in a loader or an insert
+/// hook, does not make the cache unusable for the other handles. The cache
+/// runs loaders, hooks and value indexers before it changes an entry or an
+/// index, so both are left as they were, and a loader panic is reported as a
+/// [`CacheEventKind::Panic`](crate::CacheEventKind::Panic) event.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::SharedCache;
+/// let cache = SharedCache::new();
+/// let handle = cache.clone();
+///
+/// std::thread::spawn(move || {
+///     handle.insert("session", "token123", Duration::from_secs(60));
+/// })
+/// .join()
+/// .unwrap();
+///
+/// assert_eq!(cache.get("session"), Some("token123"));
+/// ```
+#[derive(Debug)]
+pub struct SharedCache<T> {
+    inner: Arc<Mutex<Cache<T>>>,
+    // Keys being loaded by SharedCache::prefetch or
+    // SharedCache::try_get_or_insert_with_timeout, with the load the other
+    // callers for the key wait on
+    loading: Arc<Mutex<HashMap<String, Arc<Flight>>>>,
+}
+
+impl<T: Clone> SharedCache<T> {
+    /// Creates a handle to a new empty cache
+    pub fn new() -> Self {
+        Self::from_cache(Cache::new())
+    }
+
+    /// Wraps an existing cache, e.g. one returned by `load_cache`
+    pub fn from_cache(cache: Cache<T>) -> Self {
+        SharedCache {
+            inner: Arc::new(Mutex::new(cache)),
+            loading: Arc::default(),
+        }
+    }
+
+    /// Inserts a value into the cache with a specified TTL
+    pub fn insert(&self, key: &str, value: T, ttl: Duration) {
+        self.lock().insert(key, value, ttl);
+    }
+
+    /// Retrieves a value from the cache, returning None if expired or not found
+    pub fn get(&self, key: &str) -> Option<T> {
+        self.lock().get(key)
+    }
+
+    /// Retrieves a value as [`SharedCache::get`] does, giving up if the lock is not free within `timeout`
+    pub fn get_timeout(&self, key: &str, timeout: Duration) -> Result<Option<T>, CacheError> {
+        match self.lock_before(deadline_after(timeout)) {
+            Some(mut cache) => Ok(cache.get(key)),
+            None => Err(CacheError::Timeout {
+                key: Some(key.to_string()),
+                waited: timeout,
+            }),
+        }
+    }
+
+    /// Retrieves the value of the first of `keys` with a live entry, under one lock, as [`Cache::get_first`] does
+    pub fn get_first<'k>(&self, keys: &[&'k str]) -> Option<(&'k str, T)> {
+        self.lock().get_first(keys)
+    }
+
+    /// Manually removes an entry from the cache
+    pub fn invalidate(&self, key: &str) {
+        self.lock().invalidate(key);
+    }
+
+    /// Removes the live value under `key` and returns it, as [`Cache::take`] does
+    pub fn take(&self, key: &str) -> Option<T> {
+        self.lock().take(key)
+    }
+
+    /// Inserts a value that is invalidated when the returned guard is dropped
+    ///
+    /// Ties an entry to the lifetime of a request or work item, such as a
+    /// temporary reservation. The entry still expires after `ttl` if the
+    /// guard outlives it. Dropping the guard leaves alone a value written
+    /// under `key` since, and the guard does nothing if the insert was
+    /// rejected.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::SharedCache;
+    /// let cache = SharedCache::new();
+    ///
+    /// {
+    ///     let _reservation = cache.insert_scoped("seat/14C", "order-991", Duration::from_secs(300));
+    ///     assert_eq!(cache.get("seat/14C"), Some("order-991"));
+    /// }
+    /// assert_eq!(cache.get("seat/14C"), None);
+    ///
+    /// // Completing the order keeps the entry
+    /// let reservation = cache.insert_scoped("seat/14C", "order-992", Duration::from_secs(300));
+    /// reservation.keep();
+    /// assert_eq!(cache.get("seat/14C"), Some("order-992"));
+    /// ```
+    pub fn insert_scoped(&self, key: &str, value: T, ttl: Duration) -> ScopeGuard<T> {
+        let mut cache = self.lock();
+        let version = match cache.try_insert(key, value, ttl) {
+            Ok(()) => cache.version(key),
+            Err(_) => None,
+        };
+        ScopeGuard {
+            cache: self.clone(),
+            key: key.to_string(),
+            version,
+        }
+    }
+
+    /// Loads the values of `keys` on a background thread, so later reads of them hit
+    ///
+    /// Keys that already have a live entry or are being loaded, by an
+    /// earlier call or by [`SharedCache::try_get_or_insert_with_timeout`],
+    /// are skipped; the rest are claimed before this returns
+    /// and loaded one after another. A loaded value is not stored if the key
+    /// was written meanwhile, and failed loads are dropped, since nobody is
+    /// waiting for them. The returned handle yields how many values were
+    /// stored and can be ignored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::SharedCache;
+    /// let cache = SharedCache::new();
+    ///
+    /// // The user is likely to open these next
+    /// let pages = ["docs/intro", "docs/install", "docs/faq"];
+    /// let prefetch = cache.prefetch(pages, Duration::from_secs(300), |key: &str| {
+    ///     Ok::<_, std::io::Error>(format!("<rendered {}>", key))
+    /// });
+    ///
+    /// assert_eq!(prefetch.join().unwrap(), 3);
+    /// assert_eq!(cache.get("docs/faq"), Some("<rendered docs/faq>".to_string()));
+    ///
+    /// // A timed load of a key being prefetched waits for the prefetch
+    /// cache.prefetch(["docs/api"], Duration::from_secs(300), |key: &str| {
+    ///     std::thread::sleep(Duration::from_millis(100));
+    ///     Ok::<_, std::io::Error>(format!("<rendered {}>", key))
+    /// });
+    /// let page = cache.try_get_or_insert_with_timeout("docs/api", Duration::from_secs(300), Duration::from_secs(5), || {
+    ///     Ok::<_, memory_cache::CacheError>("<rendered twice>".to_string())
+    /// });
+    /// assert_eq!(page, Ok("<rendered docs/api>".to_string()));
+    /// ```
+    pub fn prefetch<I, F, E>(&self, keys: I, ttl: Duration, loader: F) -> JoinHandle<usize>
+    where
+        T: Send + 'static,
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        F: Fn(&str) -> Result<T, E> + Send + 'static,
+    {
+        let mut claimed: Vec<(String, Arc<Flight>)> = {
+            let cache = self.lock();
+            let mut loading = unpoisoned(&self.loading);
+            keys.into_iter()
+                .filter(|key| cache.ttl(key.as_ref()).is_none())
+                .filter_map(|key| match loading.entry(key.as_ref().to_string()) {
+                    Entry::Occupied(_) => None,
+                    Entry::Vacant(vacant) => {
+                        let flight = vacant.insert(Arc::default()).clone();
+                        Some((key.as_ref().to_string(), flight))
+                    }
+                })
+                .collect()
+        };
+        // Taken from the back, so loaded in the order given
+        claimed.reverse();
+        let mut claims = Claims {
+            cache: self.clone(),
+            keys: claimed,
+        };
+        std::thread::spawn(move || {
+            let mut stored = 0;
+            while let Some((key, flight)) = claims.keys.last().cloned() {
+                let loaded = loader(&key);
+                // Stored and released under one lock, as the timed loads expect
+                let mut cache = claims.cache.lock();
+                if let Ok(value) = loaded {
+                    if cache.insert_if_absent(&key, value, ttl) {
+                        stored += 1;
+                    }
+                }
+                unpoisoned(&claims.cache.loading).remove(&key);
+                drop(cache);
+                flight.finish();
+                claims.keys.pop();
+            }
+            stored
+        })
+    }
+
+    /// Returns the value under `key`, or loads it with `load` without holding the lock, giving up after `timeout`
+    ///
+    /// Unlike [`Cache::try_get_or_insert_with`] under [`SharedCache::lock`],
+    /// a slow load does not keep other handles waiting for the lock. The
+    /// load runs on a thread of its own; if it has not finished by the
+    /// deadline, this returns [`CacheError::Timeout`] converted into `E`,
+    /// and the load's value is still stored when it arrives unless the key
+    /// was written meanwhile. The timeout covers waiting for the lock too.
+    ///
+    /// Only one load per key runs at a time: callers that miss while a
+    /// load for the key is in flight, including one whose caller has
+    /// already timed out and one started by [`SharedCache::prefetch`], wait
+    /// for it instead of calling the origin again.
+    /// If that load fails, the next waiter makes its own.
+    /// A panic in `load` is reported as a
+    /// [`CacheEventKind::Panic`](crate::CacheEventKind::Panic) event
+    /// whenever it happens, and passed on to the caller if it is still
+    /// waiting.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    /// use memory_cache::{CacheError, SharedCache};
+    /// let cache = SharedCache::new();
+    /// let calls = Arc::new(AtomicUsize::new(0));
+    ///
+    /// let slow_origin = |calls: Arc<AtomicUsize>| move || {
+    ///     calls.fetch_add(1, Ordering::SeqCst);
+    ///     std::thread::sleep(Duration::from_millis(200));
+    ///     Ok::<_, CacheError>("fresh")
+    /// };
+    /// let result = cache.try_get_or_insert_with_timeout(
+    ///     "report",
+    ///     Duration::from_secs(60),
+    ///     Duration::from_millis(10),
+    ///     slow_origin(calls.clone()),
+    /// );
+    /// assert!(matches!(result, Err(CacheError::Timeout { .. })));
+    ///
+    /// // The next caller waits for the load already in flight
+    /// let result = cache.try_get_or_insert_with_timeout(
+    ///     "report",
+    ///     Duration::from_secs(60),
+    ///     Duration::from_secs(5),
+    ///     slow_origin(calls.clone()),
+    /// );
+    /// assert_eq!(result, Ok("fresh"));
+    /// assert_eq!(calls.load(Ordering::SeqCst), 1);
+    /// ```
+    pub fn try_get_or_insert_with_timeout<F, E>(
+        &self,
+        key: &str,
+        ttl: Duration,
+        timeout: Duration,
+        load: F,
+    ) -> Result<T, E>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T, E> + Send + 'static,
+        E: From<CacheError> + Send + 'static,
+    {
+        let deadline = deadline_after(timeout);
+        let timed_out = || CacheError::Timeout {
+            key: Some(key.to_string()),
+            waited: timeout,
+        };
+        let flight = loop {
+            let Some(mut cache) = self.lock_before(deadline) else {
+                return Err(timed_out().into());
+            };
+            if let Some(value) = cache.get(key) {
+                return Ok(value);
+            }
+            // Checked under the cache lock, which a finished load holds
+            // while it stores its value and leaves `loading`
+            let joined = match unpoisoned(&self.loading).entry(key.to_string()) {
+                Entry::Occupied(occupied) => occupied.get().clone(),
+                Entry::Vacant(vacant) => break vacant.insert(Arc::default()).clone(),
+            };
+            drop(cache);
+            // Once it finishes the value is read from the cache, or, if the
+            // load failed, this caller loads it itself
+            if !joined.wait_before(deadline) {
+                return Err(timed_out().into());
+            }
+        };
+        let (sender, receiver) = mpsc::channel();
+        let cache = self.clone();
+        let owned_key = key.to_string();
+        let loader = thread::spawn(move || {
+            let outcome = panic::catch_unwind(AssertUnwindSafe(load));
+            let mut guard = cache.lock();
+            // Ends the flight under the cache lock, even if storing panics
+            let landing = Landing {
+                cache: &cache,
+                key: &owned_key,
+                flight,
+            };
+            match &outcome {
+                Ok(Ok(value)) => {
+                    guard.insert_if_absent(&owned_key, value.clone(), ttl);
+                }
+                Ok(Err(_)) => {}
+                // Reported here, as the caller may have stopped waiting
+                Err(_) => {
+                    let now = guard.now();
+                    guard
+                        .feed
+                        .emit(CacheEventKind::Panic, &owned_key, now, None);
+                }
+            }
+            drop(landing);
+            drop(guard);
+            match outcome {
+                // The caller may have stopped waiting
+                Ok(result) => {
+                    let _ = sender.send(result);
+                }
+                Err(payload) => panic::resume_unwind(payload),
+            }
+        });
+        let received = match deadline {
+            Some(deadline) => {
+                receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => Err(timed_out().into()),
+            // The loader panicked before sending
+            Err(RecvTimeoutError::Disconnected) => match loader.join() {
+                Err(payload) => panic::resume_unwind(payload),
+                Ok(()) => unreachable!("the loader sends before it returns"),
+            },
+        }
+    }
+
+    /// Locks the cache as [`SharedCache::lock`] does, giving up if the lock is not free within `timeout`
+    ///
+    /// A `timeout` too long to give a deadline, such as `Duration::MAX`,
+    /// waits as long as [`SharedCache::lock`] would; so do the other
+    /// `_timeout` methods.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::SharedCache;
+    /// let cache: SharedCache<&str> = SharedCache::new();
+    ///
+    /// cache.lock_timeout(Duration::MAX)?.insert("motd", "hello", Duration::from_secs(60));
+    /// assert_eq!(cache.get_timeout("motd", Duration::MAX)?, Some("hello"));
+    /// # Ok::<(), memory_cache::CacheError>(())
+    /// ```
+    pub fn lock_timeout(&self, timeout: Duration) -> Result<MutexGuard<'_, Cache<T>>, CacheError> {
+        self.lock_before(deadline_after(timeout))
+            .ok_or(CacheError::Timeout {
+                key: None,
+                waited: timeout,
+            })
+    }
+
+    // std's Mutex has no timed lock, so this polls, backing off up to
+    // MAX_LOCK_BACKOFF between attempts; without a deadline it just locks
+    fn lock_before(&self, deadline: Option<Instant>) -> Option<MutexGuard<'_, Cache<T>>> {
+        let Some(deadline) = deadline else {
+            return Some(self.lock());
+        };
+        let mut backoff = Duration::from_micros(10);
+        loop {
+            match self.inner.try_lock() {
+                Ok(cache) => return Some(cache),
+                Err(TryLockError::Poisoned(poisoned)) => {
+                    self.inner.clear_poison();
+                    return Some(poisoned.into_inner());
+                }
+                Err(TryLockError::WouldBlock) => {}
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            thread::sleep(backoff.min(deadline - now));
+            backoff = (backoff * 2).min(MAX_LOCK_BACKOFF);
+        }
+    }
+
+    /// Locks the cache for a sequence of operations that must not interleave
+    /// with other handles
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::{CacheEventKind, SharedCache};
+    /// let cache = SharedCache::new();
+    ///
+    /// let mut guard = cache.lock();
+    /// if guard.get("counter").is_none() {
+    ///     guard.insert("counter", 1, Duration::from_secs(60));
+    /// }
+    /// let events = guard.subscribe();
+    /// drop(guard);
+    ///
+    /// // A loader panicking under the lock leaves the cache usable
+    /// let handle = cache.clone();
+    /// let panicked = std::thread::spawn(move || {
+    ///     handle.lock().get_or_insert_with("report", Duration::from_secs(60), || panic!("origin down"))
+    /// })
+    /// .join();
+    /// assert!(panicked.is_err());
+    /// assert_eq!(cache.get("counter"), Some(1));
+    /// assert_eq!(events.try_recv().map(|event| event.kind), Ok(CacheEventKind::Panic));
+    /// ```
+    pub fn lock(&self) -> MutexGuard<'_, Cache<T>> {
+        unpoisoned(&self.inner)
+    }
+}
+
+impl<T: Clone> Default for SharedCache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for SharedCache<T> {
+    fn clone(&self) -> Self {
+        SharedCache {
+            inner: self.inner.clone(),
+            loading: self.loading.clone(),
+        }
+    }
+}
+
+/// Invalidates the entry inserted by [`SharedCache::insert_scoped`] when dropped
+#[derive(Debug)]
+#[must_use = "the entry is invalidated as soon as the guard is dropped"]
+pub struct ScopeGuard<T: Clone> {
+    cache: SharedCache<T>,
+    key: String,
+    // The version of the entry the guard owns; None once there is nothing to invalidate
+    version: Option<u64>,
+}
+
+impl<T: Clone> ScopeGuard<T> {
+    /// The key of the guarded entry
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Returns true if the guarded entry is still live and has not been overwritten
+    pub fn is_live(&self) -> bool {
+        self.version.is_some() && self.cache.lock().version(&self.key) == self.version
+    }
+
+    /// Keeps the entry until its TTL lapses instead of invalidating it
+    pub fn keep(mut self) {
+        self.version = None;
+    }
+}
+
+impl<T: Clone> Drop for ScopeGuard<T> {
+    fn drop(&mut self) {
+        let Some(version) = self.version else {
+            return;
+        };
+        let mut cache = self.cache.lock();
+        if cache.version(&self.key) == Some(version) {
+            cache.invalidate(&self.key);
+        }
+    }
+}
+
+// The keys a prefetch has yet to load, released if its loader panics
+struct Claims<T> {
+    cache: SharedCache<T>,
+    keys: Vec<(String, Arc<Flight>)>,
+}
+
+impl<T> Drop for Claims<T> {
+    fn drop(&mut self) {
+        let mut loading = unpoisoned(&self.cache.loading);
+        for (key, flight) in &self.keys {
+            loading.remove(key);
+            flight.finish();
+        }
+    }
+}
+
+// When a wait of `timeout` from now ends, or None if that is too far off
+// for an Instant, as with Duration::MAX for no timeout
+fn deadline_after(timeout: Duration) -> Option<Instant> {
+    Instant::now().checked_add(timeout)
+}
+
+// A load started by SharedCache::prefetch or SharedCache::try_get_or_insert_with_timeout
+#[derive(Debug, Default)]
+struct Flight {
+    done: Mutex<bool>,
+    finished: Condvar,
+}
+
+impl Flight {
+    fn finish(&self) {
+        *unpoisoned(&self.done) = true;
+        self.finished.notify_all();
+    }
+
+    // Waits for the load to finish, returning false if `deadline` passes first
+    fn wait_before(&self, deadline: Option<Instant>) -> bool {
+        let mut done = unpoisoned(&self.done);
+        while !*done {
+            let Some(deadline) = deadline else {
+                done = self
+                    .finished
+                    .wait(done)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                continue;
+            };
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            done = match self.finished.wait_timeout(done, deadline - now) {
+                Ok((done, _)) => done,
+                Err(poisoned) => poisoned.into_inner().0,
+            };
+        }
+        true
+    }
+}
+
+// Ends a flight when dropped, so its waiters go on to read the cache
+struct Landing<'a, T> {
+    cache: &'a SharedCache<T>,
+    key: &'a str,
+    flight: Arc<Flight>,
+}
+
+impl<T> Drop for Landing<'_, T> {
+    fn drop(&mut self) {
+        unpoisoned(&self.cache.loading).remove(self.key);
+        self.flight.finish();
+    }
+}
+
+// Locks `mutex` even if a thread panicked while holding it, clearing the
+// poison; nothing guarded here is left half-changed by a panic
+pub(crate) fn unpoisoned<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| {
+        mutex.clear_poison();
+        poisoned.into_inner()
+    })
+}
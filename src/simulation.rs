@@ -0,0 +1,180 @@
+//! Deterministic replay of scripted cache operations
+//!
+//! A [`Script`] is a list of steps run against a string cache whose clock only
+//! moves when the script says so, so a bug report written as a script
+//! reproduces exactly on every run. Persistence goes to an in-memory state
+//! file through the same parser as [`load_cache_from`](crate::load_cache_from),
+//! and failing or torn writes can be injected.
+//!
+//! ```yaml
+//! start: 1000
+//! steps:
+//!   - insert: { key: user_xyz, value: session_value, ttl: 30 }
+//!   - save
+//!   - advance: 31
+//!   - get: { key: user_xyz, expect: null }
+//!   - torn_save
+//!   - load
+//! ```
+
+use std::cell::Cell;
+use std::fmt;
+use std::io::Write;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{Deserialize, Deserializer};
+
+use crate::{load_cache_from_slice, Cache};
+
+thread_local! {
+    // Clock is a plain fn pointer, so the simulated time has to live outside the cache
+    static NOW: Cell<u64> = const { Cell::new(0) };
+}
+
+fn simulated_now() -> u64 {
+    NOW.with(Cell::get)
+}
+
+/// A scripted simulation run
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Script {
+    /// Clock reading at the start of the run, in seconds since the Unix epoch
+    #[serde(default)]
+    pub start: u64,
+    // Steps are written as `- insert: {...}` rather than YAML tags
+    #[serde(with = "serde_yaml::with::singleton_map_recursive")]
+    pub steps: Vec<Step>,
+}
+
+/// A single step of a [`Script`]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Step {
+    /// Inserts `value` under `key` for `ttl` seconds
+    Insert {
+        key: String,
+        value: String,
+        ttl: u64,
+    },
+    /// Reads `key`; with `expect`, checks the result (`null` expects no value)
+    Get {
+        key: String,
+        #[serde(default, deserialize_with = "present")]
+        expect: Option<Option<String>>,
+    },
+    /// Removes `key`
+    Invalidate { key: String },
+    /// Moves the clock forward by this many seconds
+    Advance(u64),
+    /// Writes the cache to the simulated state file
+    Save,
+    /// A save that fails without touching the state file
+    FailSave,
+    /// A save interrupted halfway, leaving a truncated state file
+    TornSave,
+    /// Replaces the cache with the contents of the state file, as a restart would
+    Load,
+}
+
+// Distinguishes `expect: null` from a missing `expect`
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Option<String>>, D::Error> {
+    Option::deserialize(deserializer).map(Some)
+}
+
+impl Script {
+    /// Parses a script from YAML
+    pub fn from_yaml(yaml: &str) -> Result<Script> {
+        Ok(serde_yaml::from_str(yaml)?)
+    }
+
+    /// Runs the script, writing a trace of every step to `out`
+    ///
+    /// Returns the number of `get` expectations that did not hold. Failed
+    /// loads are traced like any other outcome and leave the cache as it was.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use memory_cache::simulation::Script;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let script = Script::from_yaml(
+    ///     "steps:
+    ///        - insert: { key: user_xyz, value: session_value, ttl: 30 }
+    ///        - advance: 31
+    ///        - get: { key: user_xyz, expect: null }",
+    /// )?;
+    /// let mut trace = Vec::new();
+    /// assert_eq!(script.run(&mut trace)?, 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn run(&self, out: &mut impl Write) -> Result<usize> {
+        NOW.with(|now| now.set(self.start));
+        let mut cache = Cache::new().with_clock(simulated_now);
+        let mut state_file = serde_json::to_vec(&cache)?;
+        let mut failures = 0;
+
+        for (index, step) in self.steps.iter().enumerate() {
+            write!(out, "#{} t={} ", index + 1, simulated_now())?;
+            match step {
+                Step::Insert { key, value, ttl } => {
+                    cache.insert(key, value.clone(), Duration::from_secs(*ttl));
+                    writeln!(out, "insert '{}' = '{}' for {}s", key, value, ttl)?;
+                }
+                Step::Get { key, expect } => {
+                    let value = cache.get(key);
+                    write!(out, "get '{}' -> {}", key, Shown(&value))?;
+                    match expect {
+                        Some(expected) if *expected != value => {
+                            failures += 1;
+                            writeln!(out, "  FAILED: expected {}", Shown(expected))?;
+                        }
+                        _ => writeln!(out)?,
+                    }
+                }
+                Step::Invalidate { key } => {
+                    cache.invalidate(key);
+                    writeln!(out, "invalidate '{}'", key)?;
+                }
+                Step::Advance(secs) => {
+                    NOW.with(|now| now.set(now.get().saturating_add(*secs)));
+                    writeln!(out, "advance {}s", secs)?;
+                }
+                Step::Save => {
+                    state_file = serde_json::to_vec(&cache)?;
+                    writeln!(out, "save ({} bytes)", state_file.len())?;
+                }
+                Step::FailSave => writeln!(out, "save failed (injected)")?,
+                Step::TornSave => {
+                    let mut contents = serde_json::to_vec(&cache)?;
+                    contents.truncate(contents.len() / 2);
+                    state_file = contents;
+                    writeln!(out, "save torn after {} bytes (injected)", state_file.len())?;
+                }
+                Step::Load => match load_cache_from_slice(&state_file) {
+                    Ok(loaded) => {
+                        cache = loaded.with_clock(simulated_now);
+                        writeln!(out, "load")?;
+                    }
+                    Err(err) => writeln!(out, "load failed: {}", err)?,
+                },
+            }
+        }
+        Ok(failures)
+    }
+}
+
+// Formats a looked-up value for the trace
+struct Shown<'a>(&'a Option<String>);
+
+impl fmt::Display for Shown<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(value) => write!(f, "'{}'", value),
+            None => f.write_str("none"),
+        }
+    }
+}
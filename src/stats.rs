@@ -0,0 +1,332 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::hash::BuildHasher;
+use core::time::Duration;
+
+#[cfg(feature = "persistence")]
+use serde::{Deserialize, Serialize};
+
+use crate::policy::Weigher;
+use crate::rng::Rng;
+use crate::{Cache, CacheEventKind, Weigh};
+
+impl<T: Clone, S: BuildHasher> Cache<T, S> {
+    /// Returns the live entries with at most `within` left to live, soonest to expire first
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new().with_clock(|| 1_000);
+    /// cache.insert("creds/db", "pw", Duration::from_secs(240));
+    /// cache.insert("creds/api", "key", Duration::from_secs(60));
+    /// cache.insert("creds/ca", "cert", Duration::from_secs(86_400));
+    ///
+    /// assert_eq!(
+    ///     cache.expiring_within(Duration::from_secs(300)),
+    ///     [("creds/api".to_string(), Duration::from_secs(60)), ("creds/db".to_string(), Duration::from_secs(240))]
+    /// );
+    /// ```
+    pub fn expiring_within(&self, within: Duration) -> Vec<(String, Duration)> {
+        if self.disabled {
+            return Vec::new();
+        }
+        let now = self.now();
+        let mut expiring: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(key, entry)| now < entry.expiry && !self.epochs.supersedes(key, entry.epoch))
+            .filter(|(_, entry)| entry.expiry - now <= within.as_secs())
+            .map(|(key, entry)| (key.clone(), Duration::from_secs(entry.expiry - now)))
+            .collect();
+        expiring.sort_unstable_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        expiring
+    }
+
+    /// Returns how long ago the live value under `key` was last written
+    ///
+    /// Returns `None` if the entry is expired or not found, or was saved
+    /// before write times were recorded.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    ///
+    /// static NOW: AtomicU64 = AtomicU64::new(1_000);
+    ///
+    /// let mut cache = Cache::new().with_clock(|| NOW.load(Ordering::SeqCst));
+    /// cache.insert("report", "v1", Duration::from_secs(86_400));
+    /// NOW.fetch_add(600, Ordering::SeqCst);
+    /// assert_eq!(cache.age("report"), Some(Duration::from_secs(600)));
+    ///
+    /// // Overwriting refreshes the age but not the creation time
+    /// cache.insert("report", "v2", Duration::from_secs(86_400));
+    /// assert_eq!(cache.age("report"), Some(Duration::ZERO));
+    /// assert_eq!(cache.created_at("report"), Some(1_000));
+    /// ```
+    pub fn age(&self, key: &str) -> Option<Duration> {
+        let now = self.now();
+        self.live_entry(key)
+            .filter(|entry| entry.updated_at > 0)
+            .map(|entry| Duration::from_secs(now.saturating_sub(entry.updated_at)))
+    }
+
+    /// Returns when the live entry under `key` was first stored, in seconds since the Unix epoch
+    ///
+    /// Overwriting a live entry keeps its creation time; storing a key whose
+    /// entry has expired starts a new one. Returns `None` as [`Cache::age`] does.
+    pub fn created_at(&self, key: &str) -> Option<u64> {
+        self.live_entry(key)
+            .map(|entry| entry.created_at)
+            .filter(|created_at| *created_at > 0)
+    }
+
+    /// Returns the live entries last written more than `age` ago, oldest first
+    ///
+    /// Entries saved before write times were recorded are never included.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    ///
+    /// static NOW: AtomicU64 = AtomicU64::new(1_000);
+    ///
+    /// let mut cache = Cache::new().with_clock(|| NOW.load(Ordering::SeqCst));
+    /// cache.insert("prices/eu", "old", Duration::from_secs(7 * 86_400));
+    /// NOW.fetch_add(2 * 86_400, Ordering::SeqCst);
+    /// cache.insert("prices/us", "new", Duration::from_secs(7 * 86_400));
+    ///
+    /// let day = Duration::from_secs(86_400);
+    /// assert_eq!(cache.older_than(day), [("prices/eu".to_string(), 2 * day)]);
+    /// assert_eq!(cache.invalidate_older_than(day), 1);
+    /// assert_eq!(cache.get("prices/eu"), None);
+    /// ```
+    pub fn older_than(&self, age: Duration) -> Vec<(String, Duration)> {
+        if self.disabled {
+            return Vec::new();
+        }
+        let now = self.now();
+        let mut older: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(key, entry)| now < entry.expiry && !self.epochs.supersedes(key, entry.epoch) && entry.updated_at > 0)
+            .map(|(key, entry)| (key.clone(), Duration::from_secs(now.saturating_sub(entry.updated_at))))
+            .filter(|(_, entry_age)| *entry_age > age)
+            .collect();
+        older.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        older
+    }
+
+    /// Returns the live entries not read for more than `idle`, most idle first
+    ///
+    /// An entry that has never been read counts as idle since it was first
+    /// stored; overwriting it does not count as a read. Reads are those by
+    /// [`Cache::get`] and the get-or-insert methods, and helpers built on
+    /// them. Entries saved before read times were recorded are never included.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    ///
+    /// static NOW: AtomicU64 = AtomicU64::new(1_000);
+    ///
+    /// let mut cache = Cache::new().with_clock(|| NOW.load(Ordering::SeqCst));
+    /// cache.insert("report/daily", "...", Duration::from_secs(86_400));
+    /// cache.insert("report/hourly", "...", Duration::from_secs(86_400));
+    /// NOW.fetch_add(7_200, Ordering::SeqCst);
+    /// cache.get("report/hourly");
+    ///
+    /// let hour = Duration::from_secs(3_600);
+    /// assert_eq!(cache.idle_keys(hour), [("report/daily".to_string(), 2 * hour)]);
+    /// ```
+    pub fn idle_keys(&self, idle: Duration) -> Vec<(String, Duration)> {
+        if self.disabled {
+            return Vec::new();
+        }
+        let now = self.now();
+        let mut idle_keys: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(key, entry)| now < entry.expiry && !self.epochs.supersedes(key, entry.epoch) && entry.accessed_at > 0)
+            .map(|(key, entry)| (key.clone(), Duration::from_secs(now.saturating_sub(entry.accessed_at))))
+            .filter(|(_, idle_for)| *idle_for > idle)
+            .collect();
+        idle_keys.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        idle_keys
+    }
+
+    /// Returns up to `n` live keys chosen uniformly at random, in no particular order
+    ///
+    /// The keys are reservoir-sampled in one pass over the entries, so a
+    /// sample costs the same whatever `n` is. Useful for estimating value
+    /// sizes and the like on caches too large to scan, and for building
+    /// probabilistic eviction on top of the cache. Without the `std` feature
+    /// the randomness comes from the cache's hasher and the time, so two
+    /// samples taken in the same second can be the same.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new();
+    /// for user in 0..1_000 {
+    ///     cache.insert(&format!("user/{}", user), user, Duration::from_secs(60));
+    /// }
+    ///
+    /// let sample = cache.random_keys(10);
+    /// assert_eq!(sample.len(), 10);
+    /// assert!(sample.iter().all(|key| cache.contains_key(key)));
+    /// ```
+    pub fn random_keys(&self, n: usize) -> Vec<String> {
+        if self.disabled || n == 0 {
+            return Vec::new();
+        }
+        let now = self.now();
+        let mut rng = Rng::new(self.random_seed(now));
+        let mut sample = Vec::with_capacity(n.min(self.entries.len()));
+        let live = self.entries.iter().filter(|(key, entry)| now < entry.expiry && !self.epochs.supersedes(key, entry.epoch));
+        for (seen, (key, _)) in live.enumerate() {
+            if seen < n {
+                sample.push(key.clone());
+            } else {
+                // Keep the key with probability n / (seen + 1)
+                let slot = rng.below(seen as u64 + 1) as usize;
+                if slot < n {
+                    sample[slot] = key.clone();
+                }
+            }
+        }
+        sample
+    }
+
+    /// Counts hits, misses, writes, expirations, evictions and bytes written, for [`Cache::stats`]
+    ///
+    /// Reads by [`Cache::get`], [`Cache::get_cow`] and
+    /// [`Cache::get_or_insert_with`] are counted, as is every write, counters
+    /// and collections included, and every entry [`Cache::clear`] drops. The
+    /// totals are saved with the cache and keep accumulating after it is
+    /// loaded again, so they span every process that used the state file;
+    /// calling this on a loaded cache keeps them. Which values' sizes to count is not saved,
+    /// so call this again after loading.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::Cache;
+    /// let mut cache = Cache::new().with_stats();
+    ///
+    /// cache.insert("greeting", "hello".to_string(), Duration::from_secs(60));
+    /// cache.get("greeting");
+    /// cache.get("farewell");
+    ///
+    /// let stats = cache.stats();
+    /// assert_eq!((stats.hits, stats.misses, stats.bytes_written), (1, 1, 5));
+    /// assert_eq!(stats.hit_ratio(), Some(0.5));
+    ///
+    /// cache.increment("visits", 1, Duration::from_secs(60));
+    /// cache.increment("visits", 1, Duration::from_secs(60));
+    /// assert_eq!(cache.stats().inserts, 3);
+    /// cache.clear();
+    /// assert_eq!(cache.stats().invalidations, 2);
+    /// ```
+    pub fn with_stats(mut self) -> Self
+    where
+        T: Weigh,
+    {
+        self.stats.get_or_insert_with(CacheStats::default);
+        self.weigh = Some(T::weight);
+        self
+    }
+
+    /// Returns the totals recorded since [`Cache::with_stats`], all zero if it was never called
+    pub fn stats(&self) -> CacheStats {
+        self.stats.unwrap_or_default()
+    }
+
+    /// Sets the recorded totals back to zero, keeping on recording
+    pub fn reset_stats(&mut self) {
+        if let Some(stats) = &mut self.stats {
+            *stats = CacheStats::default();
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn random_seed(&self, now: u64) -> u64 {
+        // Every RandomState is freshly keyed, unlike the cache's own hasher
+        std::collections::hash_map::RandomState::new().hash_one(now)
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn random_seed(&self, now: u64) -> u64 {
+        self.entries.hasher().hash_one((now, self.entries.len()))
+    }
+
+}
+
+/// Running totals recorded by [`Cache::with_stats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize), serde(default))]
+#[non_exhaustive]
+pub struct CacheStats {
+    /// Reads that found a live value
+    pub hits: u64,
+    /// Reads that found none
+    pub misses: u64,
+    /// Values written
+    pub inserts: u64,
+    /// Entries that lived out their TTL
+    pub expirations: u64,
+    /// Entries the cache dropped itself
+    pub evictions: u64,
+    /// Entries removed on request
+    pub invalidations: u64,
+    /// The [`Weigh::weight`] of the values written, summed
+    pub bytes_written: u64,
+    /// Failed loads retried by [`Cache::try_get_or_insert_with_retry`], and others added with [`Cache::record_retries`]
+    pub retries: u64,
+}
+
+impl CacheStats {
+    /// The share of reads that hit, or None before the first read
+    pub fn hit_ratio(&self) -> Option<f64> {
+        let reads = self.hits + self.misses;
+        (reads > 0).then(|| self.hits as f64 / reads as f64)
+    }
+
+    pub(crate) fn read(&mut self, hit: bool) {
+        if hit {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+    }
+
+    pub(crate) fn write<T>(&mut self, weigh: Option<Weigher<T>>, value: &T) {
+        self.inserts += 1;
+        if let Some(weigh) = weigh {
+            self.bytes_written = self.bytes_written.saturating_add(weigh(value) as u64);
+        }
+    }
+
+    // `kind` is Update for an entry overwritten while live, which is not counted
+    pub(crate) fn end(&mut self, kind: CacheEventKind) {
+        match kind {
+            CacheEventKind::Update => {}
+            CacheEventKind::Expire => self.expirations += 1,
+            CacheEventKind::Evict => self.evictions += 1,
+            _ => self.invalidations += 1,
+        }
+    }
+}
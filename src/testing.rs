@@ -0,0 +1,56 @@
+//! Property-based testing support
+//!
+//! [`Op`] implements proptest's [`Arbitrary`], so sequences of cache
+//! operations can be generated with `any::<Vec<Op>>()` and replayed against a
+//! [`Cache`](crate::Cache) and a reference model. Keys are drawn from a small
+//! pool so that operations collide on the same entries.
+
+use proptest::prelude::*;
+
+/// Number of distinct keys generated operations use
+pub const KEY_POOL: u8 = 8;
+
+/// A single operation on a `Cache<u64>` driven by a test clock
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    /// [`Cache::insert`](crate::Cache::insert) with a TTL in seconds
+    Insert { key: String, value: u64, ttl: u64 },
+    /// [`Cache::insert_if_absent`](crate::Cache::insert_if_absent) with a TTL in seconds
+    InsertIfAbsent { key: String, value: u64, ttl: u64 },
+    /// [`Cache::get`](crate::Cache::get)
+    Get { key: String },
+    /// [`Cache::get_or_insert_with`](crate::Cache::get_or_insert_with) with a TTL in seconds
+    GetOrInsert { key: String, value: u64, ttl: u64 },
+    /// [`Cache::increment`](crate::Cache::increment) with a TTL in seconds
+    Increment { key: String, delta: u64, ttl: u64 },
+    /// [`Cache::invalidate`](crate::Cache::invalidate)
+    Invalidate { key: String },
+    /// Moves the test clock forward by `secs` seconds
+    Advance { secs: u64 },
+}
+
+impl Arbitrary for Op {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Op>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let key = || (0..KEY_POOL).prop_map(|index| format!("k{}", index));
+        // Mostly short TTLs so entries expire during a run, plus the extremes
+        let ttl = || prop_oneof![8 => 0..10u64, 1 => Just(0), 1 => Just(u64::MAX)];
+        let value = || prop_oneof![4 => 0..100u64, 1 => Just(u64::MAX)];
+
+        prop_oneof![
+            3 => (key(), value(), ttl()).prop_map(|(key, value, ttl)| Op::Insert { key, value, ttl }),
+            1 => (key(), value(), ttl())
+                .prop_map(|(key, value, ttl)| Op::InsertIfAbsent { key, value, ttl }),
+            3 => key().prop_map(|key| Op::Get { key }),
+            2 => (key(), value(), ttl())
+                .prop_map(|(key, value, ttl)| Op::GetOrInsert { key, value, ttl }),
+            2 => (key(), value(), ttl())
+                .prop_map(|(key, delta, ttl)| Op::Increment { key, delta, ttl }),
+            1 => key().prop_map(|key| Op::Invalidate { key }),
+            2 => (0..5u64).prop_map(|secs| Op::Advance { secs }),
+        ]
+        .boxed()
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::shared::unpoisoned;
+use crate::SharedCache;
+
+/// An access token and how long it is valid, as a token endpoint returns it
+#[derive(Clone, PartialEq, Eq)]
+pub struct Token {
+    pub access_token: String,
+    pub expires_in: Duration,
+}
+
+impl Token {
+    /// Creates a token valid for `expires_in`, the `expires_in` of an OAuth token response
+    pub fn new(access_token: impl Into<String>, expires_in: Duration) -> Self {
+        Token {
+            access_token: access_token.into(),
+            expires_in,
+        }
+    }
+}
+
+// Keeps the token itself out of logs
+impl std::fmt::Debug for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Token")
+            .field("expires_in", &self.expires_in)
+            .finish_non_exhaustive()
+    }
+}
+
+// A refresh in progress; `done` is set when it has finished either way
+#[derive(Debug, Default)]
+struct Refresh {
+    done: Mutex<bool>,
+    finished: Condvar,
+}
+
+/// Caches API access tokens per client and scope, refreshing each at most once at a time
+///
+/// A token is kept until a safety margin before it expires, 30 seconds by
+/// default, so it is not handed out just as the server stops accepting it.
+/// When several threads want a missing token at once, only one of them
+/// calls the refresh function and the others wait for its token.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::{Token, TokenCache};
+/// let tokens = TokenCache::new().with_margin(Duration::from_secs(60));
+///
+/// let token = tokens.get_or_refresh("billing", "invoices:read", || {
+///     // POST to the token endpoint with the client credentials here
+///     Ok::<_, std::io::Error>(Token::new("eyJhbGciOi...", Duration::from_secs(3600)))
+/// })?;
+/// assert_eq!(token, "eyJhbGciOi...");
+/// assert_eq!(tokens.ttl("billing", "invoices:read"), Some(Duration::from_secs(3540)));
+///
+/// // Cached until shortly before it expires, so this does not refresh
+/// let again = tokens.get_or_refresh("billing", "invoices:read", || -> Result<Token, std::io::Error> {
+///     unreachable!()
+/// })?;
+/// assert_eq!(again, token);
+///
+/// // A 401 from the API means the token was revoked early
+/// tokens.invalidate("billing", "invoices:read");
+/// assert_eq!(tokens.get("billing", "invoices:read"), None);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct TokenCache {
+    cache: SharedCache<String>,
+    margin: Duration,
+    refreshing: Arc<Mutex<HashMap<String, Arc<Refresh>>>>,
+}
+
+impl TokenCache {
+    /// Creates a token cache with its own cache
+    pub fn new() -> Self {
+        Self::with_cache(SharedCache::new())
+    }
+
+    /// Creates a token cache that stores tokens in an existing cache
+    pub fn with_cache(cache: SharedCache<String>) -> Self {
+        TokenCache {
+            cache,
+            margin: Duration::from_secs(30),
+            refreshing: Arc::default(),
+        }
+    }
+
+    /// Sets how long before they expire tokens stop being handed out
+    pub fn with_margin(mut self, margin: Duration) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// Stores `token` for `client` and `scope` until the margin before it expires
+    ///
+    /// A token that expires within the margin is not stored.
+    pub fn insert(&self, client: &str, scope: &str, token: &Token) {
+        let ttl = token.expires_in.saturating_sub(self.margin);
+        let key = key(client, scope);
+        if ttl.is_zero() {
+            self.cache.invalidate(&key);
+        } else {
+            self.cache.insert(&key, token.access_token.clone(), ttl);
+        }
+    }
+
+    /// Returns the cached token for `client` and `scope`, if it is not about to expire
+    pub fn get(&self, client: &str, scope: &str) -> Option<String> {
+        self.cache.get(&key(client, scope))
+    }
+
+    /// Returns how much longer the token for `client` and `scope` is handed out, if there is one
+    pub fn ttl(&self, client: &str, scope: &str) -> Option<Duration> {
+        self.cache.lock().ttl(&key(client, scope))
+    }
+
+    /// Returns the cached token for `client` and `scope`, or fetches and caches one with `refresh`
+    ///
+    /// Concurrent calls for the same client and scope wait for the one that
+    /// is refreshing instead of calling `refresh` themselves. If that
+    /// refresh fails, its caller gets the error and one of the waiting
+    /// callers tries its own `refresh`.
+    pub fn get_or_refresh<F, E>(&self, client: &str, scope: &str, refresh: F) -> Result<String, E>
+    where
+        F: FnOnce() -> Result<Token, E>,
+    {
+        let key = key(client, scope);
+        loop {
+            let waiting = {
+                let mut refreshing = unpoisoned(&self.refreshing);
+                // Checked under the lock so a refresh finishing meanwhile is seen either way
+                if let Some(token) = self.cache.get(&key) {
+                    return Ok(token);
+                }
+                match refreshing.get(&key) {
+                    Some(running) => running.clone(),
+                    None => {
+                        refreshing.insert(key.clone(), Arc::default());
+                        break;
+                    }
+                }
+            };
+            let mut done = unpoisoned(&waiting.done);
+            while !*done {
+                done = waiting
+                    .finished
+                    .wait(done)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+        }
+
+        // Wakes the waiters even if `refresh` panics
+        let _finish = FinishRefresh {
+            tokens: self,
+            key: &key,
+        };
+        let token = refresh()?;
+        self.insert(client, scope, &token);
+        Ok(token.access_token)
+    }
+
+    /// Drops the token for `client` and `scope`, e.g. after the API rejected it
+    pub fn invalidate(&self, client: &str, scope: &str) {
+        self.cache.invalidate(&key(client, scope));
+    }
+}
+
+impl Default for TokenCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct FinishRefresh<'a> {
+    tokens: &'a TokenCache,
+    key: &'a str,
+}
+
+impl Drop for FinishRefresh<'_> {
+    fn drop(&mut self) {
+        let finished = unpoisoned(&self.tokens.refreshing).remove(self.key);
+        if let Some(refresh) = finished {
+            *unpoisoned(&refresh.done) = true;
+            refresh.finished.notify_all();
+        }
+    }
+}
+
+// The client's length goes first so that no two pairs share a key, even
+// when the client contains the separator
+fn key(client: &str, scope: &str) -> String {
+    format!("{}:{}/{}", client.len(), client, scope)
+}
@@ -0,0 +1,255 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::hash::BuildHasher;
+use core::time::Duration;
+
+#[cfg(feature = "persistence")]
+use serde::{Deserialize, Serialize};
+
+use crate::{Cache, CacheEntry, CacheEventKind, KEY_SEPARATOR};
+
+// Below this many reads a pattern gets no recommendation
+const MIN_READS: u64 = 20;
+
+/// What happened to the keys of one pattern, as recorded by [`Cache::with_tuning_stats`]
+///
+/// Times are in whole seconds by the cache's clock.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "persistence",
+    derive(Serialize, Deserialize),
+    serde(default)
+)]
+#[non_exhaustive]
+pub struct PatternStats {
+    /// Reads that found a live value
+    pub hits: u64,
+    /// Reads that found none
+    pub misses: u64,
+    /// Values written
+    pub inserts: u64,
+    /// Live values replaced by a write
+    pub overwrites: u64,
+    /// Entries that lived out their TTL
+    pub expirations: u64,
+    /// Entries removed on request
+    pub invalidations: u64,
+    /// Entries the cache dropped itself
+    pub evictions: u64,
+    /// The TTLs given to writes that expire, summed
+    pub ttl_secs: u64,
+    /// How long ended entries had lived since they were written, summed
+    pub lifetime_secs: u64,
+}
+
+impl PatternStats {
+    /// The share of reads that hit, or None before the first read
+    pub fn hit_ratio(&self) -> Option<f64> {
+        let reads = self.hits + self.misses;
+        (reads > 0).then(|| self.hits as f64 / reads as f64)
+    }
+
+    /// The average TTL given to writes
+    pub fn average_ttl(&self) -> Option<Duration> {
+        (self.inserts > 0).then(|| Duration::from_secs(self.ttl_secs / self.inserts))
+    }
+
+    /// The average time entries lived before they expired, were replaced or were removed
+    pub fn average_lifetime(&self) -> Option<Duration> {
+        let ended = self.overwrites + self.expirations + self.invalidations + self.evictions;
+        (ended > 0).then(|| Duration::from_secs(self.lifetime_secs / ended))
+    }
+
+    /// What to change about the TTL of the pattern's keys, if the numbers suggest anything
+    ///
+    /// There is no recommendation until the keys have been read a few times.
+    pub fn recommendation(&self) -> Option<Recommendation> {
+        let reads = self.hits + self.misses;
+        if reads < MIN_READS {
+            return None;
+        }
+        let hit_ratio = self.hit_ratio()?;
+        let ended = self.overwrites + self.expirations + self.invalidations + self.evictions;
+        if hit_ratio < 0.8 && ended > 0 && self.expirations * 2 >= ended {
+            return Some(Recommendation::RaiseTtl);
+        }
+        // Read about once per write: the cache saves nothing
+        if hit_ratio < 0.1 && self.inserts * 2 >= reads {
+            return Some(Recommendation::StopCaching);
+        }
+        let (ttl, lifetime) = (self.average_ttl()?, self.average_lifetime()?);
+        // Values are replaced long before they would expire
+        if self.expirations * 4 < ended && lifetime * 4 < ttl {
+            return Some(Recommendation::LowerTtl);
+        }
+        None
+    }
+}
+
+/// A change suggested by [`PatternStats::recommendation`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Recommendation {
+    /// Entries mostly expire before they are read again, so reads miss
+    RaiseTtl,
+    /// Entries are mostly replaced or removed far sooner than they expire, so the TTL only bounds staleness
+    LowerTtl,
+    /// Values are rarely read before they are written again
+    StopCaching,
+}
+
+impl fmt::Display for Recommendation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Recommendation::RaiseTtl => {
+                "raise the TTL: entries mostly expire before they are read again"
+            }
+            Recommendation::LowerTtl => {
+                "lower the TTL: entries are replaced long before they expire"
+            }
+            Recommendation::StopCaching => {
+                "stop caching: values are rarely read before they are written again"
+            }
+        })
+    }
+}
+
+/// Recorded stats per key pattern, busiest first, as returned by [`Cache::tuning_report`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TuningReport {
+    pub patterns: Vec<(String, PatternStats)>,
+}
+
+// The counters behind Cache::with_tuning_stats, saved with the cache
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(
+    feature = "persistence",
+    derive(Serialize, Deserialize),
+    serde(transparent)
+)]
+pub(crate) struct TuningStats {
+    patterns: BTreeMap<String, PatternStats>,
+}
+
+impl TuningStats {
+    fn stats(&mut self, key: &str) -> &mut PatternStats {
+        self.patterns.entry(pattern(key)).or_default()
+    }
+
+    pub(crate) fn read(&mut self, key: &str, hit: bool) {
+        let stats = self.stats(key);
+        if hit {
+            stats.hits += 1;
+        } else {
+            stats.misses += 1;
+        }
+    }
+
+    pub(crate) fn write(&mut self, key: &str, expiry: u64, now: u64) {
+        let stats = self.stats(key);
+        stats.inserts += 1;
+        if expiry != u64::MAX {
+            stats.ttl_secs = stats.ttl_secs.saturating_add(expiry.saturating_sub(now));
+        }
+    }
+
+    // `kind` is Update for an entry overwritten while live
+    pub(crate) fn end<T>(
+        &mut self,
+        key: &str,
+        entry: &CacheEntry<T>,
+        kind: CacheEventKind,
+        now: u64,
+    ) {
+        let stats = self.stats(key);
+        match kind {
+            CacheEventKind::Update => stats.overwrites += 1,
+            CacheEventKind::Expire => stats.expirations += 1,
+            CacheEventKind::Evict => stats.evictions += 1,
+            _ => stats.invalidations += 1,
+        }
+        // Entries saved before write times were recorded have none
+        if entry.updated_at > 0 {
+            let lifetime = now.min(entry.expiry).saturating_sub(entry.updated_at);
+            stats.lifetime_secs = stats.lifetime_secs.saturating_add(lifetime);
+        }
+    }
+}
+
+// The pattern `key` is counted under: segments with a digit in them, such
+// as IDs, become `*`, so `user/42/prefs` counts as `user/*/prefs`
+fn pattern(key: &str) -> String {
+    let mut pattern = String::with_capacity(key.len());
+    for (i, segment) in key.split(KEY_SEPARATOR).enumerate() {
+        if i > 0 {
+            pattern.push(KEY_SEPARATOR);
+        }
+        if segment.bytes().any(|byte| byte.is_ascii_digit()) {
+            pattern.push('*');
+        } else {
+            pattern.push_str(segment);
+        }
+    }
+    pattern
+}
+
+impl<T: Clone, S: BuildHasher> Cache<T, S> {
+    /// Records hits, misses, writes and how entries end per key pattern, for [`Cache::tuning_report`]
+    ///
+    /// Keys are grouped by replacing each `/`-separated segment that
+    /// contains a digit with `*`. Reads by [`Cache::get`], [`Cache::get_cow`]
+    /// and [`Cache::get_or_insert_with`] are counted. The stats are saved
+    /// with the cache and keep accumulating after it is loaded again;
+    /// calling this on a cache that already records them keeps them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    /// use std::time::Duration;
+    /// use memory_cache::{Cache, Recommendation};
+    ///
+    /// static NOW: AtomicU64 = AtomicU64::new(1_000);
+    ///
+    /// let mut cache = Cache::new().with_clock(|| NOW.load(Ordering::SeqCst)).with_tuning_stats();
+    /// for user in 0..30 {
+    ///     let key = format!("user/{}", user);
+    ///     cache.get_or_insert_with(&key, Duration::from_secs(5), || "profile");
+    ///     NOW.fetch_add(10, Ordering::SeqCst);
+    ///     cache.get_or_insert_with(&key, Duration::from_secs(5), || "profile");
+    /// }
+    ///
+    /// let report = cache.tuning_report();
+    /// let (pattern, stats) = &report.patterns[0];
+    /// assert_eq!(pattern, "user/*");
+    /// assert_eq!(stats.hit_ratio(), Some(0.0));
+    /// assert_eq!(stats.recommendation(), Some(Recommendation::RaiseTtl));
+    /// ```
+    pub fn with_tuning_stats(mut self) -> Self {
+        self.tuning.get_or_insert_with(TuningStats::default);
+        self
+    }
+
+    /// Returns the stats recorded since [`Cache::with_tuning_stats`], busiest pattern first
+    pub fn tuning_report(&self) -> TuningReport {
+        let mut patterns: Vec<(String, PatternStats)> = self
+            .tuning
+            .iter()
+            .flat_map(|tuning| tuning.patterns.iter())
+            .map(|(pattern, stats)| (pattern.clone(), stats.clone()))
+            .collect();
+        patterns.sort_by_key(|(_, stats)| {
+            core::cmp::Reverse(stats.hits + stats.misses + stats.inserts)
+        });
+        TuningReport { patterns }
+    }
+
+    /// Forgets the recorded stats, keeping on recording
+    pub fn reset_tuning_stats(&mut self) {
+        if let Some(tuning) = &mut self.tuning {
+            tuning.patterns.clear();
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use alloc::sync::Arc;
+use core::any::{Any, TypeId};
+use core::fmt;
+use core::time::Duration;
+
+use crate::{Cache, Clock, DefaultHashBuilder};
+
+type Erased = Arc<dyn Any + Send + Sync>;
+
+/// A cache holding values of any type, keyed by key and type together
+///
+/// Values of different types can share a key without overwriting each
+/// other, and [`TypedCache::get`] hands back the type that was stored, so
+/// configs, tokens and parsed structs can live in one cache without going
+/// through a common serialized form. Each type gets its own [`Cache`]
+/// internally, with its own expiries.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use memory_cache::TypedCache;
+///
+/// struct Config {
+///     retries: u32,
+/// }
+///
+/// let mut cache = TypedCache::new();
+/// cache.insert("service/api", Config { retries: 3 }, Duration::from_secs(60));
+/// cache.insert("service/api", "token123".to_string(), Duration::from_secs(60));
+///
+/// assert_eq!(cache.get::<Config>("service/api").unwrap().retries, 3);
+/// assert_eq!(cache.get::<String>("service/api").unwrap().as_str(), "token123");
+/// assert!(cache.get::<u64>("service/api").is_none());
+/// ```
+pub struct TypedCache {
+    caches: hashbrown::HashMap<TypeId, Cache<Erased>, DefaultHashBuilder>,
+    clock: Option<Clock>,
+}
+
+impl TypedCache {
+    /// Creates a new empty cache
+    pub fn new() -> Self {
+        TypedCache {
+            caches: hashbrown::HashMap::with_hasher(DefaultHashBuilder::default()),
+            clock: None,
+        }
+    }
+
+    /// Uses `clock` instead of the system clock, as [`Cache::with_clock`] does
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = Some(clock);
+        for cache in self.caches.values_mut() {
+            cache.clock = Some(clock);
+        }
+        self
+    }
+
+    /// Inserts a value with a specified TTL, replacing any value of the same type under `key`
+    pub fn insert<T: Any + Send + Sync>(&mut self, key: &str, value: T, ttl: Duration) {
+        let clock = self.clock;
+        self.caches
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| {
+                let cache = Cache::new();
+                match clock {
+                    Some(clock) => cache.with_clock(clock),
+                    None => cache,
+                }
+            })
+            .insert(key, Arc::new(value), ttl);
+    }
+
+    /// Retrieves the value of type `T` under `key`, or None if expired or not found
+    pub fn get<T: Any + Send + Sync>(&mut self, key: &str) -> Option<Arc<T>> {
+        let value = self.caches.get_mut(&TypeId::of::<T>())?.get(key)?;
+        // Each cache only holds values of the type it is filed under
+        value.downcast().ok()
+    }
+
+    /// Removes the value of type `T` under `key`, if present
+    pub fn invalidate<T: Any + Send + Sync>(&mut self, key: &str) {
+        if let Some(cache) = self.caches.get_mut(&TypeId::of::<T>()) {
+            cache.invalidate(key);
+        }
+    }
+
+    /// Removes the values of every type under `key`
+    pub fn invalidate_all(&mut self, key: &str) {
+        for cache in self.caches.values_mut() {
+            cache.invalidate(key);
+        }
+    }
+
+    /// Removes every entry
+    pub fn clear(&mut self) {
+        self.caches.clear();
+    }
+}
+
+impl Default for TypedCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for TypedCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let len: usize = self.caches.values().map(|cache| cache.entries.len()).sum();
+        f.debug_struct("TypedCache")
+            .field("types", &self.caches.len())
+            .field("len", &len)
+            .finish_non_exhaustive()
+    }
+}
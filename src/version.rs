@@ -0,0 +1,71 @@
+use core::hash::BuildHasher;
+use core::time::Duration;
+
+use crate::Cache;
+
+/// What [`Cache::get_if_modified`] found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Modified<T> {
+    /// The value still has the version the caller knows
+    Unchanged,
+    /// The value has changed since, with its current version
+    Changed(T, u64),
+}
+
+impl<T: Clone, S: BuildHasher> Cache<T, S> {
+    /// Returns the live value under `key` only if it has changed since `known_version`
+    ///
+    /// Every write of a value gives it a new version, so a poller that keeps
+    /// the version it last saw can skip transferring a value that has not
+    /// changed, much as an HTTP client does with an `ETag`. Versions increase
+    /// across the whole cache, so a key that is removed and written again
+    /// does not reuse an old version; pass 0 to always get the value.
+    /// Returns None if there is no live entry, and counts as a read like
+    /// [`Cache::get`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use memory_cache::{Cache, Modified};
+    /// let mut cache = Cache::new();
+    /// cache.insert("feed", "v1", Duration::from_secs(60));
+    ///
+    /// let Some(Modified::Changed(feed, version)) = cache.get_if_modified("feed", 0) else {
+    ///     unreachable!()
+    /// };
+    /// assert_eq!(feed, "v1");
+    /// assert_eq!(cache.get_if_modified("feed", version), Some(Modified::Unchanged));
+    ///
+    /// cache.insert("feed", "v2", Duration::from_secs(60));
+    /// assert!(matches!(cache.get_if_modified("feed", version), Some(Modified::Changed("v2", _))));
+    /// ```
+    pub fn get_if_modified(&mut self, key: &str, known_version: u64) -> Option<Modified<T>> {
+        let version = self.version(key)?;
+        // Entries saved before versions were recorded have version 0
+        if known_version != 0 && version == known_version {
+            let now = self.now();
+            let entry = self.entries.get_mut(key)?;
+            entry.accessed_at = now;
+            if let Some((within, hook)) = self.expiry_hook {
+                let left = entry.expiry - now;
+                if left <= within {
+                    hook(key, Duration::from_secs(left));
+                }
+            }
+            return Some(Modified::Unchanged);
+        }
+        self.get(key).map(|value| Modified::Changed(value, version))
+    }
+
+    /// Returns the version of the live value under `key`, without counting as a read
+    pub fn version(&self, key: &str) -> Option<u64> {
+        self.live_entry(key).map(|entry| entry.version)
+    }
+
+    // Hands out a version no entry has had yet
+    pub(crate) fn next_version(&mut self) -> u64 {
+        self.version += 1;
+        self.version
+    }
+}
@@ -0,0 +1,210 @@
+use core::fmt;
+use core::hash::BuildHasher;
+use core::ops::Index;
+#[cfg(feature = "std")]
+use core::time::Duration;
+
+use alloc::string::String;
+
+use crate::epoch::Epochs;
+use crate::{Cache, CacheEntry};
+
+/// A read-only view of the live entries of a [`Cache`], for code written against maps
+///
+/// The view is taken at one instant: an entry live when the view was
+/// created stays visible through it. Like a `HashMap`, indexing a missing
+/// key panics, and iteration is in no particular order. Reads through the
+/// view do not count as accesses.
+///
+/// # Example
+///
+/// ```
+/// use std::collections::HashMap;
+/// use std::time::Duration;
+/// use memory_cache::Cache;
+///
+/// let mut cache = Cache::new().with_clock(|| 1_000);
+/// cache.insert("en", "hello", Duration::from_secs(60));
+/// cache.insert("fr", "bonjour", Duration::from_secs(60));
+/// cache.insert("de", "hallo", Duration::ZERO);
+///
+/// let view = cache.as_map_view();
+/// assert_eq!(view["fr"], "bonjour");
+/// assert_eq!(view.len(), 2);
+///
+/// let greetings: HashMap<&str, &str> = view.iter().map(|(key, value)| (key, *value)).collect();
+/// assert_eq!(greetings, HashMap::from([("en", "hello"), ("fr", "bonjour")]));
+/// ```
+pub struct MapView<'a, T, S> {
+    cache: &'a Cache<T, S>,
+    now: u64,
+}
+
+impl<T, S: BuildHasher> Cache<T, S> {
+    /// Returns a read-only, map-like view of the live entries
+    pub fn as_map_view(&self) -> MapView<'_, T, S> {
+        // A disabled cache has no live entries; no time is after u64::MAX
+        let now = if self.disabled { u64::MAX } else { self.now() };
+        MapView { cache: self, now }
+    }
+}
+
+impl<'a, T, S: BuildHasher> MapView<'a, T, S> {
+    /// Returns the value for `key`, or None if expired or not found
+    pub fn get(&self, key: &str) -> Option<&'a T> {
+        self.cache
+            .entries
+            .get(key)
+            .filter(|entry| {
+                self.now < entry.expiry && !self.cache.epochs.supersedes(key, entry.epoch)
+            })
+            .map(|entry| &entry.value)
+    }
+
+    /// Returns true if `key` has a live entry
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns the number of live entries
+    ///
+    /// This counts them, so it takes time proportional to the size of the cache.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns true if there are no live entries
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Iterates over the live entries
+    pub fn iter(&self) -> MapViewIter<'a, T> {
+        MapViewIter {
+            entries: self.cache.entries.iter(),
+            epochs: &self.cache.epochs,
+            now: self.now,
+        }
+    }
+
+    /// Iterates over the live keys
+    pub fn keys(&self) -> impl Iterator<Item = &'a str> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Iterates over the live values
+    pub fn values(&self) -> impl Iterator<Item = &'a T> {
+        self.iter().map(|(_, value)| value)
+    }
+}
+
+impl<T, S: BuildHasher> Index<&str> for MapView<'_, T, S> {
+    type Output = T;
+
+    /// Returns the value for `key`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` has no live entry.
+    fn index(&self, key: &str) -> &T {
+        match self.get(key) {
+            Some(value) => value,
+            None => panic!("no live entry for key '{}'", key),
+        }
+    }
+}
+
+impl<'a, T, S: BuildHasher> IntoIterator for &MapView<'a, T, S> {
+    type Item = (&'a str, &'a T);
+    type IntoIter = MapViewIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, S: BuildHasher> IntoIterator for MapView<'a, T, S> {
+    type Item = (&'a str, &'a T);
+    type IntoIter = MapViewIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T, S> Clone for MapView<'_, T, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, S> Copy for MapView<'_, T, S> {}
+
+// Like the cache's own, lists keys but not values
+impl<T, S> fmt::Debug for MapView<'_, T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (now, epochs) = (self.now, &self.cache.epochs);
+        let keys = self
+            .cache
+            .entries
+            .iter()
+            .filter(|(key, entry)| now < entry.expiry && !epochs.supersedes(key, entry.epoch))
+            .map(|(key, _)| key);
+        f.debug_struct("MapView")
+            .field("keys", &DebugKeys(keys))
+            .finish()
+    }
+}
+
+struct DebugKeys<I>(I);
+
+impl<'a, I: Iterator<Item = &'a String> + Clone> fmt::Debug for DebugKeys<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.0.clone()).finish()
+    }
+}
+
+/// An iterator over the live entries of a [`MapView`]
+pub struct MapViewIter<'a, T> {
+    entries: hashbrown::hash_map::Iter<'a, String, CacheEntry<T>>,
+    epochs: &'a Epochs,
+    now: u64,
+}
+
+impl<'a, T> Iterator for MapViewIter<'a, T> {
+    type Item = (&'a str, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (now, epochs) = (self.now, self.epochs);
+        self.entries
+            .find(|(key, entry)| now < entry.expiry && !epochs.supersedes(key, entry.epoch))
+            .map(|(key, entry)| (key.as_str(), &entry.value))
+    }
+}
+
+/// Builds a cache from a map of values and TTLs, as [`Cache::insert`] would
+///
+/// # Example
+///
+/// ```
+/// use std::collections::HashMap;
+/// use std::time::Duration;
+/// use memory_cache::Cache;
+///
+/// let defaults = HashMap::from([("theme", ("dark", Duration::from_secs(3600)))]);
+/// let mut cache: Cache<_> = defaults.into();
+/// assert_eq!(cache.get("theme"), Some("dark"));
+/// ```
+#[cfg(feature = "std")]
+impl<K, T, S, H> From<std::collections::HashMap<K, (T, Duration), H>> for Cache<T, S>
+where
+    K: AsRef<str>,
+    T: Clone,
+    S: BuildHasher + Default,
+{
+    fn from(entries: std::collections::HashMap<K, (T, Duration), H>) -> Self {
+        let mut cache = Cache::default();
+        cache.extend(entries);
+        cache
+    }
+}
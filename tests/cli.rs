@@ -0,0 +1,101 @@
+//! Drives the `memory_cache` binary against a state file in a fresh
+//! directory, as a user would from a shell
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use tempfile::TempDir;
+
+// Runs the whitespace-separated `args` in `dir`, without any of the
+// environment that changes how the binary behaves
+fn run(dir: &Path, args: &str) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_memory_cache"));
+    for (name, _) in std::env::vars_os() {
+        if name.to_string_lossy().starts_with("MEMORY_CACHE_") {
+            command.env_remove(name);
+        }
+    }
+    command
+        .args(args.split_whitespace())
+        .current_dir(dir)
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .unwrap()
+}
+
+// Runs `args` and returns what it printed, failing the test if it failed
+fn ok(dir: &Path, args: &str) -> String {
+    let output = run(dir, args);
+    assert!(
+        output.status.success(),
+        "`{}` failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn get(dir: &Path, key: &str) -> String {
+    let printed = ok(dir, &format!("get -k {}", key));
+    printed.trim_end().to_string()
+}
+
+#[test]
+fn export_then_import_restores_the_entries() {
+    let dir = TempDir::new().unwrap();
+    ok(dir.path(), "insert -k user/1 -v alice -t 600");
+    ok(dir.path(), "insert -k user/2 -v bob,jr. -t 600");
+    ok(dir.path(), "export -o entries.csv");
+
+    ok(dir.path(), "clear");
+    assert_eq!(get(dir.path(), "user/1"), "No value found for key 'user/1'");
+
+    ok(dir.path(), "import -i entries.csv");
+    assert_eq!(get(dir.path(), "user/1"), "Value for key 'user/1': alice");
+    assert_eq!(get(dir.path(), "user/2"), "Value for key 'user/2': bob,jr.");
+}
+
+#[test]
+fn undo_restores_the_state_before_the_last_save() {
+    let dir = TempDir::new().unwrap();
+    ok(dir.path(), "insert -k config -v v1 -t 600");
+    ok(dir.path(), "insert -k config -v v2 -t 600");
+    assert_eq!(get(dir.path(), "config"), "Value for key 'config': v2");
+
+    ok(dir.path(), "undo");
+    assert_eq!(get(dir.path(), "config"), "Value for key 'config': v1");
+}
+
+#[test]
+fn dry_run_leaves_the_state_file_untouched() {
+    let dir = TempDir::new().unwrap();
+    ok(dir.path(), "insert -k config -v v1 -t 600");
+    let state = dir.path().join("cache_state.json");
+    let before = fs::read(&state).unwrap();
+
+    let changes = ok(dir.path(), "--dry-run insert -k config -v v2 -t 600");
+    assert!(changes.contains("config"), "{}", changes);
+    ok(dir.path(), "--dry-run clear");
+
+    assert_eq!(fs::read(&state).unwrap(), before);
+    assert_eq!(get(dir.path(), "config"), "Value for key 'config': v1");
+}
+
+#[test]
+fn expire_at_sets_the_expiry_and_rejects_past_times() {
+    let dir = TempDir::new().unwrap();
+    ok(
+        dir.path(),
+        "insert -k report -v q4 --expire-at 2999-01-01T00:00:00Z",
+    );
+    assert_eq!(get(dir.path(), "report"), "Value for key 'report': q4");
+
+    let output = run(
+        dir.path(),
+        "insert -k old -v q3 --expire-at 2000-01-01T00:00:00Z",
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already passed"));
+    assert_eq!(get(dir.path(), "old"), "No value found for key 'old'");
+}
@@ -0,0 +1,117 @@
+//! Model-based test: replays generated operations against the cache and a
+//! plain map of (value, expiry) pairs and checks they agree after every step
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use memory_cache::testing::Op;
+use memory_cache::Cache;
+use proptest::prelude::*;
+
+thread_local! {
+    static NOW: Cell<u64> = const { Cell::new(1_000) };
+}
+
+fn now() -> u64 {
+    NOW.with(Cell::get)
+}
+
+#[derive(Default)]
+struct Model {
+    entries: HashMap<String, (u64, u64)>,
+}
+
+impl Model {
+    fn live(&mut self, key: &str) -> Option<u64> {
+        match self.entries.get(key) {
+            Some(&(value, expiry)) if now() < expiry => Some(value),
+            Some(_) => {
+                self.entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&mut self, key: &str, value: u64, ttl: u64) {
+        self.entries
+            .insert(key.to_string(), (value, now().saturating_add(ttl)));
+    }
+}
+
+proptest! {
+    #[test]
+    fn cache_matches_model(ops in prop::collection::vec(any::<Op>(), 1..200)) {
+        NOW.with(|now| now.set(1_000));
+        let mut cache = Cache::new()
+            .with_clock(now)
+            .with_key_index()
+            .with_expiry_index()
+            .with_value_index("parity", |value: &u64| vec![(value % 2).to_string()]);
+        let mut model = Model::default();
+
+        for op in ops {
+            match op {
+                Op::Insert { key, value, ttl } => {
+                    cache.insert(&key, value, Duration::from_secs(ttl));
+                    model.insert(&key, value, ttl);
+                }
+                Op::InsertIfAbsent { key, value, ttl } => {
+                    let expected = model.live(&key).is_none();
+                    if expected {
+                        model.insert(&key, value, ttl);
+                    }
+                    prop_assert_eq!(
+                        cache.insert_if_absent(&key, value, Duration::from_secs(ttl)),
+                        expected
+                    );
+                }
+                Op::Get { key } => {
+                    prop_assert_eq!(cache.get(&key), model.live(&key));
+                }
+                Op::GetOrInsert { key, value, ttl } => {
+                    let expected = match model.live(&key) {
+                        Some(live) => live,
+                        None => {
+                            model.insert(&key, value, ttl);
+                            value
+                        }
+                    };
+                    prop_assert_eq!(
+                        cache.get_or_insert_with(&key, Duration::from_secs(ttl), || value),
+                        expected
+                    );
+                }
+                Op::Increment { key, delta, ttl } => {
+                    let expected = match model.live(&key) {
+                        Some(count) => {
+                            let count = count.saturating_add(delta);
+                            model.entries.get_mut(&key).unwrap().0 = count;
+                            count
+                        }
+                        None => {
+                            model.insert(&key, delta, ttl);
+                            delta
+                        }
+                    };
+                    prop_assert_eq!(
+                        cache.increment(&key, delta, Duration::from_secs(ttl)),
+                        Some(expected)
+                    );
+                }
+                Op::Invalidate { key } => {
+                    cache.invalidate(&key);
+                    model.entries.remove(&key);
+                }
+                Op::Advance { secs } => NOW.with(|now| now.set(now.get() + secs)),
+            }
+            prop_assert_eq!(cache.check_invariants(), Ok(()));
+        }
+
+        for index in 0..memory_cache::testing::KEY_POOL {
+            let key = format!("k{}", index);
+            prop_assert_eq!(cache.get(&key), model.live(&key));
+        }
+    }
+}